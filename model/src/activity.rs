@@ -17,7 +17,9 @@ pub use self::activity_usage::ActivityUsage;
 pub use self::create_activity::{CreateActivityRequest, CreateActivityResult, Credentials};
 pub use self::exe_script_command::{Capture, CaptureFormat, CaptureMode, CapturePart, Network};
 pub use self::exe_script_command::{ExeScriptCommand, FileSet, SetEntry, SetObject, TransferArgs};
-pub use self::exe_script_command_result::{CommandOutput, CommandResult, ExeScriptCommandResult};
+pub use self::exe_script_command_result::{
+    CommandOutput, CommandResult, ExeScriptCommandResult, OutputError,
+};
 pub use self::exe_script_command_state::ExeScriptCommandState;
 pub use self::exe_script_request::ExeScriptRequest;
 pub use self::provider_event::ProviderEvent;
@@ -8,6 +8,7 @@
  * Generated by: https://openapi-generator.tech
  */
 
+use base64::Engine;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -20,9 +21,47 @@ pub struct ExeScriptCommandResult {
     pub stderr: Option<CommandOutput>,
     pub message: Option<String>,
     pub is_batch_finished: bool,
+    #[serde(with = "crate::time")]
     pub event_date: DateTime<Utc>,
 }
 
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum OutputError {
+    #[error("no stdout captured for command {0}")]
+    Missing(u32),
+}
+
+impl ExeScriptCommandResult {
+    /// Raw stdout bytes of the command.
+    ///
+    /// Binary output is returned as is. Textual output is treated as base64
+    /// when the whole (whitespace-trimmed) string is a valid, padded standard
+    /// base64 encoding, and as plain text otherwise. Note that short plain
+    /// text may happen to be valid base64 too.
+    pub fn stdout_bytes(&self) -> Result<Vec<u8>, OutputError> {
+        match &self.stdout {
+            Some(CommandOutput::Bin(bytes)) => Ok(bytes.clone()),
+            Some(CommandOutput::Str(text)) => {
+                Ok(decode_base64(text.trim()).unwrap_or_else(|| text.as_bytes().to_vec()))
+            }
+            None => Err(OutputError::Missing(self.index)),
+        }
+    }
+
+    /// Stdout as text, with invalid UTF-8 sequences replaced.
+    pub fn stdout_lossy_string(&self) -> Option<String> {
+        let bytes = self.stdout_bytes().ok()?;
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() {
+        return None;
+    }
+    base64::engine::general_purpose::STANDARD.decode(text).ok()
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum CommandOutput {
@@ -35,3 +74,44 @@ pub enum CommandResult {
     Ok,
     Error,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(stdout: Option<CommandOutput>) -> ExeScriptCommandResult {
+        ExeScriptCommandResult {
+            index: 1,
+            result: CommandResult::Ok,
+            stdout,
+            stderr: None,
+            message: None,
+            is_batch_finished: true,
+            event_date: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_base64_stdout() {
+        let res = result(Some(CommandOutput::Str("AP8Kbw==\n".to_string())));
+        assert_eq!(res.stdout_bytes().unwrap(), vec![0x00, 0xff, 0x0a, 0x6f]);
+    }
+
+    #[test]
+    fn test_plain_stdout() {
+        let res = result(Some(CommandOutput::Str("hello, world!\n".to_string())));
+        assert_eq!(res.stdout_bytes().unwrap(), b"hello, world!\n".to_vec());
+        assert_eq!(res.stdout_lossy_string().unwrap(), "hello, world!\n");
+    }
+
+    #[test]
+    fn test_binary_and_missing_stdout() {
+        let res = result(Some(CommandOutput::Bin(vec![0xc3, 0x28])));
+        assert_eq!(res.stdout_bytes().unwrap(), vec![0xc3, 0x28]);
+        assert_eq!(res.stdout_lossy_string().unwrap(), "\u{fffd}(");
+
+        let res = result(None);
+        assert_eq!(res.stdout_bytes(), Err(OutputError::Missing(1)));
+        assert_eq!(res.stdout_lossy_string(), None);
+    }
+}
@@ -17,6 +17,7 @@ pub struct ProviderEvent {
     pub activity_id: String,
     pub agreement_id: String,
     pub event_type: ProviderEventType,
+    #[serde(with = "crate::time")]
     pub event_date: DateTime<Utc>,
 }
 
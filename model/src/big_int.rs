@@ -0,0 +1,116 @@
+//! Serde helpers for non-negative integers which may exceed `u64`, such as
+//! block numbers and nonces. Use with `#[serde(with = "crate::big_int")]`, or
+//! `crate::big_int::option` for optional fields.
+//!
+//! Both JSON numbers and decimal strings are accepted at full precision, as
+//! they are read from the raw JSON text rather than through `u64` or `f64`.
+//! The value has to come straight from JSON text then: a number above
+//! `u64::MAX` already in a `serde_json::Value` has been turned into a float
+//! and is rejected. Values are written as numbers when they fit in `u64`, as
+//! strings otherwise.
+//!
+//! Fields using it, the only block number or nonce in the models:
+//! * [`DriverStatus::last_block`](crate::payment::DriverStatus::last_block)
+use bigdecimal::num_bigint::BigUint;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serializer};
+use serde_json::value::RawValue;
+use std::convert::TryFrom;
+
+const EXPECTED: &str = "a non-negative integer, or a string holding one";
+
+pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+    match u64::try_from(value) {
+        Ok(value) => serializer.serialize_u64(value),
+        Err(_) => serializer.serialize_str(&value.to_string()),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+    let raw = Box::<RawValue>::deserialize(deserializer)?;
+    let raw = raw.get().trim();
+    let digits = match serde_json::from_str::<String>(raw) {
+        Ok(quoted) => quoted,
+        Err(_) => raw.to_string(),
+    };
+    let invalid = || de::Error::invalid_value(de::Unexpected::Other(raw), &EXPECTED);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    digits.parse().map_err(|_| invalid())
+}
+
+pub mod option {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(with = "super")] BigUint);
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<BigUint>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BigUint>, D::Error> {
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(value)| value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Block {
+        #[serde(with = "super")]
+        number: BigUint,
+    }
+
+    #[test]
+    fn test_roundtrip_above_u64() {
+        let number: BigUint = "340282366920938463463374607431768211457".parse().unwrap();
+        assert!(number > BigUint::from(u64::MAX));
+        let block = Block { number };
+
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(
+            value,
+            json!({"number": "340282366920938463463374607431768211457"})
+        );
+        assert_eq!(serde_json::from_value::<Block>(value).unwrap(), block);
+    }
+
+    #[test]
+    fn test_accepts_numbers_and_strings() {
+        let block: Block = serde_json::from_str(r#"{"number": 18446744073709551615}"#).unwrap();
+        assert_eq!(block.number, BigUint::from(u64::MAX));
+        assert_eq!(
+            serde_json::to_value(&block).unwrap(),
+            json!({"number": u64::MAX})
+        );
+
+        let block: Block = serde_json::from_str(r#"{"number": "42"}"#).unwrap();
+        assert_eq!(block.number, BigUint::from(42_u32));
+
+        let block: Block =
+            serde_json::from_str(r#"{"number": 340282366920938463463374607431768211457}"#).unwrap();
+        assert_eq!(
+            block.number.to_string(),
+            "340282366920938463463374607431768211457"
+        );
+
+        for invalid in ["-1", "1.5", "1e3", r#""0x10""#, r#""+1""#, r#""""#, "null"] {
+            let json = format!(r#"{{"number": {}}}"#, invalid);
+            assert!(serde_json::from_str::<Block>(&json).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -29,3 +29,26 @@ impl<T: Into<String>> From<T> for ErrorMessage {
         Self::new(s.into())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_and_round_trip() {
+        let msg = ErrorMessage::from("no such activity");
+        assert_eq!(msg, ErrorMessage::new("no such activity".to_string()));
+        assert_eq!(
+            msg.to_string(),
+            "Yagna API error: Some(\"no such activity\")"
+        );
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"message":"no such activity"}"#);
+        assert_eq!(serde_json::from_str::<ErrorMessage>(&json).unwrap(), msg);
+        assert_eq!(
+            serde_json::from_str::<ErrorMessage>("{}").unwrap(),
+            ErrorMessage::default()
+        );
+    }
+}
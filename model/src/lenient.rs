@@ -0,0 +1,21 @@
+//! Serde helpers for the `Unknown` arms enabled by the `lenient-enums` feature.
+
+/// (De)serializes the `Unknown` arm of enums internally tagged with
+/// `eventType`, preserving the raw tag only.
+pub(crate) mod event_type {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Tag<T> {
+        #[serde(rename = "eventType")]
+        event_type: T,
+    }
+
+    pub fn serialize<S: Serializer>(event_type: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        Tag { event_type }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        Ok(Tag::<String>::deserialize(deserializer)?.event_type)
+    }
+}
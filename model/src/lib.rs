@@ -1,9 +1,15 @@
 pub mod activity;
+pub mod big_int;
 pub mod error_message;
+#[cfg(feature = "lenient-enums")]
+mod lenient;
 pub mod market;
 pub mod net;
 pub mod node_id;
 pub mod payment;
+pub mod time;
+pub mod version;
 
 pub use error_message::ErrorMessage;
 pub use node_id::{NodeId, ParseError};
+pub use version::{VersionInfo, YagnaVersionInfo, YagnaVersionResponse};
@@ -1,23 +1,43 @@
 pub mod agreement;
 pub mod agreement_event;
 pub mod agreement_proposal;
+pub mod constraints;
 pub mod demand;
 pub mod demand_offer_base;
 pub mod event;
+pub mod inf_resources;
+pub mod matching;
+pub mod node_descriptor;
 pub mod offer;
+pub mod pricing;
+pub mod properties;
 pub mod property_query;
 pub mod proposal;
 pub mod reason;
+pub mod subscription_id;
 
 pub use agreement::{Agreement, AgreementListEntry, Role};
 pub use agreement_event::{AgreementEventType, AgreementOperationEvent};
 pub use agreement_proposal::AgreementProposal;
+pub use constraints::{
+    property_type, Constraint, ConstraintFailure, ConstraintParseError, Constraints,
+    ConstraintsBuilder, Operator, PropertyType,
+};
 pub use demand::Demand;
-pub use demand_offer_base::{DemandOfferBase, NewDemand, NewOffer, NewProposal};
+pub use demand_offer_base::{
+    DemandOfferBase, NewDemand, NewOffer, NewProposal, TemplateError, ValidationError,
+    EXPIRATION_PROPERTY,
+};
 pub use event::{ProviderEvent, RequestorEvent};
+pub use inf_resources::InfResources;
+pub use matching::{matching_report, MatchReport, SideReport};
+pub use node_descriptor::{NodeDescriptor, NodeDescriptorBuilder};
 pub use offer::Offer;
+pub use pricing::{estimate_price, rank_offers, LinearPricing};
+pub use properties::PropertiesBuilder;
 pub use property_query::PropertyQuery;
 pub use proposal::Proposal;
 pub use reason::Reason;
+pub use subscription_id::SubscriptionId;
 
 pub const MARKET_API_PATH: &str = "/market-api/v1";
@@ -13,7 +13,7 @@ use crate::market::Reason;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AgreementOperationEvent {
-    #[serde(rename = "eventDate")]
+    #[serde(rename = "eventDate", with = "crate::time")]
     pub event_date: DateTime<Utc>,
     #[serde(rename = "agreementId")]
     pub agreement_id: String,
@@ -0,0 +1,687 @@
+//! Parser and evaluator of the Demand/Offer constraint expressions, eg.
+//! `(&(golem.inf.cpu.cores>=4)(golem.runtime.name=vm))`.
+//!
+//! Supported are conjunctions `&`, alternatives `|`, negations `!`, the
+//! `=`, `<`, `<=`, `>`, `>=` comparisons, `name=*` presence checks and `*`
+//! wildcards in string values. The empty expression `()` matches anything.
+//! A `\` escapes the next character of a value, eg. a parenthesis.
+//!
+//! Values of the well-known `golem.*` properties are compared according to
+//! their [`property_type`], others as their literals suggest.
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::market::inf_resources::{
+    CPU_ARCHITECTURE_PROPERTY, CPU_CORES_PROPERTY, CPU_THREADS_PROPERTY, MEM_GIB_PROPERTY,
+    STORAGE_GIB_PROPERTY,
+};
+
+/// How values of a property are compared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyType {
+    Number,
+    String,
+    Bool,
+}
+
+const KNOWN_PROPERTIES: &[(&str, PropertyType)] = &[
+    (CPU_ARCHITECTURE_PROPERTY, PropertyType::String),
+    (CPU_CORES_PROPERTY, PropertyType::Number),
+    (CPU_THREADS_PROPERTY, PropertyType::Number),
+    ("golem.inf.cpu.capabilities", PropertyType::String),
+    (MEM_GIB_PROPERTY, PropertyType::Number),
+    (STORAGE_GIB_PROPERTY, PropertyType::Number),
+    ("golem.runtime.name", PropertyType::String),
+    ("golem.runtime.version", PropertyType::String),
+    ("golem.node.id.name", PropertyType::String),
+    ("golem.node.debug.subnet", PropertyType::String),
+    ("golem.com.scheme", PropertyType::String),
+    ("golem.com.pricing.model", PropertyType::String),
+    (
+        "golem.com.payment.debit-notes.accept-timeout?",
+        PropertyType::Number,
+    ),
+    (
+        "golem.com.scheme.payu.debit-note.interval-sec?",
+        PropertyType::Number,
+    ),
+    (
+        "golem.com.scheme.payu.payment-timeout-sec?",
+        PropertyType::Number,
+    ),
+    ("golem.srv.comp.expiration", PropertyType::Number),
+    ("golem.srv.caps.multi-activity", PropertyType::Bool),
+];
+
+/// Type of a well-known property, `None` for any other.
+pub fn property_type(property: &str) -> Option<PropertyType> {
+    KNOWN_PROPERTIES
+        .iter()
+        .find(|(name, _)| *name == property)
+        .map(|(_, ty)| *ty)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessOrEqual => ordering != Ordering::Greater,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Equal => "=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Constraint {
+    /// `()`, always satisfied.
+    Empty,
+    And(Vec<Constraint>),
+    Or(Vec<Constraint>),
+    Not(Box<Constraint>),
+    Compare {
+        property: String,
+        operator: Operator,
+        value: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("malformed constraints at position {position}: {reason}")]
+pub struct ConstraintParseError {
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+/// Comparison failed by the evaluated properties.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstraintFailure {
+    pub property: String,
+    pub operator: Operator,
+    pub expected: String,
+    /// Value of the property, `None` if absent.
+    pub actual: Option<Value>,
+    /// Set if the comparison failed by holding, within a `!` negation.
+    pub negated: bool,
+}
+
+impl Constraint {
+    pub fn parse(input: &str) -> Result<Constraint, ConstraintParseError> {
+        let mut parser = Parser { input, pos: 0 };
+        let constraint = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.pos < input.len() {
+            return Err(parser.error("unexpected input after the expression"));
+        }
+        Ok(constraint)
+    }
+
+    /// Evaluates against flat-convention properties.
+    pub fn matches(&self, properties: &Value) -> bool {
+        match self {
+            Constraint::Empty => true,
+            Constraint::And(all) => all.iter().all(|c| c.matches(properties)),
+            Constraint::Or(any) => any.iter().any(|c| c.matches(properties)),
+            Constraint::Not(inner) => !inner.matches(properties),
+            Constraint::Compare {
+                property,
+                operator,
+                value,
+            } => match properties.get(property) {
+                Some(actual) => compare(actual, *operator, value, property_type(property)),
+                None => false,
+            },
+        }
+    }
+
+    /// Comparisons responsible for the expression not matching, empty if
+    /// it does match.
+    ///
+    /// For an alternative all its failed branches are reported.
+    pub fn failures(&self, properties: &Value) -> Vec<ConstraintFailure> {
+        let mut failures = Vec::new();
+        self.collect_failures(properties, false, &mut failures);
+        failures
+    }
+
+    fn collect_failures(
+        &self,
+        properties: &Value,
+        negated: bool,
+        out: &mut Vec<ConstraintFailure>,
+    ) {
+        if self.matches(properties) != negated {
+            return;
+        }
+        match self {
+            Constraint::Empty => (),
+            Constraint::And(children) | Constraint::Or(children) => {
+                for child in children {
+                    child.collect_failures(properties, negated, out);
+                }
+            }
+            Constraint::Not(inner) => inner.collect_failures(properties, !negated, out),
+            Constraint::Compare {
+                property,
+                operator,
+                value,
+            } => out.push(ConstraintFailure {
+                property: property.clone(),
+                operator: *operator,
+                expected: value.clone(),
+                actual: properties.get(property).cloned(),
+                negated,
+            }),
+        }
+    }
+}
+
+fn compare(actual: &Value, operator: Operator, expected: &str, ty: Option<PropertyType>) -> bool {
+    match (actual, ty) {
+        (Value::Array(items), _) => items
+            .iter()
+            .any(|item| compare(item, operator, expected, ty)),
+        _ if operator == Operator::Equal && expected == "*" => true,
+        (_, Some(PropertyType::Number)) => {
+            let actual = match actual {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => s.trim().parse().ok(),
+                _ => None,
+            };
+            match (actual, expected.trim().parse::<f64>()) {
+                (Some(actual), Ok(expected)) => actual
+                    .partial_cmp(&expected)
+                    .is_some_and(|ordering| operator.holds(ordering)),
+                _ => false,
+            }
+        }
+        (_, Some(PropertyType::String)) => {
+            let actual = match actual {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                _ => return false,
+            };
+            match operator {
+                Operator::Equal => wildcard_match(expected, &actual),
+                _ => operator.holds(actual.as_str().cmp(expected)),
+            }
+        }
+        _ => compare_literal(actual, operator, expected),
+    }
+}
+
+/// Compares a value of unknown type, numerically if both sides look like
+/// numbers.
+fn compare_literal(actual: &Value, operator: Operator, expected: &str) -> bool {
+    match actual {
+        Value::Number(n) => match (n.as_f64(), expected.trim().parse::<f64>()) {
+            (Some(actual), Ok(expected)) => actual
+                .partial_cmp(&expected)
+                .is_some_and(|ordering| operator.holds(ordering)),
+            _ => false,
+        },
+        Value::Bool(b) => operator == Operator::Equal && expected.parse() == Ok(*b),
+        Value::String(s) if operator == Operator::Equal => wildcard_match(expected, s),
+        Value::String(s) => {
+            let ordering = match (s.parse::<f64>(), expected.parse::<f64>()) {
+                (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected),
+                _ => Some(s.as_str().cmp(expected)),
+            };
+            ordering.is_some_and(|ordering| operator.holds(ordering))
+        }
+        _ => false,
+    }
+}
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match value.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<_> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &'static str) -> ConstraintParseError {
+        ConstraintParseError {
+            position: self.pos,
+            reason,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<Constraint, ConstraintParseError> {
+        if !self.eat('(') {
+            return Err(self.error("expected '('"));
+        }
+        let constraint = if self.eat(')') {
+            return Ok(Constraint::Empty);
+        } else if self.eat('&') {
+            Constraint::And(self.operands()?)
+        } else if self.eat('|') {
+            Constraint::Or(self.operands()?)
+        } else if self.eat('!') {
+            Constraint::Not(Box::new(self.expression()?))
+        } else {
+            self.comparison()?
+        };
+        if !self.eat(')') {
+            return Err(self.error("expected ')'"));
+        }
+        Ok(constraint)
+    }
+
+    fn operands(&mut self) -> Result<Vec<Constraint>, ConstraintParseError> {
+        let mut operands = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if !self.rest().starts_with('(') {
+                return Ok(operands);
+            }
+            operands.push(self.expression()?);
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Constraint, ConstraintParseError> {
+        let rest = self.rest();
+        let name_len = rest
+            .find(['=', '<', '>', '(', ')'])
+            .ok_or_else(|| self.error("expected a comparison"))?;
+        let property = rest[..name_len].trim();
+        if property.is_empty() {
+            return Err(self.error("expected a property name"));
+        }
+        self.pos += name_len;
+
+        let rest = self.rest();
+        let (operator, len) = if rest.starts_with("<=") {
+            (Operator::LessOrEqual, 2)
+        } else if rest.starts_with(">=") {
+            (Operator::GreaterOrEqual, 2)
+        } else if rest.starts_with('<') {
+            (Operator::Less, 1)
+        } else if rest.starts_with('>') {
+            (Operator::Greater, 1)
+        } else if rest.starts_with('=') {
+            (Operator::Equal, 1)
+        } else {
+            return Err(self.error("expected a comparison operator"));
+        };
+        self.pos += len;
+
+        let mut value = String::new();
+        let mut chars = self.rest().chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '(' | ')' => break,
+                '\\' => match chars.next() {
+                    Some(escaped) => {
+                        value.push(escaped);
+                        self.pos += 1 + escaped.len_utf8();
+                        continue;
+                    }
+                    None => return Err(self.error("unfinished escape")),
+                },
+                c => value.push(c),
+            }
+            self.pos += c.len_utf8();
+        }
+        Ok(Constraint::Compare {
+            property: property.to_string(),
+            operator,
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+/// Constraints expression built with a [`ConstraintsBuilder`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraints(String);
+
+impl Constraints {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Constraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Constraints> for String {
+    fn from(constraints: Constraints) -> Self {
+        constraints.0
+    }
+}
+
+/// Composes a constraints expression, all of whose terms have to hold.
+///
+/// ```
+/// use ya_client_model::market::ConstraintsBuilder;
+///
+/// let constraints = ConstraintsBuilder::new()
+///     .greater_than("golem.inf.mem.gib", 0.5)
+///     .any_of(
+///         ConstraintsBuilder::new()
+///             .equals("golem.runtime.name", "vm")
+///             .equals("golem.runtime.name", "wasmtime"),
+///     )
+///     .build();
+/// assert_eq!(
+///     constraints.as_str(),
+///     "(&(golem.inf.mem.gib>0.5)(|(golem.runtime.name=vm)(golem.runtime.name=wasmtime)))"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintsBuilder {
+    terms: Vec<String>,
+}
+
+impl ConstraintsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a comparison, escaping the parentheses, `*` and `\` of `value`.
+    pub fn compare(mut self, property: &str, operator: Operator, value: impl fmt::Display) -> Self {
+        let mut term = format!("({}{}", property, operator);
+        for c in value.to_string().chars() {
+            if matches!(c, '(' | ')' | '*' | '\\') {
+                term.push('\\');
+            }
+            term.push(c);
+        }
+        term.push(')');
+        self.terms.push(term);
+        self
+    }
+
+    pub fn equals(self, property: &str, value: impl fmt::Display) -> Self {
+        self.compare(property, Operator::Equal, value)
+    }
+
+    pub fn greater_than(self, property: &str, value: impl fmt::Display) -> Self {
+        self.compare(property, Operator::Greater, value)
+    }
+
+    pub fn less_than(self, property: &str, value: impl fmt::Display) -> Self {
+        self.compare(property, Operator::Less, value)
+    }
+
+    /// Requires the property to be present, with any value.
+    pub fn present(mut self, property: &str) -> Self {
+        self.terms.push(format!("({}=*)", property));
+        self
+    }
+
+    /// Adds an alternative of the terms of `group`.
+    pub fn any_of(self, group: ConstraintsBuilder) -> Self {
+        self.group('|', group)
+    }
+
+    /// Adds a conjunction of the terms of `group`.
+    pub fn all_of(self, group: ConstraintsBuilder) -> Self {
+        self.group('&', group)
+    }
+
+    fn group(mut self, operator: char, group: ConstraintsBuilder) -> Self {
+        match group.terms.len() {
+            0 => (),
+            1 => self.terms.extend(group.terms),
+            _ => self
+                .terms
+                .push(format!("({}{})", operator, group.terms.concat())),
+        }
+        self
+    }
+
+    /// `()`, matching anything, if no terms were added.
+    pub fn build(self) -> Constraints {
+        Constraints(match self.terms.len() {
+            0 => "()".to_string(),
+            1 => self.terms.concat(),
+            _ => format!("(&{})", self.terms.concat()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn compare(property: &str, operator: Operator, value: &str) -> Constraint {
+        Constraint::Compare {
+            property: property.into(),
+            operator,
+            value: value.into(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let constraint = Constraint::parse(
+            "(&
+                (golem.inf.cpu.cores>=4)
+                (|(golem.runtime.name=vm)(golem.runtime.name=wasm*))
+                (!(golem.node.debug.subnet=public))
+            )",
+        )
+        .unwrap();
+        assert_eq!(
+            constraint,
+            Constraint::And(vec![
+                compare("golem.inf.cpu.cores", Operator::GreaterOrEqual, "4"),
+                Constraint::Or(vec![
+                    compare("golem.runtime.name", Operator::Equal, "vm"),
+                    compare("golem.runtime.name", Operator::Equal, "wasm*"),
+                ]),
+                Constraint::Not(Box::new(compare(
+                    "golem.node.debug.subnet",
+                    Operator::Equal,
+                    "public"
+                ))),
+            ])
+        );
+        assert_eq!(Constraint::parse(" () "), Ok(Constraint::Empty));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Constraint::parse("golem.inf.cpu.cores>=4").is_err());
+        assert!(Constraint::parse("(&(a=1)").is_err());
+        assert!(Constraint::parse("(a=1))").is_err());
+        assert!(Constraint::parse("(a)").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let properties = json!({
+            "golem.inf.cpu.cores": 4,
+            "golem.inf.mem.gib": 10.5,
+            "golem.runtime.name": "wasmtime",
+            "golem.com.payment.platform.erc20-holesky-tglm.address": "0xbabe",
+            "golem.com.scheme": ["payu", "fixed"],
+            "golem.srv.caps.multi-activity": true,
+        });
+        let matches = |c: &str| Constraint::parse(c).unwrap().matches(&properties);
+
+        assert!(matches("(golem.inf.cpu.cores>=4)"));
+        assert!(!matches("(golem.inf.cpu.cores>4)"));
+        assert!(matches("(golem.inf.mem.gib<=10.5)"));
+        assert!(matches("(golem.runtime.name=wasm*)"));
+        assert!(!matches("(golem.runtime.name=vm)"));
+        assert!(matches(
+            "(golem.com.payment.platform.erc20-holesky-tglm.address=*)"
+        ));
+        assert!(matches("(golem.com.scheme=fixed)"));
+        assert!(matches("(golem.srv.caps.multi-activity=true)"));
+        assert!(!matches("(golem.node.debug.subnet=public)"));
+        assert!(matches("(!(golem.node.debug.subnet=public))"));
+        assert!(matches("(|(golem.runtime.name=vm)(golem.inf.cpu.cores=4))"));
+    }
+
+    #[test]
+    fn test_known_property_types() {
+        let properties = json!({
+            "golem.inf.cpu.threads": "10",
+            "golem.runtime.version": "10",
+            "golem.custom.version": "10",
+            "golem.custom.name": "10",
+        });
+        let matches = |c: &str| Constraint::parse(c).unwrap().matches(&properties);
+
+        assert_eq!(
+            property_type("golem.inf.cpu.threads"),
+            Some(PropertyType::Number)
+        );
+        assert_eq!(property_type("golem.custom.version"), None);
+        // numeric property sent as a string still compares as a number
+        assert!(matches("(golem.inf.cpu.threads>4)"));
+        assert!(matches("(golem.inf.cpu.threads=10.0)"));
+        // while a string property compares lexically, even if numeric-looking
+        assert!(!matches("(golem.runtime.version>4)"));
+        assert!(!matches("(golem.runtime.version=10.0)"));
+        assert!(matches("(golem.runtime.version=1*)"));
+        // and unknown ones are guessed from the literals
+        assert!(matches("(golem.custom.version>4)"));
+        assert!(matches("(golem.custom.name>09)"));
+    }
+
+    #[test]
+    fn test_builder() {
+        let constraints = ConstraintsBuilder::new()
+            .all_of(
+                ConstraintsBuilder::new()
+                    .any_of(
+                        ConstraintsBuilder::new()
+                            .equals("golem.inf.cpu.architecture", "x86_64")
+                            .equals("golem.inf.cpu.architecture", "aarch64"),
+                    )
+                    .any_of(
+                        ConstraintsBuilder::new()
+                            .greater_than("golem.inf.cpu.cores", 4)
+                            .less_than("golem.com.pricing.max", 0.5),
+                    ),
+            )
+            .equals("golem.node.id.name", "my (test) node*")
+            .build();
+
+        assert_eq!(
+            constraints.as_str(),
+            "(&(&(|(golem.inf.cpu.architecture=x86_64)(golem.inf.cpu.architecture=aarch64))\
+             (|(golem.inf.cpu.cores>4)(golem.com.pricing.max<0.5)))\
+             (golem.node.id.name=my \\(test\\) node\\*))"
+        );
+        let parsed = Constraint::parse(constraints.as_str()).unwrap();
+        match parsed {
+            Constraint::And(terms) => {
+                assert_eq!(
+                    terms[1],
+                    compare("golem.node.id.name", Operator::Equal, "my (test) node*")
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(ConstraintsBuilder::new().build().as_str(), "()");
+        let base = crate::market::DemandOfferBase::new(
+            serde_json::json!({}),
+            ConstraintsBuilder::new()
+                .present("golem.srv.comp.expiration")
+                .build()
+                .into(),
+        );
+        assert_eq!(base.constraints, "(golem.srv.comp.expiration=*)");
+        assert!(base.validate().is_ok());
+    }
+
+    #[test]
+    fn test_failures() {
+        let properties = json!({"golem.inf.cpu.cores": 2, "golem.runtime.name": "vm"});
+        let constraint = Constraint::parse(
+            "(&(golem.inf.cpu.cores>=4)(golem.runtime.name=vm)(!(golem.runtime.name=vm)))",
+        )
+        .unwrap();
+
+        assert_eq!(
+            constraint.failures(&properties),
+            vec![
+                ConstraintFailure {
+                    property: "golem.inf.cpu.cores".into(),
+                    operator: Operator::GreaterOrEqual,
+                    expected: "4".into(),
+                    actual: Some(json!(2)),
+                    negated: false,
+                },
+                ConstraintFailure {
+                    property: "golem.runtime.name".into(),
+                    operator: Operator::Equal,
+                    expected: "vm".into(),
+                    actual: Some(json!("vm")),
+                    negated: true,
+                },
+            ]
+        );
+    }
+}
@@ -6,7 +6,13 @@
  * Generated by: https://openapi-generator.tech
  */
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Expiration timestamp of a Demand/Offer, in milliseconds since the epoch.
+pub const EXPIRATION_PROPERTY: &str = "golem.srv.comp.expiration";
 
 pub type NewOffer = DemandOfferBase;
 pub type NewDemand = DemandOfferBase;
@@ -46,11 +52,332 @@ pub struct DemandOfferBase {
     pub constraints: String,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("properties must be a JSON object")]
+    PropertiesNotObject,
+    #[error("property name must not be empty")]
+    EmptyPropertyName,
+    #[error("invalid property name '{0}'")]
+    InvalidPropertyName(String),
+    #[error("constraints must not be empty")]
+    EmptyConstraints,
+    #[error("unbalanced parentheses in constraints at position {0}")]
+    UnbalancedConstraints(usize),
+    #[error("invalid value of property {name}: {reason}")]
+    InvalidPropertyValue { name: String, reason: &'static str },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unable to read template {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("malformed template {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("invalid template {}: {source}", path.display())]
+    Invalid {
+        path: PathBuf,
+        source: ValidationError,
+    },
+}
+
 impl DemandOfferBase {
+    /// Takes the constraints either as a raw string or, converted with
+    /// `into()`, built with a [`ConstraintsBuilder`](crate::market::ConstraintsBuilder).
     pub fn new(properties: serde_json::Value, constraints: String) -> DemandOfferBase {
         DemandOfferBase {
             properties,
             constraints,
         }
     }
+
+    /// Loads a Demand/Offer template: a JSON file with `properties` and
+    /// `constraints` fields, the same as sent to the market.
+    pub fn from_template_file(path: &Path) -> Result<DemandOfferBase, TemplateError> {
+        let content = std::fs::read(path).map_err(|source| TemplateError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let template: DemandOfferBase =
+            serde_json::from_slice(&content).map_err(|source| TemplateError::Parse {
+                path: path.to_owned(),
+                source,
+            })?;
+        template
+            .validate()
+            .map_err(|source| TemplateError::Invalid {
+                path: path.to_owned(),
+                source,
+            })?;
+        Ok(template)
+    }
+
+    /// Performs basic sanity checks, without evaluating the constraints.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let properties = self
+            .properties
+            .as_object()
+            .ok_or(ValidationError::PropertiesNotObject)?;
+        if properties.keys().any(|key| key.trim().is_empty()) {
+            return Err(ValidationError::EmptyPropertyName);
+        }
+        if self.constraints.trim().is_empty() {
+            return Err(ValidationError::EmptyConstraints);
+        }
+        let mut depth = 0_usize;
+        let mut escaped = false;
+        for (pos, c) in self.constraints.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or(ValidationError::UnbalancedConstraints(pos))?
+                }
+                _ => (),
+            }
+        }
+        if depth > 0 {
+            return Err(ValidationError::UnbalancedConstraints(
+                self.constraints.len(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies flat-convention property `overlay` on top of own properties.
+    ///
+    /// Keys of the overlay are added or overwritten as a whole (nested values
+    /// are not merged), while keys set to `null` in the overlay are removed.
+    /// Properties not mentioned in the overlay are left intact.
+    pub fn apply_overlay(&mut self, overlay: &Value) {
+        let overlay = match overlay.as_object() {
+            Some(overlay) => overlay,
+            None => return,
+        };
+        if !self.properties.is_object() {
+            self.properties = Value::Object(Map::new());
+        }
+        let properties = self.properties.as_object_mut().unwrap();
+        for (key, value) in overlay {
+            if value.is_null() {
+                properties.remove(key);
+            } else {
+                properties.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Body of a subscribe request, exactly as the market endpoint accepts it.
+    ///
+    /// On top of [`validate`](Self::validate), checks that properties follow
+    /// the flat convention: each value is a string, number or boolean, or an
+    /// array of those, while the [`EXPIRATION_PROPERTY`] is an integer.
+    pub fn to_subscribe_body(&self) -> Result<Value, ValidationError> {
+        self.validate()?;
+        let invalid = |name: &str, reason| ValidationError::InvalidPropertyValue {
+            name: name.to_string(),
+            reason,
+        };
+        for (name, value) in self.properties.as_object().unwrap() {
+            match value {
+                Value::Null => return Err(invalid(name, "null is not a property value")),
+                Value::Object(_) => {
+                    return Err(invalid(name, "nested objects are not flat convention"))
+                }
+                Value::Array(items) if !items.iter().all(is_scalar) => {
+                    return Err(invalid(name, "arrays may only hold scalar values"))
+                }
+                _ => (),
+            }
+        }
+        if let Some(expiration) = self.properties.get(EXPIRATION_PROPERTY) {
+            if !(expiration.is_i64() || expiration.is_u64()) {
+                return Err(invalid(
+                    EXPIRATION_PROPERTY,
+                    "expected a timestamp in milliseconds",
+                ));
+            }
+        }
+        Ok(serde_json::json!({
+            "properties": self.properties,
+            "constraints": self.constraints,
+        }))
+    }
+
+    /// Sets the [`EXPIRATION_PROPERTY`], leaving all the other properties intact.
+    pub fn set_expiration(&mut self, expiration: DateTime<Utc>) {
+        if !self.properties.is_object() {
+            self.properties = Value::Object(Map::new());
+        }
+        self.properties.as_object_mut().unwrap().insert(
+            EXPIRATION_PROPERTY.into(),
+            expiration.timestamp_millis().into(),
+        );
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn template_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ya-client-template-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_template() {
+        let path = template_file(
+            "valid",
+            r#"{
+                "properties": {"golem.inf.cpu.cores": 4, "golem.runtime.name": "vm"},
+                "constraints": "(&(golem.srv.comp.expiration>0)(golem.node.debug.subnet=public))"
+            }"#,
+        );
+        let template = DemandOfferBase::from_template_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(template.properties["golem.inf.cpu.cores"], json!(4));
+        assert!(template.constraints.starts_with("(&"));
+    }
+
+    #[test]
+    fn test_reject_malformed_constraints() {
+        let path = template_file(
+            "unbalanced",
+            r#"{"properties": {}, "constraints": "(&(golem.inf.cpu.cores>2)"}"#,
+        );
+        let result = DemandOfferBase::from_template_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(TemplateError::Invalid {
+                source: ValidationError::UnbalancedConstraints(_),
+                ..
+            })
+        ));
+
+        let path = template_file("not-string", r#"{"properties": {}, "constraints": 7}"#);
+        let result = DemandOfferBase::from_template_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(TemplateError::Parse { .. })));
+
+        let missing = std::env::temp_dir().join("ya-client-template-missing.json");
+        let err = DemandOfferBase::from_template_file(&missing).unwrap_err();
+        assert!(err.to_string().contains("ya-client-template-missing.json"));
+    }
+
+    fn base() -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({
+                "golem.node.debug.subnet": "public",
+                "golem.com.payment.platform.erc20-holesky-tglm.address": "0xbabe",
+                "golem.srv.comp.expiration": 1_700_000_000_000_i64,
+            }),
+            "()".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_overlay_adds_keys() {
+        let mut demand = base();
+        demand.apply_overlay(&json!({"golem.srv.caps.multi-activity": true}));
+
+        assert_eq!(
+            demand.properties["golem.srv.caps.multi-activity"],
+            json!(true)
+        );
+        assert_eq!(
+            demand.properties["golem.node.debug.subnet"],
+            json!("public")
+        );
+        assert_eq!(demand.properties.as_object().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_overlay_overwrites_keys() {
+        let mut demand = base();
+        demand.apply_overlay(&json!({"golem.node.debug.subnet": {"name": "devnet"}}));
+
+        assert_eq!(
+            demand.properties["golem.node.debug.subnet"],
+            json!({"name": "devnet"})
+        );
+        assert_eq!(
+            demand.properties["golem.com.payment.platform.erc20-holesky-tglm.address"],
+            json!("0xbabe")
+        );
+    }
+
+    #[test]
+    fn test_overlay_null_removes_keys() {
+        let mut demand = base();
+        demand.apply_overlay(&json!({
+            "golem.srv.comp.expiration": null,
+            "golem.not.there": null,
+        }));
+
+        let properties = demand.properties.as_object().unwrap();
+        assert!(!properties.contains_key("golem.srv.comp.expiration"));
+        assert!(!properties.contains_key("golem.not.there"));
+        assert_eq!(properties.len(), 2);
+    }
+
+    #[test]
+    fn test_subscribe_body() {
+        let mut demand = base();
+        demand.apply_overlay(&json!({
+            "golem.inf.cpu.cores": 4,
+            "golem.srv.caps.multi-activity": true,
+            "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+        }));
+
+        let body = demand.to_subscribe_body().unwrap();
+        assert_eq!(body, serde_json::to_value(&demand).unwrap());
+        assert_eq!(body["constraints"], "()");
+        assert_eq!(body["properties"]["golem.inf.cpu.cores"], json!(4));
+    }
+
+    #[test]
+    fn test_subscribe_body_rejects_invalid_property() {
+        let mut demand = base();
+        demand.apply_overlay(&json!({"golem.inf.mem": {"gib": 4}}));
+        assert_eq!(
+            demand.to_subscribe_body(),
+            Err(ValidationError::InvalidPropertyValue {
+                name: "golem.inf.mem".into(),
+                reason: "nested objects are not flat convention",
+            })
+        );
+
+        let mut demand = base();
+        demand.apply_overlay(&json!({"golem.com.usage.vector": [["nested"]]}));
+        assert!(demand.to_subscribe_body().is_err());
+
+        let mut demand = base();
+        demand.apply_overlay(&json!({EXPIRATION_PROPERTY: "1700000000000"}));
+        assert!(matches!(
+            demand.to_subscribe_body(),
+            Err(ValidationError::InvalidPropertyValue { name, .. }) if name == EXPIRATION_PROPERTY
+        ));
+    }
 }
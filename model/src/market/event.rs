@@ -13,17 +13,18 @@ use crate::market::{Agreement, PropertyQuery, Proposal, Reason};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "eventType")]
+#[allow(clippy::large_enum_variant)]
 pub enum ProviderEvent {
     #[serde(rename = "ProposalEvent")]
     ProposalEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "proposal")]
         proposal: Proposal,
     },
     #[serde(rename = "ProposalRejectedEvent")]
     ProposalRejectedEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "proposalId")]
         proposal_id: String,
@@ -32,14 +33,14 @@ pub enum ProviderEvent {
     },
     #[serde(rename = "AgreementEvent")]
     AgreementEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "agreement")]
         agreement: Agreement,
     },
     #[serde(rename = "PropertyQueryEvent")]
     PropertyQueryEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "propertyQuery")]
         property_query: PropertyQuery,
@@ -51,14 +52,14 @@ pub enum ProviderEvent {
 pub enum RequestorEvent {
     #[serde(rename = "ProposalEvent")]
     ProposalEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "proposal")]
         proposal: Proposal,
     },
     #[serde(rename = "ProposalRejectedEvent")]
     ProposalRejectedEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "proposalId")]
         proposal_id: String,
@@ -67,7 +68,7 @@ pub enum RequestorEvent {
     },
     #[serde(rename = "PropertyQueryEvent")]
     PropertyQueryEvent {
-        #[serde(rename = "eventDate")]
+        #[serde(rename = "eventDate", with = "crate::time")]
         event_date: DateTime<Utc>,
         #[serde(rename = "propertyQuery")]
         property_query: PropertyQuery,
@@ -0,0 +1,122 @@
+use serde_json::Value;
+use std::convert::TryFrom;
+
+use crate::market::DemandOfferBase;
+
+pub const CPU_ARCHITECTURE_PROPERTY: &str = "golem.inf.cpu.architecture";
+pub const CPU_CORES_PROPERTY: &str = "golem.inf.cpu.cores";
+pub const CPU_THREADS_PROPERTY: &str = "golem.inf.cpu.threads";
+pub const MEM_GIB_PROPERTY: &str = "golem.inf.mem.gib";
+pub const STORAGE_GIB_PROPERTY: &str = "golem.inf.storage.gib";
+
+/// Read-only view over the well-known `golem.inf.*` resource properties.
+///
+/// Absent or malformed properties (eg. a fractional or negative core count)
+/// are reported as `None`.
+#[derive(Clone, Copy, Debug)]
+pub struct InfResources<'a> {
+    properties: &'a Value,
+}
+
+impl<'a> InfResources<'a> {
+    pub fn new(properties: &'a Value) -> Self {
+        InfResources { properties }
+    }
+
+    pub fn cpu_architecture(&self) -> Option<&'a str> {
+        self.properties.get(CPU_ARCHITECTURE_PROPERTY)?.as_str()
+    }
+
+    pub fn cpu_cores(&self) -> Option<u32> {
+        self.count(CPU_CORES_PROPERTY)
+    }
+
+    pub fn cpu_threads(&self) -> Option<u32> {
+        self.count(CPU_THREADS_PROPERTY)
+    }
+
+    pub fn mem_gib(&self) -> Option<f64> {
+        self.size(MEM_GIB_PROPERTY)
+    }
+
+    pub fn storage_gib(&self) -> Option<f64> {
+        self.size(STORAGE_GIB_PROPERTY)
+    }
+
+    fn count(&self, name: &str) -> Option<u32> {
+        u32::try_from(self.properties.get(name)?.as_u64()?).ok()
+    }
+
+    fn size(&self, name: &str) -> Option<f64> {
+        self.properties
+            .get(name)?
+            .as_f64()
+            .filter(|size| size.is_finite() && *size >= 0.0)
+    }
+}
+
+impl DemandOfferBase {
+    pub fn inf_resources(&self) -> InfResources<'_> {
+        InfResources::new(&self.properties)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn doc_offer() -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({
+                "golem.com.pricing.model": "linear",
+                "golem.com.pricing.model.linear.coeffs": [0.001, 0.002, 0.0],
+                "golem.com.scheme": "payu",
+                "golem.com.scheme.payu.interval_sec": 6.0,
+                "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+                "golem.inf.cpu.architecture": "x86_64",
+                "golem.inf.cpu.cores": 4,
+                "golem.inf.cpu.threads": 7,
+                "golem.inf.mem.gib": 10.612468048930168,
+                "golem.inf.storage.gib": 81.7227783203125,
+                "golem.node.debug.subnet": "market-devnet",
+                "golem.node.id.name": "tworec@mf-market-devnet",
+                "golem.runtime.name": "vm",
+                "golem.runtime.version@v": "0.1.0"
+            }),
+            "()".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_read_doc_offer() {
+        let offer = doc_offer();
+        let inf = offer.inf_resources();
+
+        assert_eq!(inf.cpu_architecture(), Some("x86_64"));
+        assert_eq!(inf.cpu_cores(), Some(4));
+        assert_eq!(inf.cpu_threads(), Some(7));
+        assert_eq!(inf.mem_gib(), Some(10.612468048930168));
+        assert_eq!(inf.storage_gib(), Some(81.7227783203125));
+    }
+
+    #[test]
+    fn test_malformed_properties() {
+        let offer = DemandOfferBase::new(
+            json!({
+                "golem.inf.cpu.architecture": 64,
+                "golem.inf.cpu.cores": 2.5,
+                "golem.inf.cpu.threads": -1,
+                "golem.inf.mem.gib": "16",
+            }),
+            "()".to_string(),
+        );
+        let inf = offer.inf_resources();
+
+        assert_eq!(inf.cpu_architecture(), None);
+        assert_eq!(inf.cpu_cores(), None);
+        assert_eq!(inf.cpu_threads(), None);
+        assert_eq!(inf.mem_gib(), None);
+        assert_eq!(inf.storage_gib(), None);
+    }
+}
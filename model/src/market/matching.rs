@@ -0,0 +1,116 @@
+use crate::market::constraints::{Constraint, ConstraintFailure, ConstraintParseError};
+use crate::market::DemandOfferBase;
+
+/// Outcome of evaluating one side's constraints against the other's properties.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SideReport {
+    pub failures: Vec<ConstraintFailure>,
+    /// Set if the constraints could not be parsed, failing the match.
+    pub error: Option<ConstraintParseError>,
+}
+
+impl SideReport {
+    fn evaluate(constraints: &str, properties: &serde_json::Value) -> Self {
+        match Constraint::parse(constraints) {
+            Ok(constraint) => SideReport {
+                failures: constraint.failures(properties),
+                error: None,
+            },
+            Err(e) => SideReport {
+                failures: Vec::new(),
+                error: Some(e),
+            },
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.failures.is_empty() && self.error.is_none()
+    }
+}
+
+/// Explains whether, and why not, a Demand and an Offer match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatchReport {
+    /// Demand constraints evaluated against the Offer properties.
+    pub demand: SideReport,
+    /// Offer constraints evaluated against the Demand properties.
+    pub offer: SideReport,
+}
+
+impl MatchReport {
+    pub fn is_match(&self) -> bool {
+        self.demand.is_match() && self.offer.is_match()
+    }
+}
+
+/// Evaluates the constraints of both sides locally, without a market.
+///
+/// This only approximates the market matcher: properties referenced by the
+/// constraints have to be present, and versions are compared as strings.
+pub fn matching_report(demand: &DemandOfferBase, offer: &DemandOfferBase) -> MatchReport {
+    MatchReport {
+        demand: SideReport::evaluate(&demand.constraints, &offer.properties),
+        offer: SideReport::evaluate(&offer.constraints, &demand.properties),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::market::constraints::Operator;
+    use serde_json::json;
+
+    fn offer(cores: u32) -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({
+                "golem.inf.cpu.cores": cores,
+                "golem.inf.mem.gib": 10.612468048930168,
+                "golem.runtime.name": "vm",
+            }),
+            "(golem.srv.comp.expiration>0)".to_string(),
+        )
+    }
+
+    fn demand() -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({"golem.srv.comp.expiration": 1_700_000_000_000_u64}),
+            "(&(golem.inf.cpu.cores>=4)(golem.runtime.name=vm))".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_match() {
+        let report = matching_report(&demand(), &offer(4));
+        assert!(report.is_match(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_near_miss_on_cpu_cores() {
+        let report = matching_report(&demand(), &offer(3));
+
+        assert!(!report.is_match());
+        assert!(report.offer.is_match());
+        assert_eq!(report.demand.error, None);
+        assert_eq!(
+            report.demand.failures,
+            vec![ConstraintFailure {
+                property: "golem.inf.cpu.cores".into(),
+                operator: Operator::GreaterOrEqual,
+                expected: "4".into(),
+                actual: Some(json!(3)),
+                negated: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_malformed_constraints() {
+        let mut offer = offer(4);
+        offer.constraints = "(golem.srv.comp.expiration>0".into();
+        let report = matching_report(&demand(), &offer);
+
+        assert!(report.demand.is_match());
+        assert!(report.offer.error.is_some());
+        assert!(!report.is_match());
+    }
+}
@@ -0,0 +1,152 @@
+use serde_json::{Map, Value};
+
+use crate::market::DemandOfferBase;
+use crate::NodeId;
+
+pub const NODE_NAME_PROPERTY: &str = "golem.node.id.name";
+pub const NODE_SUBNET_PROPERTY: &str = "golem.node.debug.subnet";
+pub const NODE_ID_PROPERTY: &str = "golem.node.id";
+
+/// Read-only view over the well-known `golem.node.*` properties.
+///
+/// Absent (or mistyped) properties are reported as `None`.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeDescriptor<'a> {
+    properties: &'a Value,
+}
+
+impl<'a> NodeDescriptor<'a> {
+    pub fn new(properties: &'a Value) -> Self {
+        NodeDescriptor { properties }
+    }
+
+    pub fn node_name(&self) -> Option<&'a str> {
+        self.properties.get(NODE_NAME_PROPERTY)?.as_str()
+    }
+
+    pub fn subnet(&self) -> Option<&'a str> {
+        self.properties.get(NODE_SUBNET_PROPERTY)?.as_str()
+    }
+
+    pub fn node_id(&self) -> Option<NodeId> {
+        self.properties
+            .get(NODE_ID_PROPERTY)?
+            .as_str()?
+            .parse()
+            .ok()
+    }
+}
+
+/// Sets the `golem.node.*` properties of a Demand/Offer in one go.
+#[derive(Clone, Debug, Default)]
+pub struct NodeDescriptorBuilder {
+    node_name: Option<String>,
+    subnet: Option<String>,
+    node_id: Option<NodeId>,
+}
+
+impl NodeDescriptorBuilder {
+    pub fn node_name(mut self, name: impl Into<String>) -> Self {
+        self.node_name = Some(name.into());
+        self
+    }
+
+    pub fn subnet(mut self, subnet: impl Into<String>) -> Self {
+        self.subnet = Some(subnet.into());
+        self
+    }
+
+    pub fn node_id(mut self, node_id: NodeId) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    /// Writes the configured properties, leaving all the others intact.
+    pub fn apply(&self, base: &mut DemandOfferBase) {
+        if !base.properties.is_object() {
+            base.properties = Value::Object(Map::new());
+        }
+        let properties = base.properties.as_object_mut().unwrap();
+        if let Some(name) = &self.node_name {
+            properties.insert(NODE_NAME_PROPERTY.into(), name.as_str().into());
+        }
+        if let Some(subnet) = &self.subnet {
+            properties.insert(NODE_SUBNET_PROPERTY.into(), subnet.as_str().into());
+        }
+        if let Some(node_id) = &self.node_id {
+            properties.insert(NODE_ID_PROPERTY.into(), node_id.to_string().into());
+        }
+    }
+}
+
+impl DemandOfferBase {
+    pub fn node_descriptor(&self) -> NodeDescriptor<'_> {
+        NodeDescriptor::new(&self.properties)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn doc_offer() -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({
+                "golem.com.pricing.model": "linear",
+                "golem.com.pricing.model.linear.coeffs": [0.001, 0.002, 0.0],
+                "golem.com.scheme": "payu",
+                "golem.com.scheme.payu.interval_sec": 6.0,
+                "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+                "golem.inf.cpu.architecture": "x86_64",
+                "golem.inf.cpu.cores": 4,
+                "golem.inf.cpu.threads": 7,
+                "golem.inf.mem.gib": 10.612468048930168,
+                "golem.inf.storage.gib": 81.7227783203125,
+                "golem.node.debug.subnet": "market-devnet",
+                "golem.node.id.name": "tworec@mf-market-devnet",
+                "golem.runtime.name": "vm",
+                "golem.runtime.version@v": "0.1.0"
+            }),
+            "()".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_read_doc_offer() {
+        let offer = doc_offer();
+        let node = offer.node_descriptor();
+
+        assert_eq!(node.node_name(), Some("tworec@mf-market-devnet"));
+        assert_eq!(node.subnet(), Some("market-devnet"));
+        assert_eq!(node.node_id(), None);
+    }
+
+    #[test]
+    fn test_absent_properties() {
+        let offer = DemandOfferBase::new(json!({}), "()".to_string());
+        let node = offer.node_descriptor();
+
+        assert_eq!(node.node_name(), None);
+        assert_eq!(node.subnet(), None);
+        assert_eq!(node.node_id(), None);
+    }
+
+    #[test]
+    fn test_builder_roundtrip() {
+        let node_id: NodeId = "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap();
+        let mut offer = doc_offer();
+        NodeDescriptorBuilder::default()
+            .subnet("public")
+            .node_id(node_id)
+            .apply(&mut offer);
+
+        let node = offer.node_descriptor();
+        assert_eq!(node.node_name(), Some("tworec@mf-market-devnet"));
+        assert_eq!(node.subnet(), Some("public"));
+        assert_eq!(node.node_id(), Some(node_id));
+        assert_eq!(offer.properties["golem.inf.cpu.cores"], json!(4));
+    }
+}
@@ -0,0 +1,148 @@
+use bigdecimal::BigDecimal;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::market::DemandOfferBase;
+
+pub const PRICING_MODEL_PROPERTY: &str = "golem.com.pricing.model";
+pub const LINEAR_COEFFS_PROPERTY: &str = "golem.com.pricing.model.linear.coeffs";
+pub const USAGE_VECTOR_PROPERTY: &str = "golem.com.usage.vector";
+
+/// Linear pricing scheme of an Offer.
+///
+/// Each coefficient is the price per unit of the usage counter at the same
+/// position in the usage vector, while the extra, last coefficient is the
+/// fixed price of starting an activity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearPricing {
+    pub usage_vector: Vec<String>,
+    pub coeffs: Vec<BigDecimal>,
+    pub fixed_price: BigDecimal,
+}
+
+impl LinearPricing {
+    /// Extracts the scheme, `None` unless the properties describe a complete
+    /// linear pricing model.
+    pub fn from_properties(properties: &Value) -> Option<Self> {
+        if properties.get(PRICING_MODEL_PROPERTY)?.as_str()? != "linear" {
+            return None;
+        }
+        let mut coeffs = properties
+            .get(LINEAR_COEFFS_PROPERTY)?
+            .as_array()?
+            .iter()
+            .map(|c| {
+                c.as_f64()
+                    .and_then(|_| BigDecimal::from_str(&c.to_string()).ok())
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let usage_vector = properties
+            .get(USAGE_VECTOR_PROPERTY)?
+            .as_array()?
+            .iter()
+            .map(|u| u.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?;
+        if coeffs.len() != usage_vector.len() + 1 {
+            return None;
+        }
+        let fixed_price = coeffs.pop()?;
+        Some(LinearPricing {
+            usage_vector,
+            coeffs,
+            fixed_price,
+        })
+    }
+
+    /// Price for the given usage, ordered as the usage vector.
+    pub fn price_for(&self, usage: &[f64]) -> Option<BigDecimal> {
+        if usage.len() != self.coeffs.len() {
+            return None;
+        }
+        let mut price = self.fixed_price.clone();
+        for (coeff, amount) in self.coeffs.iter().zip(usage) {
+            if !amount.is_finite() {
+                return None;
+            }
+            price += coeff * BigDecimal::from_str(&amount.to_string()).ok()?;
+        }
+        Some(price)
+    }
+}
+
+impl DemandOfferBase {
+    pub fn linear_pricing(&self) -> Option<LinearPricing> {
+        LinearPricing::from_properties(&self.properties)
+    }
+}
+
+/// Estimated price of the Offer for the expected usage (ordered as its usage
+/// vector), or `None` if it has no usable linear pricing.
+pub fn estimate_price(offer: &DemandOfferBase, expected_usage: &[f64]) -> Option<BigDecimal> {
+    offer.linear_pricing()?.price_for(expected_usage)
+}
+
+/// Indices of the Offers paired with their estimated price, cheapest first.
+///
+/// Offers without a usable linear pricing are left out.
+pub fn rank_offers(offers: &[DemandOfferBase], expected_usage: &[f64]) -> Vec<(usize, BigDecimal)> {
+    let mut ranked: Vec<_> = offers
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, offer)| Some((idx, estimate_price(offer, expected_usage)?)))
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| a.cmp(b));
+    ranked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn offer(coeffs: Value) -> DemandOfferBase {
+        DemandOfferBase::new(
+            json!({
+                "golem.com.pricing.model": "linear",
+                "golem.com.pricing.model.linear.coeffs": coeffs,
+                "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+            }),
+            "()".to_string(),
+        )
+    }
+
+    fn amount(v: &str) -> BigDecimal {
+        BigDecimal::from_str(v).unwrap()
+    }
+
+    #[test]
+    fn test_estimate_price() {
+        let offer = offer(json!([0.001, 0.002, 0.5]));
+        assert_eq!(estimate_price(&offer, &[100.0, 50.0]), Some(amount("0.7")));
+        assert_eq!(estimate_price(&offer, &[100.0]), None);
+    }
+
+    #[test]
+    fn test_no_linear_pricing() {
+        let missing_fixed_price = offer(json!([0.1, 0.1]));
+        assert_eq!(missing_fixed_price.linear_pricing(), None);
+
+        let mut auction = offer(json!([0.001, 0.002, 0.5]));
+        auction.properties[PRICING_MODEL_PROPERTY] = json!("auction");
+        assert_eq!(estimate_price(&auction, &[1.0, 1.0]), None);
+    }
+
+    #[test]
+    fn test_rank_offers() {
+        let offers = vec![
+            offer(json!([0.003, 0.001, 0.0])),
+            offer(json!([0.001, 0.001, 0.0])),
+            DemandOfferBase::new(json!({}), "()".to_string()),
+            offer(json!([0.001, 0.002, 0.05])),
+        ];
+
+        assert_eq!(
+            rank_offers(&offers, &[100.0, 100.0]),
+            vec![(1, amount("0.2")), (3, amount("0.35")), (0, amount("0.4"))]
+        );
+    }
+}
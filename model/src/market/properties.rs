@@ -0,0 +1,174 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::market::{Constraints, DemandOfferBase, ValidationError};
+
+/// Accumulates the properties of a Demand/Offer into a flat-convention
+/// object, checking the property names on the way.
+///
+/// Names have to be dot-separated, non-empty segments of ASCII letters,
+/// digits, `-` and `_`, optionally with a `@` type suffix (eg. `@v`) or
+/// a `?` marking a negotiable property. The first invalid name or value is
+/// reported by [`build`](Self::build).
+#[derive(Clone, Debug, Default)]
+pub struct PropertiesBuilder {
+    properties: Map<String, Value>,
+    error: Option<ValidationError>,
+}
+
+impl PropertiesBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the property, replacing its previous value.
+    pub fn set<T: Serialize>(mut self, name: &str, value: T) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        if !is_property_name(name) {
+            self.error = Some(ValidationError::InvalidPropertyName(name.to_string()));
+            return self;
+        }
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.properties.insert(name.to_string(), value);
+            }
+            Err(_) => {
+                self.error = Some(ValidationError::InvalidPropertyValue {
+                    name: name.to_string(),
+                    reason: "not representable in JSON",
+                })
+            }
+        }
+        self
+    }
+
+    /// Sets the property only if `value` is present.
+    pub fn set_if_some<T: Serialize>(self, name: &str, value: Option<T>) -> Self {
+        match value {
+            Some(value) => self.set(name, value),
+            None => self,
+        }
+    }
+
+    pub fn build(self) -> Result<Value, ValidationError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(Value::Object(self.properties)),
+        }
+    }
+}
+
+fn is_property_name(name: &str) -> bool {
+    let name = name.strip_suffix('?').unwrap_or(name);
+    let name = match name.rsplit_once('@') {
+        Some((name, suffix)) if is_segment(suffix) => name,
+        Some(_) => return false,
+        None => name,
+    };
+    name.split('.').all(is_segment)
+}
+
+fn is_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl DemandOfferBase {
+    pub fn from_properties(
+        properties: PropertiesBuilder,
+        constraints: Constraints,
+    ) -> Result<DemandOfferBase, ValidationError> {
+        Ok(DemandOfferBase::new(
+            properties.build()?,
+            constraints.into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::market::ConstraintsBuilder;
+    use serde_json::json;
+
+    #[test]
+    fn test_doc_example() {
+        let threads: Option<u32> = Some(7);
+        let subnet: Option<&str> = None;
+        let properties = PropertiesBuilder::new()
+            .set("golem.com.pricing.model", "linear")
+            .set("golem.com.pricing.model.linear.coeffs", [0.001, 0.002, 0.0])
+            .set("golem.com.scheme", "payu")
+            .set("golem.com.scheme.payu.interval_sec", 6.0)
+            .set(
+                "golem.com.usage.vector",
+                ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+            )
+            .set("golem.inf.cpu.architecture", "x86_64")
+            .set("golem.inf.cpu.cores", 4)
+            .set_if_some("golem.inf.cpu.threads", threads)
+            .set("golem.inf.mem.gib", 10.612468048930168)
+            .set("golem.inf.storage.gib", 81.7227783203125)
+            .set_if_some("golem.node.debug.subnet", subnet)
+            .set("golem.node.debug.subnet", "market-devnet")
+            .set("golem.node.id.name", "tworec@mf-market-devnet")
+            .set("golem.runtime.name", "vm")
+            .set("golem.runtime.version@v", "0.1.0");
+        let offer = DemandOfferBase::from_properties(properties, ConstraintsBuilder::new().build())
+            .unwrap();
+
+        assert_eq!(
+            offer.properties,
+            json!({
+                "golem.com.pricing.model": "linear",
+                "golem.com.pricing.model.linear.coeffs": [0.001, 0.002, 0.0],
+                "golem.com.scheme": "payu",
+                "golem.com.scheme.payu.interval_sec": 6.0,
+                "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"],
+                "golem.inf.cpu.architecture": "x86_64",
+                "golem.inf.cpu.cores": 4,
+                "golem.inf.cpu.threads": 7,
+                "golem.inf.mem.gib": 10.612468048930168,
+                "golem.inf.storage.gib": 81.7227783203125,
+                "golem.node.debug.subnet": "market-devnet",
+                "golem.node.id.name": "tworec@mf-market-devnet",
+                "golem.runtime.name": "vm",
+                "golem.runtime.version@v": "0.1.0"
+            })
+        );
+        assert_eq!(offer.constraints, "()");
+        assert!(offer.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in [
+            "",
+            "golem..cores",
+            "golem.inf.cpu cores",
+            ".golem",
+            "golem.x@",
+            "golem@v.x",
+        ] {
+            assert_eq!(
+                PropertiesBuilder::new().set(name, 1).build(),
+                Err(ValidationError::InvalidPropertyName(name.into())),
+                "{:?}",
+                name
+            );
+        }
+        let properties = PropertiesBuilder::new()
+            .set("golem.com.payment.debit-notes.accept-timeout?", 240)
+            .set(
+                "golem.com.payment.platform.erc20-holesky-tglm.address",
+                "0xbabe",
+            )
+            .build()
+            .unwrap();
+        assert_eq!(properties.as_object().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,29 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// Id of a Demand or Offer subscription, as returned by `subscribe`.
+#[derive(Clone, Debug, Display, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SubscriptionId(String);
+
+impl SubscriptionId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for SubscriptionId {
+    fn from(id: String) -> Self {
+        SubscriptionId(id)
+    }
+}
+
+impl AsRef<str> for SubscriptionId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
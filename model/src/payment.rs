@@ -7,6 +7,7 @@ pub mod debit_note;
 pub mod debit_note_event;
 pub mod document_status;
 pub mod driver_details;
+pub mod driver_status;
 pub mod invoice;
 pub mod invoice_event;
 pub mod market_decoration;
@@ -17,18 +18,23 @@ pub mod payment;
 pub mod rejection;
 pub mod rejection_reason;
 
-pub use self::acceptance::Acceptance;
+pub use self::acceptance::{Acceptance, AmountError};
 pub use self::account::Account;
 pub use self::activity_payment::ActivityPayment;
 pub use self::agreement_payment::AgreementPayment;
 pub use self::allocation::Allocation;
 pub use self::allocation::AllocationUpdate;
+pub use self::allocation::AllocationUpdateBuilder;
+pub use self::allocation::AllocationValidation;
 pub use self::allocation::NewAllocation;
+pub use self::allocation::NewAllocationBuilder;
+pub use self::allocation::PaymentPlatform;
 pub use self::debit_note::DebitNote;
 pub use self::debit_note::NewDebitNote;
 pub use self::debit_note_event::{DebitNoteEvent, DebitNoteEventType};
 pub use self::document_status::DocumentStatus;
 pub use self::driver_details::DriverDetails;
+pub use self::driver_status::DriverStatus;
 pub use self::invoice::Invoice;
 pub use self::invoice::NewInvoice;
 pub use self::invoice_event::{InvoiceEvent, InvoiceEventType};
@@ -1,4 +1,4 @@
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, Signed};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -7,3 +7,79 @@ pub struct Acceptance {
     pub total_amount_accepted: BigDecimal,
     pub allocation_id: String,
 }
+
+/// Why an accepted amount given to [`Acceptance::new`] or
+/// [`Rejection::new`](crate::payment::Rejection::new) is refused.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AmountError {
+    #[error("accepted amount {0} is negative")]
+    Negative(BigDecimal),
+    #[error("accepted amount {accepted} exceeds the document amount {amount}")]
+    ExceedsAmount {
+        accepted: BigDecimal,
+        amount: BigDecimal,
+    },
+}
+
+impl Acceptance {
+    /// Accepts `total_amount_accepted` paid from the allocation, refusing
+    /// a negative amount or one above the `amount` of the accepted invoice
+    /// or debit note, when given.
+    pub fn new(
+        total_amount_accepted: BigDecimal,
+        allocation_id: String,
+        amount: Option<&BigDecimal>,
+    ) -> Result<Self, AmountError> {
+        check_non_negative(&total_amount_accepted)?;
+        if let Some(amount) = amount.filter(|amount| total_amount_accepted > **amount) {
+            return Err(AmountError::ExceedsAmount {
+                accepted: total_amount_accepted,
+                amount: amount.clone(),
+            });
+        }
+        Ok(Acceptance {
+            total_amount_accepted,
+            allocation_id,
+        })
+    }
+}
+
+pub(crate) fn check_non_negative(amount: &BigDecimal) -> Result<(), AmountError> {
+    if amount.is_negative() {
+        return Err(AmountError::Negative(amount.clone()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    fn amount(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn acceptance_amount_is_validated() {
+        let acceptance =
+            Acceptance::new(amount("10"), "alloc".into(), Some(&amount("10"))).unwrap();
+        assert_eq!(
+            serde_json::to_value(&acceptance).unwrap(),
+            json!({"totalAmountAccepted": "10", "allocationId": "alloc"})
+        );
+        assert_eq!(
+            Acceptance::new(amount("10.01"), "alloc".into(), Some(&amount("10"))),
+            Err(AmountError::ExceedsAmount {
+                accepted: amount("10.01"),
+                amount: amount("10"),
+            })
+        );
+        assert_eq!(
+            Acceptance::new(amount("-1"), "alloc".into(), None),
+            Err(AmountError::Negative(amount("-1")))
+        );
+        assert!(Acceptance::new(amount("1000"), "alloc".into(), None).is_ok());
+    }
+}
@@ -17,6 +17,18 @@ pub struct Allocation {
     pub make_deposit: bool,
 }
 
+impl Allocation {
+    /// Whether the spent and remaining amounts add up to the total one.
+    pub fn is_consistent(&self) -> bool {
+        &self.spent_amount + &self.remaining_amount == self.total_amount
+    }
+
+    /// Whether `amount` can still be paid from the allocation.
+    pub fn can_afford(&self, amount: &BigDecimal) -> bool {
+        *amount <= self.remaining_amount
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentPlatform {
@@ -43,7 +55,58 @@ pub struct NewAllocation {
     pub make_deposit: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+impl NewAllocation {
+    /// Allocation of `total_amount` on the default platform and address,
+    /// without a timeout or a deposit unless set otherwise.
+    pub fn builder(total_amount: BigDecimal) -> NewAllocationBuilder {
+        NewAllocationBuilder(NewAllocation {
+            address: None,
+            payment_platform: None,
+            total_amount,
+            timeout: None,
+            make_deposit: false,
+        })
+    }
+}
+
+/// Builder of a [`NewAllocation`], see [`NewAllocation::builder`].
+#[derive(Clone, Debug)]
+pub struct NewAllocationBuilder(NewAllocation);
+
+impl NewAllocationBuilder {
+    pub fn address(mut self, address: &str) -> Self {
+        self.0.address = Some(address.to_string());
+        self
+    }
+
+    pub fn payment_platform(mut self, platform: PaymentPlatformEnum) -> Self {
+        self.0.payment_platform = Some(platform);
+        self
+    }
+
+    pub fn total_amount(mut self, total_amount: BigDecimal) -> Self {
+        self.0.total_amount = total_amount;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: DateTime<Utc>) -> Self {
+        self.0.timeout = Some(timeout);
+        self
+    }
+
+    pub fn make_deposit(mut self, make_deposit: bool) -> Self {
+        self.0.make_deposit = make_deposit;
+        self
+    }
+
+    pub fn build(self) -> NewAllocation {
+        self.0
+    }
+}
+
+/// Partial update of an allocation, leaving the fields which are not set
+/// unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AllocationUpdate {
     #[serde(skip_serializing_if = "Option::is_none", default)]
@@ -51,3 +114,150 @@ pub struct AllocationUpdate {
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub timeout: Option<DateTime<Utc>>,
 }
+
+impl AllocationUpdate {
+    /// Update changing nothing until some of the fields are set.
+    pub fn builder() -> AllocationUpdateBuilder {
+        AllocationUpdateBuilder(AllocationUpdate::default())
+    }
+}
+
+/// Builder of an [`AllocationUpdate`], see [`AllocationUpdate::builder`].
+#[derive(Clone, Debug)]
+pub struct AllocationUpdateBuilder(AllocationUpdate);
+
+impl AllocationUpdateBuilder {
+    pub fn total_amount(mut self, total_amount: BigDecimal) -> Self {
+        self.0.total_amount = Some(total_amount);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: DateTime<Utc>) -> Self {
+        self.0.timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> AllocationUpdate {
+        self.0
+    }
+}
+
+/// Outcome of a preflight check whether an allocation would be accepted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocationValidation {
+    pub valid: bool,
+    /// Why the allocation would be refused, only present when not `valid`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub required_amount: Option<BigDecimal>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub available_amount: Option<BigDecimal>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{from_value, json, to_value};
+    use std::str::FromStr;
+
+    fn allocation(total: &str, spent: &str, remaining: &str) -> Allocation {
+        from_value(json!({
+            "allocationId": "a",
+            "address": "0xbabe",
+            "paymentPlatform": "erc20-holesky-tglm",
+            "totalAmount": total,
+            "spentAmount": spent,
+            "remainingAmount": remaining,
+            "timestamp": "2024-01-01T00:00:00Z",
+            "makeDeposit": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn allocation_consistency() {
+        assert!(allocation("10", "2.5", "7.5").is_consistent());
+        assert!(allocation("10.00", "10", "0").is_consistent());
+        assert!(!allocation("10", "2.5", "8").is_consistent());
+    }
+
+    #[test]
+    fn allocation_affordability() {
+        let allocation = allocation("10", "2.5", "7.5");
+        let amount = |value| BigDecimal::from_str(value).unwrap();
+        assert!(allocation.can_afford(&amount("7.5")));
+        assert!(allocation.can_afford(&amount("7.500")));
+        assert!(allocation.can_afford(&amount("0")));
+        assert!(!allocation.can_afford(&amount("7.5000001")));
+    }
+
+    #[test]
+    fn new_allocation_builder() {
+        let timeout = "2024-01-02T00:00:00Z".parse().unwrap();
+        let allocation = NewAllocation::builder(BigDecimal::from(5))
+            .timeout(timeout)
+            .make_deposit(true)
+            .build();
+        assert_eq!(
+            to_value(&allocation).unwrap(),
+            json!({
+                "address": null,
+                "paymentPlatform": null,
+                "totalAmount": "5",
+                "timeout": "2024-01-02T00:00:00Z",
+                "makeDeposit": true,
+            })
+        );
+    }
+
+    #[test]
+    fn allocation_update_builder() {
+        let timeout = "2024-01-02T00:00:00Z".parse().unwrap();
+        let update = AllocationUpdate::builder().timeout(timeout).build();
+        assert_eq!(
+            to_value(&update).unwrap(),
+            json!({"timeout": "2024-01-02T00:00:00Z"})
+        );
+
+        let update = AllocationUpdate::builder()
+            .total_amount(BigDecimal::from(7))
+            .build();
+        assert_eq!(to_value(&update).unwrap(), json!({"totalAmount": "7"}));
+        assert_eq!(to_value(AllocationUpdate::default()).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn valid_allocation_validation() {
+        let validation: AllocationValidation = from_value(json!({"valid": true})).unwrap();
+        assert_eq!(
+            validation,
+            AllocationValidation {
+                valid: true,
+                reason: None,
+                required_amount: None,
+                available_amount: None,
+            }
+        );
+        assert_eq!(to_value(&validation).unwrap(), json!({"valid": true}));
+    }
+
+    #[test]
+    fn invalid_allocation_validation() {
+        let validation: AllocationValidation = from_value(json!({
+            "valid": false,
+            "reason": "insufficient funds",
+            "requiredAmount": "10.5",
+            "availableAmount": "3",
+        }))
+        .unwrap();
+        assert!(!validation.valid);
+        assert_eq!(validation.reason.as_deref(), Some("insufficient funds"));
+        assert_eq!(
+            validation.required_amount,
+            Some(BigDecimal::from_str("10.5").unwrap())
+        );
+        assert_eq!(validation.available_amount, Some(BigDecimal::from(3)));
+    }
+}
@@ -2,18 +2,19 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
-use super::DriverStatusProperty;
+use super::{DocumentStatus, DriverStatusProperty};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct DebitNoteEvent {
     pub debit_note_id: String,
+    #[serde(with = "crate::time")]
     pub event_date: DateTime<Utc>,
     #[serde(flatten)]
     pub event_type: DebitNoteEventType,
 }
 
-#[derive(Clone, Debug, Display, Serialize, Deserialize)]
+#[derive(Clone, Debug, Display, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "eventType")]
 pub enum DebitNoteEventType {
     DebitNoteReceivedEvent,
@@ -27,6 +28,12 @@ pub enum DebitNoteEventType {
         property: DriverStatusProperty,
     },
     DebitNotePaymentOkEvent,
+    /// Event type unknown to this crate, with its raw `eventType` tag.
+    ///
+    /// Known event types whose payload fails to parse end up here as well.
+    #[cfg(feature = "lenient-enums")]
+    #[serde(untagged, with = "crate::lenient::event_type")]
+    Unknown(String),
 }
 
 impl DebitNoteEventType {
@@ -40,6 +47,22 @@ impl DebitNoteEventType {
             DebitNoteSettledEvent => "SETTLED",
             DebitNotePaymentStatusEvent { .. } => "PAYMENT_EVENT",
             DebitNotePaymentOkEvent => "PAYMENT_OK",
+            #[cfg(feature = "lenient-enums")]
+            Unknown(_) => "UNKNOWN",
+        }
+    }
+
+    /// Status of the debit note after this event, `None` for the payment
+    /// events, which leave it unchanged.
+    pub fn resulting_status(&self) -> Option<DocumentStatus> {
+        use DebitNoteEventType::*;
+        match self {
+            DebitNoteReceivedEvent => Some(DocumentStatus::Received),
+            DebitNoteAcceptedEvent => Some(DocumentStatus::Accepted),
+            DebitNoteRejectedEvent { .. } => Some(DocumentStatus::Rejected),
+            DebitNoteCancelledEvent => Some(DocumentStatus::Cancelled),
+            DebitNoteSettledEvent => Some(DocumentStatus::Settled),
+            _ => None,
         }
     }
 
@@ -112,4 +135,80 @@ mod test {
             serde_json::to_string(&ie).unwrap()
         );
     }
+
+    #[test]
+    fn test_deserialize_event() {
+        let ie: DebitNoteEvent = serde_json::from_str(
+            "{\
+                \"debitNoteId\":\"ajdik\",\
+                \"eventDate\":\"2020-12-21T15:51:21.126645Z\",\
+                \"eventType\":\"DebitNoteAcceptedEvent\"\
+            }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            DebitNoteEvent {
+                debit_note_id: "ajdik".to_string(),
+                event_date: DateTime::parse_from_str("2020-12-21T15:51:21.126645Z", "%+")
+                    .unwrap()
+                    .into(),
+                event_type: DebitNoteEventType::DebitNoteAcceptedEvent,
+            },
+            ie
+        );
+    }
+
+    #[test]
+    fn test_serialize_event_type() {
+        let iet = DebitNoteEventType::DebitNoteSettledEvent;
+        assert_eq!(
+            "{\"eventType\":\"DebitNoteSettledEvent\"}",
+            serde_json::to_string(&iet).unwrap()
+        );
+        assert_eq!(iet.to_string(), "DebitNoteSettledEvent");
+    }
+
+    #[test]
+    fn test_deserialize_rejected_event_type() {
+        let iet: DebitNoteEventType = serde_json::from_str(
+            "{\
+                \"eventType\":\"DebitNoteRejectedEvent\",\
+                \"rejection\":{\
+                    \"rejectionReason\":\"UNSOLICITED_SERVICE\",\
+                    \"totalAmountAccepted\":\"13.14\"\
+                }\
+            }",
+        )
+        .unwrap();
+        assert_eq!(
+            DebitNoteEventType::DebitNoteRejectedEvent {
+                rejection: Rejection {
+                    rejection_reason: RejectionReason::UnsolicitedService,
+                    total_amount_accepted: "13.14".parse().unwrap(),
+                    message: None,
+                },
+            },
+            iet
+        );
+        assert_eq!(iet.resulting_status(), Some(DocumentStatus::Rejected));
+    }
+
+    #[test]
+    fn test_deserialize_event_type_from_str() {
+        let iet = DebitNoteEventType::from_discriminant_and_details(
+            "REJECTED",
+            DebitNoteEventType::DebitNoteRejectedEvent {
+                rejection: Default::default(),
+            }
+            .details(),
+        )
+        .unwrap();
+        assert_eq!(
+            DebitNoteEventType::DebitNoteRejectedEvent {
+                rejection: Default::default()
+            },
+            iet
+        );
+    }
 }
@@ -2,7 +2,13 @@ use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+/// Status of an invoice or a debit note.
+///
+/// With the `lenient-enums` feature statuses unknown to this crate are kept
+/// in the `Unknown` arm instead of failing deserialization. The enum is not
+/// `Copy` then.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "lenient-enums"), derive(Copy))]
 #[serde(rename_all = "UPPERCASE")]
 pub enum DocumentStatus {
     Issued,
@@ -12,6 +18,9 @@ pub enum DocumentStatus {
     Failed,
     Settled,
     Cancelled,
+    #[cfg(feature = "lenient-enums")]
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -30,11 +39,45 @@ impl TryFrom<String> for DocumentStatus {
             "FAILED" => Ok(DocumentStatus::Failed),
             "SETTLED" => Ok(DocumentStatus::Settled),
             "CANCELLED" => Ok(DocumentStatus::Cancelled),
+            #[cfg(feature = "lenient-enums")]
+            _ => Ok(DocumentStatus::Unknown(value)),
+            #[cfg(not(feature = "lenient-enums"))]
             _ => Err(InvalidOption(value)),
         }
     }
 }
 
+impl DocumentStatus {
+    /// Checks if a document in this status can later be seen in `next` status,
+    /// either directly or through statuses in between.
+    ///
+    /// Documents go Issued, Received, Accepted and Settled in this order. Until
+    /// Accepted they may end up Rejected or Cancelled instead, and until
+    /// Settled they may end up Failed.
+    /// Transitions from or to an unknown status are never expected.
+    pub fn can_transition_to(&self, next: &DocumentStatus) -> bool {
+        use DocumentStatus::*;
+        match self {
+            Issued => matches!(
+                next,
+                Received | Accepted | Settled | Rejected | Cancelled | Failed
+            ),
+            Received => matches!(next, Accepted | Settled | Rejected | Cancelled | Failed),
+            Accepted => matches!(next, Settled | Failed),
+            Settled | Rejected | Cancelled | Failed => false,
+            #[cfg(feature = "lenient-enums")]
+            Unknown(_) => false,
+        }
+    }
+
+    /// Checks if a document in this status never changes it again, ie. it
+    /// is Settled, Rejected, Cancelled or Failed.
+    pub fn is_terminal(&self) -> bool {
+        use DocumentStatus::*;
+        matches!(self, Settled | Rejected | Cancelled | Failed)
+    }
+}
+
 impl From<DocumentStatus> for String {
     fn from(invoice_status: DocumentStatus) -> Self {
         invoice_status.to_string()
@@ -50,3 +93,73 @@ impl Display for DocumentStatus {
         write!(f, "{}", str)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_known_status_round_trip() {
+        let status: DocumentStatus = serde_json::from_str("\"SETTLED\"").unwrap();
+        assert_eq!(status, DocumentStatus::Settled);
+        assert_eq!(serde_json::to_string(&status).unwrap(), "\"SETTLED\"");
+        assert_eq!(status.to_string(), "SETTLED");
+    }
+
+    #[test]
+    fn test_transitions() {
+        use DocumentStatus::*;
+        for (from, to) in [
+            (Issued, Received),
+            (Received, Accepted),
+            (Accepted, Settled),
+            (Issued, Settled),
+            (Issued, Cancelled),
+            (Received, Rejected),
+            (Accepted, Failed),
+        ] {
+            assert!(from.can_transition_to(&to), "{} -> {}", from, to);
+        }
+        for (from, to) in [
+            (Settled, Received),
+            (Received, Issued),
+            (Accepted, Received),
+            (Accepted, Rejected),
+            (Accepted, Cancelled),
+            (Rejected, Accepted),
+            (Failed, Settled),
+            (Cancelled, Accepted),
+            (Settled, Failed),
+            (Received, Received),
+        ] {
+            assert!(!from.can_transition_to(&to), "{} -> {}", from, to);
+        }
+        for status in [Settled, Rejected, Cancelled, Failed] {
+            assert!(status.is_terminal(), "{}", status);
+        }
+        for status in [Issued, Received, Accepted] {
+            assert!(!status.is_terminal(), "{}", status);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "lenient-enums"))]
+    fn test_unknown_status_is_rejected() {
+        assert!(serde_json::from_str::<DocumentStatus>("\"REFUNDED\"").is_err());
+        assert!(DocumentStatus::try_from("REFUNDED".to_string()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "lenient-enums")]
+    fn test_unknown_status_round_trip() {
+        let status: DocumentStatus = serde_json::from_str("\"REFUNDED\"").unwrap();
+        assert_eq!(status, DocumentStatus::Unknown("REFUNDED".into()));
+        assert_eq!(serde_json::to_string(&status).unwrap(), "\"REFUNDED\"");
+        assert_eq!(status.to_string(), "REFUNDED");
+        assert_eq!(
+            DocumentStatus::try_from("REFUNDED".to_string()).unwrap(),
+            status
+        );
+        assert!(!status.can_transition_to(&DocumentStatus::Settled));
+    }
+}
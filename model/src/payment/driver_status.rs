@@ -0,0 +1,68 @@
+use crate::payment::DriverStatusProperty;
+use bigdecimal::num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+/// Synchronization state of a single payment driver on a single network.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriverStatus {
+    pub syncing: bool,
+    /// Latest block seen, kept as a [`BigUint`] as some chains outgrow `u64`.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        default,
+        with = "crate::big_int::option"
+    )]
+    pub last_block: Option<BigUint>,
+    /// Outstanding problems reported by the driver, empty when healthy.
+    #[serde(default)]
+    pub issues: Vec<DriverStatusProperty>,
+}
+
+impl DriverStatus {
+    /// Checks if any of the reported issues blocks payments on this network.
+    pub fn is_blocked(&self) -> bool {
+        self.issues.iter().any(DriverStatusProperty::is_blocking)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{from_value, json, to_value};
+
+    #[test]
+    fn driver_status_serialization() {
+        let status = DriverStatus {
+            syncing: false,
+            last_block: Some(35_102_114_u32.into()),
+            issues: vec![DriverStatusProperty::RpcError {
+                driver: "erc20".into(),
+                network: "polygon".into(),
+            }],
+        };
+        assert_eq!(
+            json!({
+                "syncing": false,
+                "lastBlock": 35_102_114,
+                "issues": [{"kind": "RpcError", "driver": "erc20", "network": "polygon"}],
+            }),
+            to_value(&status).unwrap()
+        );
+        assert!(status.is_blocked());
+    }
+
+    #[test]
+    fn driver_status_deserialization_defaults() {
+        let status: DriverStatus = from_value(json!({"syncing": true})).unwrap();
+        assert_eq!(
+            status,
+            DriverStatus {
+                syncing: true,
+                last_block: None,
+                issues: vec![],
+            }
+        );
+        assert!(!status.is_blocked());
+    }
+}
@@ -2,12 +2,13 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
-use super::DriverStatusProperty;
+use super::{DocumentStatus, DriverStatusProperty};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct InvoiceEvent {
     pub invoice_id: String,
+    #[serde(with = "crate::time")]
     pub event_date: DateTime<Utc>,
     #[serde(flatten)]
     pub event_type: InvoiceEventType,
@@ -27,6 +28,12 @@ pub enum InvoiceEventType {
         property: DriverStatusProperty,
     },
     InvoicePaymentOkEvent,
+    /// Event type unknown to this crate, with its raw `eventType` tag.
+    ///
+    /// Known event types whose payload fails to parse end up here as well.
+    #[cfg(feature = "lenient-enums")]
+    #[serde(untagged, with = "crate::lenient::event_type")]
+    Unknown(String),
 }
 
 impl InvoiceEventType {
@@ -40,6 +47,22 @@ impl InvoiceEventType {
             InvoiceSettledEvent => "SETTLED",
             InvoicePaymentStatusEvent { .. } => "PAYMENT_EVENT",
             InvoicePaymentOkEvent => "PAYMENT_OK",
+            #[cfg(feature = "lenient-enums")]
+            Unknown(_) => "UNKNOWN",
+        }
+    }
+
+    /// Status of the invoice after this event, `None` for the payment
+    /// events, which leave it unchanged.
+    pub fn resulting_status(&self) -> Option<DocumentStatus> {
+        use InvoiceEventType::*;
+        match self {
+            InvoiceReceivedEvent => Some(DocumentStatus::Received),
+            InvoiceAcceptedEvent => Some(DocumentStatus::Accepted),
+            InvoiceRejectedEvent { .. } => Some(DocumentStatus::Rejected),
+            InvoiceCancelledEvent => Some(DocumentStatus::Cancelled),
+            InvoiceSettledEvent => Some(DocumentStatus::Settled),
+            _ => None,
         }
     }
 
@@ -136,6 +159,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_event_date_formats() {
+        for (date, canonical) in [
+            ("2020-12-21T15:51:21Z", "2020-12-21T15:51:21Z"),
+            ("2020-12-21T15:51:21.126645Z", "2020-12-21T15:51:21.126645Z"),
+            (
+                "2020-12-21T15:51:21.126645+00:00",
+                "2020-12-21T15:51:21.126645Z",
+            ),
+            ("2020-12-21T15:51:21+00:00", "2020-12-21T15:51:21Z"),
+        ] {
+            let json = format!(
+                r#"{{"invoiceId":"ajdik","eventDate":"{}","eventType":"InvoiceSettledEvent"}}"#,
+                date
+            );
+            let ie: InvoiceEvent = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                serde_json::to_string(&ie).unwrap(),
+                json.replace(date, canonical)
+            );
+        }
+    }
+
     #[test]
     fn test_serialize_event_type() {
         let iet = InvoiceEventType::InvoiceSettledEvent;
@@ -170,6 +216,59 @@ mod test {
         );
     }
 
+    #[test]
+    #[cfg(not(feature = "lenient-enums"))]
+    fn test_deserialize_unknown_event_type_fails() {
+        let result = serde_json::from_str::<InvoiceEvent>(
+            "{\
+                \"invoiceId\":\"ajdik\",\
+                \"eventDate\":\"2020-12-21T15:51:21.126645Z\",\
+                \"eventType\":\"InvoiceRefundedEvent\"\
+            }",
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "lenient-enums")]
+    fn test_unknown_event_type_round_trip() {
+        let json = "{\
+                \"invoiceId\":\"ajdik\",\
+                \"eventDate\":\"2020-12-21T15:51:21.126645Z\",\
+                \"eventType\":\"InvoiceRefundedEvent\"\
+            }";
+        let ie: InvoiceEvent = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            ie.event_type,
+            InvoiceEventType::Unknown("InvoiceRefundedEvent".into())
+        );
+        assert_eq!(serde_json::to_string(&ie).unwrap(), json);
+
+        let iet: InvoiceEventType =
+            serde_json::from_str("{\"eventType\":\"InvoiceSettledEvent\"}").unwrap();
+        assert_eq!(iet, InvoiceEventType::InvoiceSettledEvent);
+    }
+
+    #[test]
+    fn test_resulting_status() {
+        use InvoiceEventType::*;
+        for (event_type, status) in [
+            (InvoiceReceivedEvent, DocumentStatus::Received),
+            (InvoiceAcceptedEvent, DocumentStatus::Accepted),
+            (
+                InvoiceRejectedEvent {
+                    rejection: Default::default(),
+                },
+                DocumentStatus::Rejected,
+            ),
+            (InvoiceCancelledEvent, DocumentStatus::Cancelled),
+            (InvoiceSettledEvent, DocumentStatus::Settled),
+        ] {
+            assert_eq!(event_type.resulting_status(), Some(status));
+        }
+        assert_eq!(InvoicePaymentOkEvent.resulting_status(), None);
+    }
+
     #[test]
     fn test_deserialize_event_type_to_string() {
         assert_eq!(
@@ -1,6 +1,9 @@
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 
+use crate::payment::acceptance::{check_non_negative, AmountError};
+use crate::payment::RejectionReason;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Rejection {
@@ -9,3 +12,47 @@ pub struct Rejection {
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub message: Option<String>,
 }
+
+impl Rejection {
+    /// Rejects a document for `rejection_reason`, still accepting
+    /// `total_amount_accepted` of it, which must not be negative.
+    pub fn new(
+        rejection_reason: RejectionReason,
+        total_amount_accepted: BigDecimal,
+        message: Option<String>,
+    ) -> Result<Self, AmountError> {
+        check_non_negative(&total_amount_accepted)?;
+        Ok(Rejection {
+            rejection_reason,
+            total_amount_accepted,
+            message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::FromPrimitive;
+
+    #[test]
+    fn rejection_serializes_like_event_payload() {
+        let rejection = Rejection::new(
+            RejectionReason::UnsolicitedService,
+            BigDecimal::from_f32(13.14).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            "{\"rejectionReason\":\"UNSOLICITED_SERVICE\",\
+                \"totalAmountAccepted\":\"13.14000\"}",
+            serde_json::to_string(&rejection).unwrap()
+        );
+
+        let negative = BigDecimal::from_i32(-1).unwrap();
+        assert_eq!(
+            Rejection::new(RejectionReason::BadService, negative.clone(), None),
+            Err(AmountError::Negative(negative))
+        );
+    }
+}
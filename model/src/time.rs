@@ -0,0 +1,76 @@
+//! Timestamp (de)serialization shared by the model types.
+//!
+//! Timestamps are parsed as RFC 3339, falling back to the same format without
+//! any offset, taken as UTC. Use with `#[serde(with = "crate::time")]`.
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+/// Start of the message of a deserialization error caused by a malformed
+/// timestamp, followed by the offending value as a JSON string.
+pub const INVALID_TIMESTAMP: &str = "invalid timestamp ";
+
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|e| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                .map(|dt| dt.and_utc())
+                .map_err(|_| e)
+        })
+}
+
+/// Reads back the value from an [`INVALID_TIMESTAMP`] error message.
+pub fn invalid_timestamp_value(message: &str) -> Option<String> {
+    let quoted = message.strip_prefix(INVALID_TIMESTAMP)?;
+    serde_json::Deserializer::from_str(quoted)
+        .into_iter::<String>()
+        .next()?
+        .ok()
+}
+
+pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(value, serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_timestamp(&value).map_err(|e| {
+        de::Error::custom(format_args!(
+            "{}{}: {}",
+            INVALID_TIMESTAMP,
+            serde_json::Value::from(value.as_str()),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_formats() {
+        let expected = Utc.with_ymd_and_hms(2020, 12, 21, 15, 51, 21).unwrap();
+        assert_eq!(parse_timestamp("2020-12-21T15:51:21Z"), Ok(expected));
+        assert_eq!(parse_timestamp("2020-12-21T16:51:21+01:00"), Ok(expected));
+        assert_eq!(parse_timestamp("2020-12-21T15:51:21"), Ok(expected));
+        assert!(parse_timestamp("21.12.2020").is_err());
+    }
+
+    #[test]
+    fn test_error_message_roundtrip() {
+        let err = serde_json::from_str::<crate::payment::InvoiceEvent>(
+            r#"{
+                "invoiceId": "i-1",
+                "eventDate": "yesterday \"noon\"",
+                "eventType": "InvoiceReceivedEvent"
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            invalid_timestamp_value(&err.to_string()).as_deref(),
+            Some(r#"yesterday "noon""#)
+        );
+    }
+}
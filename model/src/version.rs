@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+/// Version and build metadata of the running yagna daemon.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_date: Option<String>,
+    /// Whether a newer release is known to the daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_update: Option<bool>,
+}
+
+/// Body of the `version/get` response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YagnaVersionResponse {
+    pub current: YagnaVersionInfo,
+    /// Newer release the daemon knows of, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending: Option<YagnaVersionInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YagnaVersionInfo {
+    pub version: String,
+    pub name: String,
+    pub seen: bool,
+    pub release_ts: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insertion_ts: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_ts: Option<String>,
+}
+
+impl From<YagnaVersionResponse> for VersionInfo {
+    /// The version API carries no commit, the release timestamp stands for
+    /// the build date.
+    fn from(response: YagnaVersionResponse) -> Self {
+        VersionInfo {
+            version: response.current.version,
+            commit: None,
+            build_date: Some(response.current.release_ts),
+            requires_update: Some(response.pending.is_some()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_roundtrip() {
+        let info = VersionInfo {
+            version: "0.15.0".into(),
+            commit: Some("2a6b7c8".into()),
+            build_date: Some("2024-02-01".into()),
+            requires_update: Some(false),
+        };
+        let value = serde_json::to_value(&info).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "version": "0.15.0",
+                "commit": "2a6b7c8",
+                "buildDate": "2024-02-01",
+                "requiresUpdate": false,
+            })
+        );
+        assert_eq!(serde_json::from_value::<VersionInfo>(value).unwrap(), info);
+    }
+
+    #[test]
+    fn test_missing_optional_fields() {
+        let info: VersionInfo = serde_json::from_value(json!({"version": "0.15.0"})).unwrap();
+        assert_eq!(info.commit, None);
+        assert_eq!(info.build_date, None);
+        assert_eq!(info.requires_update, None);
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({"version": "0.15.0"})
+        );
+    }
+
+    #[test]
+    fn test_from_version_response() {
+        let response: YagnaVersionResponse = serde_json::from_value(json!({
+            "current": {
+                "version": "0.15.0",
+                "name": "v0.15.0 Some Release",
+                "seen": true,
+                "releaseTs": "2024-02-01T10:00:00",
+            },
+            "pending": {
+                "version": "0.15.1",
+                "name": "v0.15.1 Next Release",
+                "seen": false,
+                "releaseTs": "2024-03-01T10:00:00",
+                "insertionTs": "2024-03-02T08:00:00",
+            },
+        }))
+        .unwrap();
+        assert_eq!(response.current.insertion_ts, None);
+        assert_eq!(
+            VersionInfo::from(response.clone()),
+            VersionInfo {
+                version: "0.15.0".into(),
+                commit: None,
+                build_date: Some("2024-02-01T10:00:00".into()),
+                requires_update: Some(true),
+            }
+        );
+
+        let up_to_date = YagnaVersionResponse {
+            pending: None,
+            ..response
+        };
+        assert_eq!(VersionInfo::from(up_to_date).requires_update, Some(false));
+    }
+}
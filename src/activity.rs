@@ -3,7 +3,7 @@ mod provider;
 mod requestor;
 
 pub use provider::ActivityProviderApi;
-pub use requestor::control::ActivityRequestorControlApi;
+pub use requestor::control::{ActivityRequestorControlApi, STREAM_EXEC_RECONNECTS};
 pub use requestor::state::ActivityRequestorStateApi;
 pub use requestor::ActivityRequestorApi;
 
@@ -1,7 +1,7 @@
 //! Provider part of the Activity API
 use ya_client_model::activity::{ActivityState, ActivityUsage, ProviderEvent, ACTIVITY_API_PATH};
 
-use crate::{web::default_on_timeout, web::WebClient, web::WebInterface, Result};
+use crate::{web::WebClient, web::WebInterface, Result};
 use chrono::{DateTime, Utc};
 use std::time::Duration;
 
@@ -69,6 +69,6 @@ impl ActivityProviderApi {
             #[query] max_events,
         );
 
-        self.client.get(&url).send().json().await.or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 }
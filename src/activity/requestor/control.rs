@@ -1,12 +1,16 @@
 //! Requestor control part of Activity API
 use ya_client_model::activity::{
-    CreateActivityRequest, CreateActivityResult, ExeScriptCommandResult, ExeScriptRequest,
-    RuntimeEvent, ACTIVITY_API_PATH,
+    CommandOutput, CreateActivityRequest, CreateActivityResult, ExeScriptCommandResult,
+    ExeScriptRequest, RuntimeEvent, RuntimeEventKind, ACTIVITY_API_PATH,
 };
 
-use crate::web::{default_on_timeout, Event, WebClient, WebInterface};
+use crate::error::EventStreamErrorReason;
+use crate::web::{Event, WebClient, WebInterface};
 use crate::{Error, Result};
-use futures::{Stream, StreamExt};
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::stream::LocalBoxStream;
+use futures::{SinkExt, Stream, StreamExt};
 use std::convert::TryFrom;
 
 /// Bindings for Requestor Control part of the Activity API.
@@ -62,14 +66,14 @@ impl ActivityRequestorControlApi {
             secret,
         )
         .await
-        .map_err(|e| crate::Error::InternalError(e.to_string()))?;
+        .map_err(|e| crate::Error::internal(e.to_string()))?;
         Ok(api)
     }
 
     /// Destroys given Activity.
     pub async fn destroy_activity(&self, activity_id: &str) -> Result<()> {
         let uri = url_format!("activity/{activity_id}");
-        self.client.delete(&uri).send().json().await?;
+        self.client.delete(&uri).send().json::<()>().await?;
         Ok(())
     }
 
@@ -93,7 +97,7 @@ impl ActivityRequestorControlApi {
             #[query] timeout,
             #[query] command_index,
         );
-        self.client.get(&uri).send().json().await.or_else(default_on_timeout)
+        self.client.get(&uri).send().json_events().await
     }
 
     /// Streams ExeScript batch results
@@ -115,6 +119,150 @@ impl ActivityRequestorControlApi {
             });
         Ok(stream)
     }
+
+    /// Streams typed ExeScript batch events.
+    ///
+    /// Unlike [`stream_exec_batch_results`](#method.stream_exec_batch_results),
+    /// malformed events are reported as errors instead of being skipped, and a
+    /// stream dropped before the batch has finished is transparently reopened
+    /// (up to [`STREAM_EXEC_RECONNECTS`] times in a row without any event in
+    /// between). Must be polled within an actix runtime.
+    pub fn stream_exec(
+        &self,
+        activity_id: &str,
+        batch_id: &str,
+    ) -> impl Stream<Item = Result<RuntimeEvent>> {
+        let exec = ExecStream {
+            api: self.clone(),
+            activity_id: activity_id.to_string(),
+            batch_id: batch_id.to_string(),
+            events: None,
+            reconnects: 0,
+        };
+        futures::stream::unfold(Some(exec), |exec| async move { exec?.next().await })
+    }
+
+    /// Streams ExeScript batch output as separate stdout and stderr streams.
+    ///
+    /// Each of them preserves the order of its own chunks and both complete
+    /// once the batch finishes. Must be called within an actix runtime.
+    ///
+    /// Both streams are bounded, so both have to be drained, or the unneeded
+    /// one dropped: once the buffer of a stream kept but not read fills up,
+    /// the other one stalls as well.
+    pub async fn exec_output_streams(
+        &self,
+        activity_id: &str,
+        batch_id: &str,
+    ) -> Result<(impl Stream<Item = Bytes>, impl Stream<Item = Bytes>)> {
+        let events = self
+            .stream_exec_batch_results(activity_id, batch_id)
+            .await?;
+        Ok(split_output(events, OUTPUT_BUFFER))
+    }
+}
+
+const OUTPUT_BUFFER: usize = 64;
+
+/// Number of consecutive reconnects after which [`stream_exec`] gives up.
+///
+/// [`stream_exec`]: ActivityRequestorControlApi::stream_exec
+pub const STREAM_EXEC_RECONNECTS: usize = 3;
+
+struct ExecStream {
+    api: ActivityRequestorControlApi,
+    activity_id: String,
+    batch_id: String,
+    events: Option<LocalBoxStream<'static, Result<Event>>>,
+    reconnects: usize,
+}
+
+impl ExecStream {
+    /// Yields the next item together with the state to continue from, if any.
+    async fn next(mut self) -> Option<(Result<RuntimeEvent>, Option<Self>)> {
+        loop {
+            let events = match self.events.as_mut() {
+                Some(events) => events,
+                None => {
+                    let (activity_id, batch_id) = (&self.activity_id, &self.batch_id);
+                    let uri = url_format!("activity/{activity_id}/exec/{batch_id}");
+                    match self.api.client.event_stream(&uri).await {
+                        Ok(events) => self.events.insert(events.boxed_local()),
+                        Err(e) => return Some((Err(e), None)),
+                    }
+                }
+            };
+
+            match events.next().await {
+                Some(Ok(evt)) => {
+                    self.reconnects = 0;
+                    return Some((RuntimeEvent::try_from(evt), Some(self)));
+                }
+                Some(Err(e)) => return Some((Err(e), Some(self))),
+                None => self.events = None,
+            }
+
+            match self.is_batch_finished().await {
+                Ok(true) => return None,
+                Ok(false) if self.reconnects < STREAM_EXEC_RECONNECTS => {
+                    self.reconnects += 1;
+                    log::debug!(
+                        "exec stream of batch {} dropped, reconnecting ({}/{})",
+                        self.batch_id,
+                        self.reconnects,
+                        STREAM_EXEC_RECONNECTS
+                    );
+                }
+                Ok(false) => {
+                    let (activity_id, batch_id) = (&self.activity_id, &self.batch_id);
+                    let err = Error::event_stream(
+                        url_format!("activity/{activity_id}/exec/{batch_id}"),
+                        EventStreamErrorReason::Disconnected,
+                    );
+                    return Some((Err(err), None));
+                }
+                Err(e) => return Some((Err(e), None)),
+            }
+        }
+    }
+
+    async fn is_batch_finished(&self) -> Result<bool> {
+        let results = self
+            .api
+            .get_exec_batch_results(&self.activity_id, &self.batch_id, None, None)
+            .await?;
+        Ok(results.last().is_some_and(|r| r.is_batch_finished))
+    }
+}
+
+fn split_output<S>(events: S, buffer: usize) -> (mpsc::Receiver<Bytes>, mpsc::Receiver<Bytes>)
+where
+    S: Stream<Item = RuntimeEvent> + 'static,
+{
+    let (mut stdout_tx, stdout_rx) = mpsc::channel(buffer);
+    let (mut stderr_tx, stderr_rx) = mpsc::channel(buffer);
+
+    actix_rt::spawn(async move {
+        futures::pin_mut!(events);
+        while let Some(event) = events.next().await {
+            let (tx, output) = match event.kind {
+                RuntimeEventKind::StdOut(output) => (&mut stdout_tx, output),
+                RuntimeEventKind::StdErr(output) => (&mut stderr_tx, output),
+                _ => continue,
+            };
+            let bytes = match output {
+                CommandOutput::Str(text) => Bytes::from(text),
+                CommandOutput::Bin(bin) => Bytes::from(bin),
+            };
+            // keep feeding the other stream when only one of them got dropped
+            let _ = tx.send(bytes).await;
+            if stdout_tx.is_closed() && stderr_tx.is_closed() {
+                break;
+            }
+        }
+    });
+
+    (stdout_rx, stderr_rx)
 }
 
 impl TryFrom<Event> for RuntimeEvent {
@@ -357,6 +505,134 @@ pub mod sgx {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use crate::web::mock::{MockResponse, MockServer};
+    use chrono::Utc;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use ya_client_model::activity::CommandResult;
+
+    fn sse(events: &[RuntimeEvent]) -> MockResponse {
+        let body: String = events
+            .iter()
+            .map(|e| format!("event: runtime\ndata: {}\n\n", json!(e)))
+            .collect();
+        MockResponse::new(200)
+            .header("content-type", "text/event-stream")
+            .body(body)
+    }
+
+    fn batch_results(is_batch_finished: bool) -> MockResponse {
+        MockResponse::json(json!([ExeScriptCommandResult {
+            index: 0,
+            result: CommandResult::Ok,
+            stdout: None,
+            stderr: None,
+            message: None,
+            is_batch_finished,
+            event_date: Utc::now(),
+        }]))
+    }
+
+    #[actix_rt::test]
+    async fn test_stream_exec_reconnects_until_batch_finished() {
+        let batch = "batch".to_string();
+        let frames = [
+            vec![
+                RuntimeEvent::stdout(batch.clone(), 0, CommandOutput::Str("out".into())),
+                RuntimeEvent::finished(batch.clone(), 0, 0, None),
+            ],
+            vec![RuntimeEvent::finished(batch.clone(), 1, 0, None)],
+        ];
+        let expected: Vec<_> = frames.concat();
+
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let server = MockServer::start(move |req| {
+            if req.header("accept") == Some("text/event-stream") {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                sse(&frames[n])
+            } else {
+                batch_results(counter.load(Ordering::SeqCst) == frames.len())
+            }
+        });
+        let api: ActivityRequestorControlApi = server.client().interface().unwrap();
+
+        let events: Vec<_> = api
+            .stream_exec("activity", "batch")
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events, expected);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert!(paths
+            .iter()
+            .all(|p| p.contains("/activity/activity/exec/batch")));
+    }
+
+    #[actix_rt::test]
+    async fn test_stream_exec_reports_malformed_event() {
+        let server = MockServer::start(|req| {
+            if req.header("accept") == Some("text/event-stream") {
+                MockResponse::new(200).body("event: runtime\ndata: {}\n\n")
+            } else {
+                batch_results(true)
+            }
+        });
+        let api: ActivityRequestorControlApi = server.client().interface().unwrap();
+
+        let events: Vec<_> = api.stream_exec("activity", "batch").collect().await;
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_split_output() {
+        let batch = "batch".to_string();
+        let events = futures::stream::iter(vec![
+            RuntimeEvent::stdout(batch.clone(), 0, CommandOutput::Str("out-1 ".into())),
+            RuntimeEvent::stderr(batch.clone(), 0, CommandOutput::Str("err-1 ".into())),
+            RuntimeEvent::finished(batch.clone(), 0, 0, None),
+            RuntimeEvent::stdout(batch.clone(), 1, CommandOutput::Bin(b"out-2".to_vec())),
+            RuntimeEvent::stderr(batch.clone(), 1, CommandOutput::Bin(b"err-2".to_vec())),
+            RuntimeEvent::stdout(batch.clone(), 1, CommandOutput::Str(" out-3".into())),
+            RuntimeEvent::finished(batch, 1, 0, None),
+        ]);
+
+        let (stdout, stderr) = split_output(events, 1);
+        let (stdout, stderr): (Vec<Bytes>, Vec<Bytes>) =
+            futures::join!(stdout.collect(), stderr.collect());
+
+        assert_eq!(stdout.concat(), b"out-1 out-2 out-3");
+        assert_eq!(stderr.concat(), b"err-1 err-2");
+    }
+
+    #[actix_rt::test]
+    async fn test_split_output_needs_both_drained() {
+        let events = || {
+            let batch = "batch".to_string();
+            futures::stream::iter((0..4).flat_map(move |idx| {
+                let output = |text: &str| CommandOutput::Str(format!("{}-{} ", text, idx));
+                [
+                    RuntimeEvent::stderr(batch.clone(), idx, output("err")),
+                    RuntimeEvent::stdout(batch.clone(), idx, output("out")),
+                ]
+            }))
+        };
+        let timeout = std::time::Duration::from_millis(200);
+
+        let (stdout, stderr) = split_output(events(), 1);
+        drop(stderr);
+        let stdout = actix_rt::time::timeout(timeout, stdout.collect::<Vec<_>>()).await;
+        assert_eq!(stdout.unwrap().concat(), b"out-0 out-1 out-2 out-3 ");
+
+        let (stdout, _stderr) = split_output(events(), 1);
+        let stdout = actix_rt::time::timeout(timeout, stdout.collect::<Vec<_>>()).await;
+        assert!(stdout.is_err(), "stdout should stall behind stderr");
+    }
 
     #[test]
     #[cfg(feature = "sgx")]
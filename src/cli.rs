@@ -8,9 +8,7 @@ use crate::activity::ACTIVITY_URL_ENV_VAR;
 use crate::market::MARKET_URL_ENV_VAR;
 use crate::net::NET_URL_ENV_VAR;
 use crate::payment::PAYMENT_URL_ENV_VAR;
-use crate::web::{DEFAULT_YAGNA_API_URL, YAGNA_API_URL_ENV_VAR};
-
-const YAGNA_APPKEY_ENV_VAR: &str = "YAGNA_APPKEY";
+use crate::web::{DEFAULT_YAGNA_API_URL, YAGNA_API_URL_ENV_VAR, YAGNA_APPKEY_ENV_VAR};
 
 pub trait ApiClient: Clone {
     type Market: WebInterface;
@@ -1,22 +1,33 @@
 //! Error definitions and mappings
 use awc::error::{JsonPayloadError, PayloadError, SendRequestError};
 use awc::http::{Method, StatusCode};
+use bigdecimal::BigDecimal;
+use std::time::Duration;
 
 use ya_client_model::ErrorMessage;
 
+use crate::web::truncate_body;
+
+/// The variants describing a failed request are `#[non_exhaustive]`, as they
+/// gain fields as more context is kept; match them with `..`.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("AWC error requesting {method} {url}: {msg}")]
+    #[error("AWC error requesting {method} {url}{}: {msg}", fingerprint_suffix(.fingerprint))]
+    #[non_exhaustive]
     SendRequestError {
         msg: String,
         method: Method,
         url: String,
+        /// See [`request_fingerprint`](crate::web::request_fingerprint).
+        fingerprint: Option<String>,
     },
-    #[error("AWC timeout requesting {method} {url}: {msg}")]
+    #[error("AWC timeout requesting {method} {url}{}: {msg}", fingerprint_suffix(.fingerprint))]
+    #[non_exhaustive]
     TimeoutError {
         msg: String,
         method: Method,
         url: String,
+        fingerprint: Option<String>,
     },
     #[error("AWC payload error: {0}")]
     PayloadError(PayloadError),
@@ -24,12 +35,41 @@ pub enum Error {
     JsonPayloadError(JsonPayloadError),
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::error::Error),
-    #[error("HTTP error requesting {method} {url}: {code}; msg: '{msg}'")]
+    #[error(
+        "HTTP error requesting {method} {url}{}: {code}; msg: '{msg}'{}",
+        fingerprint_suffix(.fingerprint),
+        body_suffix(.msg, .body)
+    )]
+    #[non_exhaustive]
     HttpError {
         code: StatusCode,
         msg: String,
         method: Method,
         url: String,
+        fingerprint: Option<String>,
+        /// Raw text of the response body, also when it could not be parsed
+        /// into the structured `msg`.
+        body: Option<String>,
+    },
+    /// A `429 Too Many Requests` or `503 Service Unavailable` response, with
+    /// the delay of its `Retry-After` header, `None` if it is missing or
+    /// cannot be parsed.
+    #[error(
+        "Rate limited requesting {method} {url}{}: {code}{}; msg: '{msg}'{}",
+        fingerprint_suffix(.fingerprint),
+        retry_after_suffix(.retry_after),
+        body_suffix(.msg, .body)
+    )]
+    #[non_exhaustive]
+    RateLimited {
+        code: StatusCode,
+        msg: String,
+        method: Method,
+        url: String,
+        fingerprint: Option<String>,
+        retry_after: Option<Duration>,
+        /// Raw text of the response body, as in [`Error::HttpError`].
+        body: Option<String>,
     },
     #[error("WebSocket error: {0}")]
     WebSocketError(String),
@@ -51,8 +91,62 @@ pub enum Error {
     ApiErrorMessage(#[from] ErrorMessage),
     #[error("Internal ya-client error: {0}")]
     InternalError(String),
-    #[error("Event stream error: {0}")]
-    EventStreamError(String),
+    #[error("Event stream error at {url}: {reason}")]
+    EventStreamError {
+        url: String,
+        reason: EventStreamErrorReason,
+    },
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Invalid app key: {0}")]
+    InvalidAppKey(String),
+    #[error("Unknown payment driver '{driver}' or network '{network}'")]
+    UnknownDriver { driver: String, network: String },
+    #[error("Unexpected content type '{content_type}' of {method} {url} response: '{snippet}'")]
+    UnexpectedContentType {
+        content_type: String,
+        snippet: String,
+        method: Method,
+        url: String,
+    },
+    #[error("Invalid timestamp '{value}' in field `{field}`")]
+    TimestampParse { field: String, value: String },
+    #[error("{algorithm} digest mismatch: expected {expected}, got {actual}")]
+    IntegrityError {
+        algorithm: &'static str,
+        expected: String,
+        actual: String,
+    },
+    #[error("Allocation of {amount} exceeds the limit of {limit}")]
+    AllocationLimitExceeded {
+        amount: BigDecimal,
+        limit: BigDecimal,
+    },
+    #[error("Client is shutting down, not sending new requests")]
+    ShuttingDown,
+    #[error("Proposal {proposal_id} has expired")]
+    ProposalExpired { proposal_id: String },
+}
+
+/// Why an event stream failed, see [`Error::EventStreamError`].
+#[derive(thiserror::Error, Debug)]
+pub enum EventStreamErrorReason {
+    #[error("stream disconnected")]
+    Disconnected,
+    #[error("cannot parse event: {0}")]
+    Parse(serde_json::Error),
+    #[error("unexpected response status {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("unexpected content type '{0}'")]
+    UnexpectedContentType(String),
+}
+
+/// An event stream error not yet tied to the address of the stream, which
+/// [`WebClient::event_stream`](crate::web::WebClient::event_stream) fills in.
+impl From<EventStreamErrorReason> for Error {
+    fn from(reason: EventStreamErrorReason) -> Self {
+        Error::event_stream(String::new(), reason)
+    }
 }
 
 impl From<PayloadError> for Error {
@@ -77,26 +171,159 @@ impl Error {
     pub(crate) fn from_request(err: SendRequestError, method: Method, url: String) -> Self {
         let msg = err.to_string();
         match err {
-            SendRequestError::Timeout => Error::TimeoutError { msg, method, url },
-            _ => Error::SendRequestError { msg, method, url },
+            SendRequestError::Timeout => Error::TimeoutError {
+                msg,
+                method,
+                url,
+                fingerprint: None,
+            },
+            _ => Error::SendRequestError {
+                msg,
+                method,
+                url,
+                fingerprint: None,
+            },
         }
     }
 
     pub(crate) fn from_response(
         code: StatusCode,
         msg: String,
+        body: Option<String>,
         method: Method,
         url: String,
     ) -> Self {
         if code == StatusCode::REQUEST_TIMEOUT {
-            Error::TimeoutError { msg, method, url }
+            Error::TimeoutError {
+                msg,
+                method,
+                url,
+                fingerprint: None,
+            }
         } else {
             Error::HttpError {
                 method,
                 url,
+                fingerprint: None,
                 code,
                 msg,
+                body,
+            }
+        }
+    }
+
+    pub fn internal(msg: impl Into<String>) -> Self {
+        Error::InternalError(msg.into())
+    }
+
+    pub fn event_stream(url: impl Into<String>, reason: EventStreamErrorReason) -> Self {
+        Error::EventStreamError {
+            url: url.into(),
+            reason,
+        }
+    }
+
+    /// Sets the address of an event stream error which has none yet.
+    pub(crate) fn at_stream(mut self, stream_url: &str) -> Self {
+        if let Error::EventStreamError { url, .. } = &mut self {
+            if url.is_empty() {
+                *url = stream_url.to_string();
             }
         }
+        self
+    }
+
+    /// Status of the response the request failed with, if it got one.
+    pub(crate) fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::HttpError { code, .. } | Error::RateLimited { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Marks a request error with the fingerprint of the failed request.
+    pub(crate) fn with_fingerprint(mut self, request: &str) -> Self {
+        if let Error::SendRequestError { fingerprint, .. }
+        | Error::TimeoutError { fingerprint, .. }
+        | Error::HttpError { fingerprint, .. }
+        | Error::RateLimited { fingerprint, .. } = &mut self
+        {
+            *fingerprint = Some(request.to_string());
+        }
+        self
+    }
+}
+
+/// Maximal number of characters of a response body shown by the `Display`
+/// of [`Error::HttpError`].
+const DISPLAY_BODY_MAX_LEN: usize = 256;
+
+fn retry_after_suffix(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(delay) => format!(", retry after {:?}", delay),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn fingerprint_suffix(fingerprint: &Option<String>) -> String {
+    match fingerprint {
+        Some(fingerprint) => format!(" [{}]", fingerprint),
+        None => String::new(),
+    }
+}
+
+/// The raw body of an [`Error::HttpError`] or [`Error::RateLimited`], unless
+/// it is already shown as its message.
+fn body_suffix(msg: &str, body: &Option<String>) -> String {
+    match body {
+        Some(body) if !body.is_empty() && body != msg => {
+            format!("; body: '{}'", truncate_body(body, DISPLAY_BODY_MAX_LEN))
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_stream_errors_are_readable() {
+        let url = "http://127.0.0.1:7465/activity-api/v1/activity/a/exec/b";
+        let parse = serde_json::from_str::<u32>("x").unwrap_err();
+        for (reason, expected) in [
+            (EventStreamErrorReason::Disconnected, "stream disconnected"),
+            (
+                EventStreamErrorReason::Parse(parse),
+                "cannot parse event: expected value at line 1 column 1",
+            ),
+            (
+                EventStreamErrorReason::UnexpectedStatus(StatusCode::NOT_FOUND),
+                "unexpected response status 404 Not Found",
+            ),
+        ] {
+            let err = Error::from(reason).at_stream(url);
+            assert_eq!(
+                err.to_string(),
+                format!("Event stream error at {}: {}", url, expected)
+            );
+        }
+        assert_eq!(
+            Error::event_stream(url, EventStreamErrorReason::Disconnected).to_string(),
+            format!("Event stream error at {}: stream disconnected", url)
+        );
+    }
+
+    #[test]
+    fn helper_constructors() {
+        assert_eq!(
+            Error::internal("no runtime").to_string(),
+            "Internal ya-client error: no runtime"
+        );
+        let err = Error::from(ErrorMessage::from("no such activity"));
+        assert_eq!(
+            err.to_string(),
+            "Yagna API error: Some(\"no such activity\")"
+        );
     }
 }
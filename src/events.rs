@@ -0,0 +1,866 @@
+//! Helpers for consuming Yagna event feeds.
+use actix_rt::time::{sleep, sleep_until, Instant, Sleep};
+use chrono::{DateTime, Utc};
+use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
+use futures::{stream, Future, FutureExt, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::model::activity::ProviderEvent as ActivityProviderEvent;
+use crate::model::market::{AgreementOperationEvent, ProviderEvent, RequestorEvent};
+use crate::model::payment::{DebitNoteEvent, InvoiceEvent};
+use crate::web::WebClient;
+use crate::Result;
+
+/// Pause of an [`EventCollector`] between polls bringing events, doubled
+/// after every idle one.
+pub(crate) const COLLECTOR_MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pause of a non-adaptive [`EventPoller`] after a failed poll, doubled
+/// after every further one.
+const ERROR_MIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Feed a [`YagnaEvent`] originates from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventSource {
+    Invoice,
+    DebitNote,
+    Agreement,
+}
+
+/// Event of any of the merged feeds, tagged by its source.
+#[derive(Debug)]
+pub enum YagnaEvent {
+    Invoice(InvoiceEvent),
+    DebitNote(DebitNoteEvent),
+    Agreement(AgreementOperationEvent),
+}
+
+impl YagnaEvent {
+    pub fn source(&self) -> EventSource {
+        match self {
+            YagnaEvent::Invoice(_) => EventSource::Invoice,
+            YagnaEvent::DebitNote(_) => EventSource::DebitNote,
+            YagnaEvent::Agreement(_) => EventSource::Agreement,
+        }
+    }
+
+    pub fn event_date(&self) -> DateTime<Utc> {
+        match self {
+            YagnaEvent::Invoice(e) => e.event_date,
+            YagnaEvent::DebitNote(e) => e.event_date,
+            YagnaEvent::Agreement(e) => e.event_date,
+        }
+    }
+}
+
+/// Position in an event feed: the date of the latest event already seen,
+/// to be passed as `after_timestamp` of the next poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventCursor(pub DateTime<Utc>);
+
+pub trait HasEventDate {
+    fn event_date(&self) -> DateTime<Utc>;
+}
+
+impl HasEventDate for InvoiceEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        self.event_date
+    }
+}
+
+impl HasEventDate for DebitNoteEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        self.event_date
+    }
+}
+
+impl HasEventDate for AgreementOperationEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        self.event_date
+    }
+}
+
+impl HasEventDate for ActivityProviderEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        self.event_date
+    }
+}
+
+impl HasEventDate for ProviderEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        match self {
+            ProviderEvent::ProposalEvent { event_date, .. }
+            | ProviderEvent::ProposalRejectedEvent { event_date, .. }
+            | ProviderEvent::AgreementEvent { event_date, .. }
+            | ProviderEvent::PropertyQueryEvent { event_date, .. } => *event_date,
+        }
+    }
+}
+
+impl HasEventDate for RequestorEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        match self {
+            RequestorEvent::ProposalEvent { event_date, .. }
+            | RequestorEvent::ProposalRejectedEvent { event_date, .. }
+            | RequestorEvent::PropertyQueryEvent { event_date, .. } => *event_date,
+        }
+    }
+}
+
+impl HasEventDate for YagnaEvent {
+    fn event_date(&self) -> DateTime<Utc> {
+        YagnaEvent::event_date(self)
+    }
+}
+
+/// Cursor to poll for events following the given page.
+///
+/// Events within a page are not guaranteed to be sorted, so the latest date
+/// of the whole page is taken. The cursor never moves backwards: an empty
+/// page, or one with older events only, leaves `prev` unchanged.
+pub fn next_cursor<T: HasEventDate>(page: &[T], prev: Option<EventCursor>) -> Option<EventCursor> {
+    let latest = page.iter().map(|e| EventCursor(e.event_date())).max();
+    latest.max(prev)
+}
+
+/// Pause between polls adapting to the observed activity: it doubles after
+/// every idle poll, up to `cap`, and halves after every poll which brought
+/// some events, down to `floor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveInterval {
+    floor: Duration,
+    cap: Duration,
+    current: Duration,
+}
+
+impl AdaptiveInterval {
+    /// Starts at `floor`, ie. assuming some activity.
+    pub fn new(floor: Duration, cap: Duration) -> Self {
+        let cap = cap.max(floor);
+        AdaptiveInterval {
+            floor,
+            cap,
+            current: floor,
+        }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    pub fn on_idle(&mut self) {
+        self.current = (self.current * 2).max(self.floor).min(self.cap);
+    }
+
+    pub fn on_events(&mut self) {
+        self.current = (self.current / 2).max(self.floor);
+    }
+}
+
+/// Repeatedly long-polls an event feed, keeping track of the [`EventCursor`].
+///
+/// `fetch` is given the cursor and the long-poll timeout, eg.
+///
+/// ```ignore
+/// |cursor, timeout| {
+///     api.get_invoice_events(cursor.map(|c| c.0).as_ref(), Some(timeout), None, None)
+/// }
+/// ```
+///
+/// By default the next poll is sent right away; see [`adaptive`](Self::adaptive)
+/// for pausing in between.
+pub struct EventPoller<T, F> {
+    fetch: F,
+    timeout: Duration,
+    cursor: Option<EventCursor>,
+    interval: Option<AdaptiveInterval>,
+    polled: bool,
+    error_backoff: Option<Duration>,
+    events: PhantomData<T>,
+}
+
+impl<T, F, Fut> EventPoller<T, F>
+where
+    T: HasEventDate,
+    F: FnMut(Option<EventCursor>, Duration) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    pub fn new(timeout: Duration, fetch: F) -> Self {
+        EventPoller {
+            fetch,
+            timeout,
+            cursor: None,
+            interval: None,
+            polled: false,
+            error_backoff: None,
+            events: PhantomData,
+        }
+    }
+
+    /// Starts polling after the given cursor instead of from the beginning.
+    pub fn after(mut self, cursor: EventCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Pauses between polls with an [`AdaptiveInterval`] ranging from `floor`
+    /// to the long-poll timeout, which is how long an idle poll takes anyway.
+    pub fn adaptive(mut self, floor: Duration) -> Self {
+        self.interval = Some(AdaptiveInterval::new(floor, self.timeout));
+        self
+    }
+
+    pub fn cursor(&self) -> Option<EventCursor> {
+        self.cursor
+    }
+
+    /// Current pause between polls, `None` unless [`adaptive`](Self::adaptive).
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval.as_ref().map(AdaptiveInterval::current)
+    }
+
+    /// Waits for the current interval (except before the first poll) and
+    /// fetches the next page of events.
+    ///
+    /// A failed poll leaves the cursor unchanged and backs off: an adaptive
+    /// interval grows as after an idle poll, otherwise the next poll waits
+    /// 100ms, doubled after every further failure up to the timeout.
+    pub async fn poll(&mut self) -> Result<Vec<T>> {
+        let pause = match self.interval {
+            Some(interval) if self.polled => Some(interval.current()),
+            _ => self.error_backoff,
+        };
+        if let Some(pause) = pause {
+            sleep(pause).await;
+        }
+        self.polled = true;
+        let page = match (self.fetch)(self.cursor, self.timeout).await {
+            Ok(page) => page,
+            Err(e) => {
+                match self.interval.as_mut() {
+                    Some(interval) => interval.on_idle(),
+                    None => {
+                        let backoff = self.error_backoff.map_or(ERROR_MIN_BACKOFF, |b| b * 2);
+                        self.error_backoff = Some(backoff.min(self.timeout.max(ERROR_MIN_BACKOFF)));
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.error_backoff = None;
+        self.cursor = next_cursor(&page, self.cursor);
+        if let Some(interval) = self.interval.as_mut() {
+            if page.is_empty() {
+                interval.on_idle();
+            } else {
+                interval.on_events();
+            }
+        }
+        Ok(page)
+    }
+
+    /// Polls for as long as the stream is consumed, yielding the events one
+    /// by one. A failed poll is yielded as an error, after which polling
+    /// goes on from the same cursor, backing off as [`poll`](Self::poll) does.
+    pub fn into_stream(self) -> impl Stream<Item = Result<T>> {
+        stream::unfold(self, |mut poller| async move {
+            let page = poller.poll().await;
+            Some((page, poller))
+        })
+        .flat_map(|page| match page {
+            Ok(events) => stream::iter(events.into_iter().map(Ok).collect::<Vec<_>>()),
+            Err(e) => stream::iter(vec![Err(e)]),
+        })
+    }
+}
+
+type WebFetch<T> =
+    Box<dyn FnMut(Option<EventCursor>, Duration) -> LocalBoxFuture<'static, Result<Vec<T>>>>;
+
+/// [`EventPoller`] of an endpoint of a [`WebClient`], see
+/// [`EventCollector::with_client`].
+pub type EventCollector<T> = EventPoller<T, WebFetch<T>>;
+
+impl<T> EventCollector<T>
+where
+    T: DeserializeOwned + HasEventDate + 'static,
+{
+    /// Long-polls `client` at the url built from the cursor and the timeout,
+    /// eg. with `afterTimestamp` and `timeout` query parameters.
+    ///
+    /// A poll timing out yields no events rather than an error, as with
+    /// [`WebRequest::json_events`](crate::web::WebRequest::json_events).
+    /// Polls are [`adaptive`](EventPoller::adaptive), starting at 100ms.
+    pub fn with_client<U>(client: WebClient, timeout: Duration, url: U) -> Self
+    where
+        U: Fn(Option<EventCursor>, Duration) -> String + 'static,
+    {
+        let fetch: WebFetch<T> = Box::new(move |cursor, timeout| {
+            let request = client.get(&url(cursor, timeout)).long_poll().send();
+            request.json_events().boxed_local()
+        });
+        EventPoller::new(timeout, fetch).adaptive(COLLECTOR_MIN_INTERVAL)
+    }
+}
+
+/// Stream adapter dropping items whose key has been seen recently.
+///
+/// Only the last `capacity` keys are remembered, the oldest being forgotten
+/// first. Items without a key (eg. errors) are always passed through.
+pub struct Dedup<S, K, F> {
+    stream: S,
+    key: F,
+    capacity: usize,
+    order: VecDeque<K>,
+    seen: HashSet<K>,
+}
+
+impl<S, K, F> Dedup<S, K, F>
+where
+    S: Stream + Unpin,
+    K: Clone + Eq + Hash,
+    F: FnMut(&S::Item) -> Option<K>,
+{
+    pub fn new(stream: S, capacity: usize, key: F) -> Self {
+        Dedup {
+            stream,
+            key,
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            seen: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Records the key, returning whether it is new.
+    fn remember(&mut self, key: K) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.seen.contains(&key) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.seen.insert(key);
+        true
+    }
+}
+
+impl<S, K, F> Stream for Dedup<S, K, F>
+where
+    S: Stream + Unpin,
+    K: Clone + Eq + Hash + Unpin,
+    F: FnMut(&S::Item) -> Option<K> + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let item = match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => item,
+                other => return other,
+            };
+            let is_new = match (self.key)(&item) {
+                Some(key) => self.remember(key),
+                None => true,
+            };
+            if is_new {
+                return Poll::Ready(Some(item));
+            }
+        }
+    }
+}
+
+/// Drops `ProposalEvent`s repeating one of the last `capacity` proposal ids.
+pub fn dedup_proposals<S>(
+    events: S,
+    capacity: usize,
+) -> Dedup<S, String, impl FnMut(&Result<RequestorEvent>) -> Option<String>>
+where
+    S: Stream<Item = Result<RequestorEvent>> + Unpin,
+{
+    Dedup::new(events, capacity, |event| match event {
+        Ok(RequestorEvent::ProposalEvent { proposal, .. }) => Some(proposal.proposal_id.clone()),
+        _ => None,
+    })
+}
+
+/// Item of an [`IdleMarkers`] stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamStatus<T> {
+    /// Item of the underlying stream.
+    Event(T),
+    /// Nothing has arrived for an interval, though the stream is still live.
+    Idle {
+        /// When the last event arrived, or the stream was created.
+        since: DateTime<Utc>,
+    },
+}
+
+impl<T> StreamStatus<T> {
+    /// The event, `None` for an idle marker, eg. to skip markers with
+    /// `filter_map`.
+    pub fn into_event(self) -> Option<T> {
+        match self {
+            StreamStatus::Event(event) => Some(event),
+            StreamStatus::Idle { .. } => None,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        matches!(self, StreamStatus::Idle { .. })
+    }
+}
+
+/// Stream adapter interleaving events with a [`StreamStatus::Idle`] marker
+/// for every `interval` passed without one, telling a quiet stream from a
+/// stuck consumer.
+pub struct IdleMarkers<S> {
+    stream: S,
+    interval: Duration,
+    timer: Pin<Box<Sleep>>,
+    since: DateTime<Utc>,
+}
+
+impl<S: Stream + Unpin> IdleMarkers<S> {
+    pub fn new(stream: S, interval: Duration) -> Self {
+        IdleMarkers {
+            stream,
+            interval,
+            timer: Box::pin(sleep(interval)),
+            since: Utc::now(),
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for IdleMarkers<S> {
+    type Item = StreamStatus<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match this.stream.poll_next_unpin(cx) {
+            Poll::Ready(Some(item)) => {
+                this.since = Utc::now();
+                this.timer.as_mut().reset(Instant::now() + this.interval);
+                Poll::Ready(Some(StreamStatus::Event(item)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                futures::ready!(this.timer.as_mut().poll(cx));
+                this.timer.as_mut().reset(Instant::now() + this.interval);
+                Poll::Ready(Some(StreamStatus::Idle { since: this.since }))
+            }
+        }
+    }
+}
+
+struct Feed {
+    stream: LocalBoxStream<'static, Result<YagnaEvent>>,
+    /// At most a single event is taken from a feed ahead of time, so a busy
+    /// feed is throttled by the consumer instead of being buffered.
+    head: Option<(Instant, YagnaEvent)>,
+    done: bool,
+}
+
+/// Merges invoice, debit note and agreement event feeds into a single stream.
+///
+/// Events are yielded in `event_date` order as long as they arrive within
+/// `window` of each other: an event is held back until either every other
+/// live feed has an event ready to compare with, or it has waited `window`.
+/// All the feeds are polled on every turn, so none of them gets starved.
+///
+/// Errors are passed through as soon as they are received.
+pub struct MergedEventStream {
+    feeds: Vec<Feed>,
+    window: Duration,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl MergedEventStream {
+    pub fn new(window: Duration) -> Self {
+        MergedEventStream {
+            feeds: Vec::new(),
+            window,
+            timer: None,
+        }
+    }
+
+    pub fn invoices<S>(self, events: S) -> Self
+    where
+        S: Stream<Item = Result<InvoiceEvent>> + 'static,
+    {
+        self.feed(events.map(|e| e.map(YagnaEvent::Invoice)))
+    }
+
+    pub fn debit_notes<S>(self, events: S) -> Self
+    where
+        S: Stream<Item = Result<DebitNoteEvent>> + 'static,
+    {
+        self.feed(events.map(|e| e.map(YagnaEvent::DebitNote)))
+    }
+
+    pub fn agreements<S>(self, events: S) -> Self
+    where
+        S: Stream<Item = Result<AgreementOperationEvent>> + 'static,
+    {
+        self.feed(events.map(|e| e.map(YagnaEvent::Agreement)))
+    }
+
+    fn feed<S>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<YagnaEvent>> + 'static,
+    {
+        self.feeds.push(Feed {
+            stream: stream.boxed_local(),
+            head: None,
+            done: false,
+        });
+        self
+    }
+}
+
+impl Stream for MergedEventStream {
+    type Item = Result<YagnaEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        for feed in this.feeds.iter_mut() {
+            if feed.head.is_some() || feed.done {
+                continue;
+            }
+            match feed.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(event))) => feed.head = Some((Instant::now(), event)),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => feed.done = true,
+                Poll::Pending => (),
+            }
+        }
+
+        let earliest = this
+            .feeds
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, feed)| feed.head.as_ref().map(|(_, e)| (idx, e.event_date())))
+            .min_by_key(|(_, date)| *date)
+            .map(|(idx, _)| idx);
+        let earliest = match earliest {
+            Some(idx) => idx,
+            None if this.feeds.iter().all(|feed| feed.done) => return Poll::Ready(None),
+            None => return Poll::Pending,
+        };
+
+        let all_ready = this
+            .feeds
+            .iter()
+            .all(|feed| feed.done || feed.head.is_some());
+        let deadline = this
+            .feeds
+            .iter()
+            .filter_map(|feed| feed.head.as_ref().map(|(at, _)| *at + this.window))
+            .min()
+            .unwrap();
+
+        if !all_ready && deadline > Instant::now() {
+            let timer = this
+                .timer
+                .get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+            timer.as_mut().reset(deadline);
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        this.timer = None;
+        let (_, event) = this.feeds[earliest].head.take().unwrap();
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::payment::{DebitNoteEventType, InvoiceEventType};
+    use chrono::TimeZone;
+    use futures::stream;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    fn invoice(secs: i64) -> Result<InvoiceEvent> {
+        Ok(InvoiceEvent {
+            invoice_id: format!("invoice-{}", secs),
+            event_date: at(secs),
+            event_type: InvoiceEventType::InvoiceReceivedEvent,
+        })
+    }
+
+    fn debit_note(secs: i64) -> Result<DebitNoteEvent> {
+        Ok(DebitNoteEvent {
+            debit_note_id: format!("debit-note-{}", secs),
+            event_date: at(secs),
+            event_type: DebitNoteEventType::DebitNoteReceivedEvent,
+        })
+    }
+
+    #[test]
+    fn next_cursor_takes_latest_event() {
+        let page: Vec<_> = (1..=3).map(|secs| invoice(secs).unwrap()).collect();
+        assert_eq!(next_cursor(&page, None), Some(EventCursor(at(3))));
+        assert_eq!(
+            next_cursor(&page, Some(EventCursor(at(0)))),
+            Some(EventCursor(at(3)))
+        );
+    }
+
+    #[test]
+    fn next_cursor_keeps_previous_on_empty_page() {
+        let page: Vec<InvoiceEvent> = Vec::new();
+        assert_eq!(next_cursor(&page, None), None);
+        assert_eq!(
+            next_cursor(&page, Some(EventCursor(at(7)))),
+            Some(EventCursor(at(7)))
+        );
+    }
+
+    #[test]
+    fn next_cursor_handles_out_of_order_events() {
+        let page: Vec<_> = [5, 9, 2]
+            .into_iter()
+            .map(|secs| debit_note(secs).unwrap())
+            .collect();
+        assert_eq!(
+            next_cursor(&page, Some(EventCursor(at(1)))),
+            Some(EventCursor(at(9)))
+        );
+        assert_eq!(
+            next_cursor(&page, Some(EventCursor(at(10)))),
+            Some(EventCursor(at(10)))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn adaptive_interval_follows_activity() {
+        let pages = std::cell::RefCell::new(vec![
+            vec![invoice(4).unwrap()],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        ]);
+        let mut poller = EventPoller::new(Duration::from_millis(8), |_, timeout| {
+            assert_eq!(timeout, Duration::from_millis(8));
+            futures::future::ready(Ok(pages.borrow_mut().pop().unwrap()))
+        })
+        .adaptive(Duration::from_millis(1));
+        assert_eq!(poller.interval(), Some(Duration::from_millis(1)));
+
+        let mut intervals = Vec::new();
+        for _ in 0..5 {
+            poller.poll().await.unwrap();
+            intervals.push(poller.interval().unwrap().as_millis());
+        }
+        assert_eq!(intervals, vec![2, 4, 8, 8, 4]);
+        assert_eq!(poller.cursor(), Some(EventCursor(at(4))));
+    }
+
+    #[actix_rt::test]
+    async fn failed_poll_backs_off() {
+        let fetches = std::cell::Cell::new(0);
+        let fetch = |_, _| {
+            fetches.set(fetches.get() + 1);
+            let page = match fetches.get() {
+                1 | 2 => Err(crate::Error::internal("unavailable")),
+                _ => Ok(vec![invoice(1).unwrap()]),
+            };
+            futures::future::ready(page)
+        };
+        let mut poller = EventPoller::new(Duration::from_secs(1), fetch);
+
+        let started = Instant::now();
+        let results: Vec<_> = poller.into_stream().take(3).collect().await;
+        assert!(results[0].is_err() && results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().invoice_id, "invoice-1");
+        // 100ms after the first failure, 200ms after the second one
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(fetches.get(), 3);
+
+        fetches.set(0);
+        poller =
+            EventPoller::new(Duration::from_millis(8), fetch).adaptive(Duration::from_millis(1));
+        poller.poll().await.unwrap_err();
+        assert_eq!(poller.interval(), Some(Duration::from_millis(2)));
+    }
+
+    #[actix_rt::test]
+    async fn collector_advances_cursor() {
+        use crate::web::mock::{MockResponse, MockServer};
+        use crate::web::QueryParamsBuilder;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let event = |secs| {
+            serde_json::json!({
+                "invoiceId": format!("invoice-{}", secs),
+                "eventDate": at(secs),
+                "eventType": "InvoiceReceivedEvent",
+            })
+        };
+        let polls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => MockResponse::json(serde_json::json!([event(1), event(2)])),
+            1 => MockResponse::new(408),
+            _ => MockResponse::json(serde_json::json!([event(3)])),
+        });
+        let mut collector = EventCollector::<InvoiceEvent>::with_client(
+            server.client(),
+            Duration::from_secs(5),
+            |cursor, timeout| {
+                let query = QueryParamsBuilder::default()
+                    .put_timestamp("afterTimestamp", cursor.map(|c| c.0))
+                    .put("timeout", Some(timeout.as_secs()))
+                    .build();
+                format!("invoiceEvents?{}", query)
+            },
+        )
+        .adaptive(Duration::from_millis(1));
+
+        assert_eq!(collector.poll().await.unwrap().len(), 2);
+        assert_eq!(collector.cursor(), Some(EventCursor(at(2))));
+        assert!(collector.poll().await.unwrap().is_empty());
+        assert_eq!(collector.interval(), Some(Duration::from_millis(2)));
+        let events: Vec<_> = collector.into_stream().take(1).collect().await;
+        assert_eq!(events[0].as_ref().unwrap().invoice_id, "invoice-3");
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        let after_2 = "/invoiceEvents?afterTimestamp=2020-09-13T12%3A26%3A42Z&timeout=5";
+        assert_eq!(paths, ["/invoiceEvents?timeout=5", after_2, after_2]);
+    }
+
+    fn proposal_event(id: &str) -> Result<RequestorEvent> {
+        let proposal = serde_json::from_value(serde_json::json!({
+            "properties": {},
+            "constraints": "()",
+            "proposalId": id,
+            "issuerId": "0xbabe000000000000000000000000000000000000",
+            "state": "Initial",
+            "timestamp": "2022-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        Ok(RequestorEvent::ProposalEvent {
+            event_date: at(0),
+            proposal,
+        })
+    }
+
+    fn proposal_ids(events: Vec<Result<RequestorEvent>>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|event| match event.unwrap() {
+                RequestorEvent::ProposalEvent { proposal, .. } => proposal.proposal_id,
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn dedup_drops_repeated_proposal() {
+        let events = stream::iter(vec![
+            proposal_event("p1"),
+            proposal_event("p2"),
+            proposal_event("p1"),
+            proposal_event("p3"),
+        ]);
+
+        let events: Vec<_> = dedup_proposals(events, 8).collect().await;
+        assert_eq!(proposal_ids(events), vec!["p1", "p2", "p3"]);
+    }
+
+    #[actix_rt::test]
+    async fn dedup_forgets_oldest_ids() {
+        let events = stream::iter(vec![
+            proposal_event("p1"),
+            proposal_event("p2"),
+            proposal_event("p3"),
+            proposal_event("p1"),
+            proposal_event("p3"),
+        ]);
+
+        let events: Vec<_> = dedup_proposals(events, 2).collect().await;
+        assert_eq!(proposal_ids(events), vec!["p1", "p2", "p3", "p1"]);
+    }
+
+    #[actix_rt::test]
+    async fn merges_feeds_in_timestamp_order() {
+        let merged = MergedEventStream::new(Duration::from_millis(50))
+            .invoices(stream::iter(vec![invoice(1), invoice(4), invoice(5)]))
+            .debit_notes(stream::iter(vec![
+                debit_note(2),
+                debit_note(3),
+                debit_note(6),
+            ]));
+
+        let events: Vec<_> = merged.map(|e| e.unwrap()).collect().await;
+        let order: Vec<_> = events
+            .iter()
+            .map(|e| (e.source(), e.event_date()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (EventSource::Invoice, at(1)),
+                (EventSource::DebitNote, at(2)),
+                (EventSource::DebitNote, at(3)),
+                (EventSource::Invoice, at(4)),
+                (EventSource::Invoice, at(5)),
+                (EventSource::DebitNote, at(6)),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn idle_markers_fill_quiet_periods() {
+        let quiet = stream::once(sleep(Duration::from_millis(110))).filter_map(|()| async { None });
+        let events = stream::iter([1]).chain(quiet).chain(stream::iter([2, 3]));
+
+        let statuses: Vec<_> = IdleMarkers::new(Box::pin(events), Duration::from_millis(30))
+            .collect()
+            .await;
+
+        // markers only while quiet, all since the first event
+        let markers = statuses.iter().filter(|s| s.is_idle()).count();
+        assert!((2..=4).contains(&markers), "{:?}", statuses);
+        assert_eq!(statuses[0], StreamStatus::Event(1));
+        assert!(statuses[1..=markers].iter().all(|s| *s == statuses[1]));
+        assert_eq!(
+            statuses[markers + 1..],
+            [StreamStatus::Event(2), StreamStatus::Event(3)]
+        );
+        let events: Vec<_> = statuses
+            .into_iter()
+            .filter_map(StreamStatus::into_event)
+            .collect();
+        assert_eq!(events, [1, 2, 3]);
+    }
+
+    #[actix_rt::test]
+    async fn silent_feed_does_not_block_others() {
+        let merged = MergedEventStream::new(Duration::from_millis(20))
+            .invoices(stream::iter(vec![invoice(1), invoice(2)]))
+            .debit_notes(stream::pending());
+
+        let events: Vec<_> = merged.take(2).map(|e| e.unwrap()).collect().await;
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.source() == EventSource::Invoice));
+    }
+}
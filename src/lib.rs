@@ -4,6 +4,7 @@
 pub mod web;
 
 pub mod activity;
+pub mod events;
 pub mod market;
 pub mod net;
 pub mod payment;
@@ -4,5 +4,6 @@ mod requestor;
 
 pub use provider::MarketProviderApi;
 pub use requestor::MarketRequestorApi;
+pub use ya_client_model::market::{matching_report, MatchReport};
 
 pub(crate) const MARKET_URL_ENV_VAR: &str = "YAGNA_MARKET_URL";
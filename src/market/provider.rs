@@ -4,7 +4,8 @@ use ya_client_model::market::{
     NewProposal, Offer, Proposal, ProviderEvent, Reason, MARKET_API_PATH,
 };
 
-use crate::{web::default_on_timeout, web::WebClient, web::WebInterface, Result};
+use crate::{web::WebClient, web::WebInterface, Error, Result};
+use awc::http::StatusCode;
 use chrono::{DateTime, TimeZone, Utc};
 use std::fmt::Display;
 
@@ -56,7 +57,7 @@ impl MarketProviderApi {
     /// Returns Proposal related events:
     ///
     /// * `ProposalEvent` - Indicates that there is new Demand Proposal for
-    /// this Offer.
+    ///   this Offer.
     ///
     /// * `ProposalRejectedEvent` - Indicates that the Requestor has rejected
     ///   our previous Proposal related to this Offer. This effectively ends a
@@ -88,7 +89,7 @@ impl MarketProviderApi {
             #[query] max_events,
         );
 
-        self.client.get(&url).send().json().await.or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 
     /// Fetches Proposal (Demand) with given id.
@@ -97,6 +98,31 @@ impl MarketProviderApi {
         self.client.get(&url).send().json().await
     }
 
+    /// Same as [`get_proposal`](#method.get_proposal), but a Proposal which
+    /// does not exist yields `None`, while one which has already expired
+    /// fails with [`Error::ProposalExpired`].
+    pub async fn find_proposal(
+        &self,
+        subscription_id: &str,
+        proposal_id: &str,
+    ) -> Result<Option<Proposal>> {
+        let url = url_format!("offers/{subscription_id}/proposals/{proposal_id}");
+        self.client
+            .get(&url)
+            .send()
+            .json_found()
+            .await
+            .map_err(|e| match e {
+                Error::HttpError {
+                    code: StatusCode::GONE,
+                    ..
+                } => Error::ProposalExpired {
+                    proposal_id: proposal_id.to_string(),
+                },
+                e => e,
+            })
+    }
+
     /// Rejects Proposal (Demand).
     ///
     /// Effectively ends a Negotiation chain - it explicitly indicates that
@@ -138,15 +164,16 @@ impl MarketProviderApi {
     /// It returns one of the following options:
     ///
     /// * `Ok` Agreement approved. Indicates that the approved Agreement has been
-    ///  successfully delivered to the Requestor and acknowledged.
+    ///   successfully delivered to the Requestor and acknowledged.
     ///    - The Requestor side has been notified about the Provider’s commitment.
     ///    - The Provider is now ready to accept a request to start an Activity.
     ///    - The Requestor’s corresponding `wait_for_approval` call returns `Ok`
-    ///    (Approved) **after** this endpoint on the Provider side.
+    ///      (Approved) **after** this endpoint on the Provider side.
     ///
     /// * `Err` - Indicates that Agreement is not approved.
     ///   - `408` Agreement not approved within given timeout. Try again.
     ///   - `410` Agreement approval failed permanently.
+    ///
     /// Attached `ErrorMessage` contains further details:
     ///   - `Rejected` - Indicates that the Provider himself has already
     ///     called `reject_agreement`.
@@ -274,6 +301,6 @@ impl MarketProviderApi {
             #[query] max_events,
             #[query] app_session_id,
         );
-        self.client.get(&url).send().json().await.or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 }
@@ -1,12 +1,14 @@
 //! Requestor part of the Market API
 use ya_client_model::market::{
     agreement::State, Agreement, AgreementListEntry, AgreementOperationEvent, AgreementProposal,
-    Demand, NewDemand, NewProposal, Proposal, Reason, RequestorEvent,
+    Demand, NewDemand, NewProposal, Proposal, Reason, RequestorEvent, SubscriptionId,
 };
 
-use crate::{web::default_on_timeout, web::WebClient, web::WebInterface, Result};
+use crate::{web::WebClient, web::WebInterface, Error, Result};
+use awc::http::StatusCode;
 use chrono::{DateTime, TimeZone, Utc};
 use std::fmt::Display;
+use std::time::{Duration, Instant};
 
 /// Bindings for Requestor part of the Market API.
 #[derive(Clone)]
@@ -36,6 +38,23 @@ impl MarketRequestorApi {
         self.client.post("demands").send_json(&demand).json().await
     }
 
+    /// Publishes the Demand set to expire `ttl` from now.
+    ///
+    /// Overrides the `golem.srv.comp.expiration` property, if already present.
+    pub async fn subscribe_for(
+        &self,
+        mut demand: NewDemand,
+        ttl: Duration,
+    ) -> Result<SubscriptionId> {
+        if ttl.is_zero() {
+            return Err(Error::InvalidArgument("demand ttl must be positive".into()));
+        }
+        let ttl = chrono::Duration::from_std(ttl)
+            .map_err(|e| Error::InvalidArgument(format!("demand ttl out of range: {}", e)))?;
+        demand.set_expiration(Utc::now() + ttl);
+        Ok(self.subscribe(&demand).await?.into())
+    }
+
     /// Fetches all active Demands which have been published by the Requestor.
     pub async fn get_demands(&self) -> Result<Vec<Demand>> {
         self.client.get("demands").send().json().await
@@ -57,12 +76,12 @@ impl MarketRequestorApi {
     /// Returns Proposal related events:
     ///
     /// * `ProposalEvent` - Indicates that there is new Offer Proposal for
-    /// this Demand.
+    ///   this Demand.
     ///
     /// * `ProposalRejectedEvent` - Indicates that the Provider has rejected
-    /// our previous Proposal related to this Demand. This effectively ends a
-    /// Negotiation chain - it explicitly indicates that the sender will not
-    /// create another counter-Proposal.
+    ///   our previous Proposal related to this Demand. This effectively ends a
+    ///   Negotiation chain - it explicitly indicates that the sender will not
+    ///   create another counter-Proposal.
     ///
     /// * `PropertyQueryEvent` - not supported yet.
     ///
@@ -85,7 +104,45 @@ impl MarketRequestorApi {
             #[query] timeout,
             #[query] max_events,
         );
-        self.client.get(&url).send().json().await.or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
+    }
+
+    /// Publishes the Demand, collects Proposals for `ttl` and unsubscribes.
+    ///
+    /// The Demand is unsubscribed also when collecting fails, in which case
+    /// the collection error is returned and a failed unsubscribe only logged.
+    pub async fn collect_once(&self, demand: NewDemand, ttl: Duration) -> Result<Vec<Proposal>> {
+        let subscription_id = self.subscribe(&demand).await?;
+        let deadline = Instant::now() + ttl;
+        let collected = async {
+            let mut proposals = Vec::new();
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                if remaining.is_zero() {
+                    break;
+                }
+                let events = self
+                    .collect(&subscription_id, Some(remaining.as_secs_f32()), None)
+                    .await?;
+                proposals.extend(events.into_iter().filter_map(|event| match event {
+                    RequestorEvent::ProposalEvent { proposal, .. } => Some(proposal),
+                    _ => None,
+                }));
+            }
+            Ok(proposals)
+        }
+        .await;
+        let unsubscribed = self.unsubscribe(&subscription_id).await;
+        match (collected, unsubscribed) {
+            (Err(e), Err(unsubscribe_err)) => {
+                log::warn!(
+                    "failed to unsubscribe demand {}: {}",
+                    subscription_id,
+                    unsubscribe_err
+                );
+                Err(e)
+            }
+            (collected, unsubscribed) => unsubscribed.and(collected),
+        }
     }
 
     /// Responds with a bespoke Demand to received Offer.
@@ -109,6 +166,31 @@ impl MarketRequestorApi {
         self.client.get(&url).send().json().await
     }
 
+    /// Same as [`get_proposal`](#method.get_proposal), but a Proposal which
+    /// does not exist yields `None`, while one which has already expired
+    /// fails with [`Error::ProposalExpired`].
+    pub async fn find_proposal(
+        &self,
+        subscription_id: &str,
+        proposal_id: &str,
+    ) -> Result<Option<Proposal>> {
+        let url = url_format!("demands/{subscription_id}/proposals/{proposal_id}");
+        self.client
+            .get(&url)
+            .send()
+            .json_found()
+            .await
+            .map_err(|e| match e {
+                Error::HttpError {
+                    code: StatusCode::GONE,
+                    ..
+                } => Error::ProposalExpired {
+                    proposal_id: proposal_id.to_string(),
+                },
+                e => e,
+            })
+    }
+
     /// Rejects Proposal (Offer)
     ///
     /// Effectively ends a Negotiation chain - it explicitly indicates that
@@ -203,9 +285,9 @@ impl MarketRequestorApi {
     /// It returns one of the following options:
     ///
     /// * `Ok` Agreement approved by the Provider.
-    ///  The Providers’s corresponding `approveAgreement` call returns `204`
-    ///  (Approved) **before** this endpoint on the Requestor side.
-    ///  The Provider is now ready to accept a request to start an Activity.
+    ///   The Providers’s corresponding `approveAgreement` call returns `204`
+    ///   (Approved) **before** this endpoint on the Requestor side.
+    ///   The Provider is now ready to accept a request to start an Activity.
     ///
     /// * `Err` - Indicates that Agreement is not approved.
     ///   - `408` Agreement not approved within given timeout. Try again.
@@ -214,14 +296,14 @@ impl MarketRequestorApi {
     ///
     /// Attached `ErrorMessage` contains further details:
     /// - `Rejected` - Indicates that the Provider has called
-    /// `rejectAgreement`, which effectively stops the Agreement handshake.
-    /// The Requestor may attempt to return to the Negotiation phase by
-    /// sending a new Proposal or to the Agreement phase by creating
-    /// new Agreement.
+    ///   `rejectAgreement`, which effectively stops the Agreement handshake.
+    ///   The Requestor may attempt to return to the Negotiation phase by
+    ///   sending a new Proposal or to the Agreement phase by creating
+    ///   new Agreement.
     /// - `Cancelled` - Indicates that the Requestor himself has called
-    /// `cancelAgreement`, which effectively stops the Agreement handshake.
+    ///   `cancelAgreement`, which effectively stops the Agreement handshake.
     /// - `Expired` - Indicates that Agreement validity period elapsed and it
-    /// was not approved, rejected nor cancelled.
+    ///   was not approved, rejected nor cancelled.
     /// - `Terminated` - Indicates that Agreement is already terminated.
     #[rustfmt::skip]
     pub async fn wait_for_approval(
@@ -306,6 +388,164 @@ impl MarketRequestorApi {
             #[query] max_events,
             #[query] app_session_id,
         );
-        self.client.get(&url).send().json().await.or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::{MockResponse, MockServer};
+    use serde_json::json;
+    use ya_client_model::market::EXPIRATION_PROPERTY;
+
+    fn proposal_server() -> MockServer {
+        MockServer::start(|req| match req.path.rsplit('/').next() {
+            Some("found") => MockResponse::json(json!({
+                "properties": {"golem.node.debug.subnet": "public"},
+                "constraints": "()",
+                "proposalId": "found",
+                "issuerId": "0xbabe000000000000000000000000000000000000",
+                "state": "Initial",
+                "timestamp": "2022-01-01T00:00:00Z",
+            })),
+            Some("expired") => MockResponse::new(410).body("proposal expired"),
+            _ => MockResponse::new(404).body("proposal not found"),
+        })
+    }
+
+    fn collect_server(
+        events: impl Fn(usize) -> MockResponse + Send + Sync + 'static,
+    ) -> MockServer {
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        MockServer::start(move |req| match req.method.as_str() {
+            "POST" => MockResponse::json(json!("demand-1")),
+            "DELETE" => MockResponse::new(204),
+            _ => events(polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst)),
+        })
+    }
+
+    fn assert_unsubscribed(server: &MockServer) {
+        let last = server.requests().pop().unwrap();
+        assert_eq!(last.method, "DELETE");
+        assert_eq!(last.path, "/demands/demand-1");
+    }
+
+    #[actix_rt::test]
+    async fn collect_once_gathers_proposals() {
+        let server = collect_server(|poll| match poll {
+            0 => MockResponse::json(json!([{
+                "eventType": "ProposalEvent",
+                "eventDate": "2022-01-01T00:00:00Z",
+                "proposal": {
+                    "properties": {},
+                    "constraints": "()",
+                    "proposalId": "proposal-1",
+                    "issuerId": "0xbabe000000000000000000000000000000000000",
+                    "state": "Initial",
+                    "timestamp": "2022-01-01T00:00:00Z",
+                },
+            }])),
+            _ => MockResponse::json(json!([])).delay(Duration::from_millis(150)),
+        });
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+
+        let proposals = api
+            .collect_once(
+                NewDemand::new(json!({}), "()".into()),
+                Duration::from_millis(100),
+            )
+            .await
+            .unwrap();
+        assert_eq!(proposals.len(), 1);
+        assert_eq!(proposals[0].proposal_id, "proposal-1");
+        assert_unsubscribed(&server);
+    }
+
+    #[actix_rt::test]
+    async fn collect_once_unsubscribes_on_error() {
+        let server = collect_server(|_| MockResponse::new(500).body("market down"));
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+
+        let result = api
+            .collect_once(
+                NewDemand::new(json!({}), "()".into()),
+                Duration::from_secs(5),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::HttpError { .. })));
+        assert_unsubscribed(&server);
+    }
+
+    #[actix_rt::test]
+    async fn find_proposal_found() {
+        let server = proposal_server();
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+
+        let proposal = api
+            .find_proposal("demand-1", "found")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(proposal.proposal_id, "found");
+        assert_eq!(proposal.properties["golem.node.debug.subnet"], "public");
+        assert_eq!(
+            server.requests()[0].path,
+            "/demands/demand-1/proposals/found"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn find_proposal_not_found() {
+        let server = proposal_server();
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+
+        assert!(api
+            .find_proposal("demand-1", "gone")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[actix_rt::test]
+    async fn find_proposal_expired() {
+        let server = proposal_server();
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+
+        match api.find_proposal("demand-1", "expired").await {
+            Err(Error::ProposalExpired { proposal_id }) => assert_eq!(proposal_id, "expired"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn subscribe_for_sets_expiration() {
+        let server = MockServer::start(|_| MockResponse::json(json!("demand-1")));
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+        let demand = NewDemand::new(json!({"golem.node.debug.subnet": "public"}), "()".into());
+
+        let before = Utc::now().timestamp_millis();
+        let id = api
+            .subscribe_for(demand, Duration::from_secs(600))
+            .await
+            .unwrap();
+        let after = Utc::now().timestamp_millis();
+
+        assert_eq!(id.as_str(), "demand-1");
+        let body = server.requests()[0].json();
+        let expiration = body["properties"][EXPIRATION_PROPERTY].as_i64().unwrap();
+        assert!(expiration >= before + 600_000 && expiration <= after + 600_000);
+        assert_eq!(body["properties"]["golem.node.debug.subnet"], "public");
+    }
+
+    #[actix_rt::test]
+    async fn subscribe_for_rejects_zero_ttl() {
+        let server = MockServer::start(|_| MockResponse::json(json!("demand-1")));
+        let api: MarketRequestorApi = server.client().interface_at(server.url()).unwrap();
+        let demand = NewDemand::new(json!({}), "()".into());
+
+        let result = api.subscribe_for(demand, Duration::ZERO).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert!(server.requests().is_empty());
     }
 }
@@ -124,11 +124,14 @@ impl NetVpnApi {
         let status = res.status();
         if status.is_success().not() && status.is_informational().not() {
             let body = res.body().limit(16384).await?;
+            let msg = String::from_utf8(body.to_vec())?;
             return Err(Error::HttpError {
                 code: status,
-                msg: String::from_utf8(body.to_vec())?,
+                body: Some(msg.clone()),
+                msg,
                 method: Method::GET,
                 url,
+                fingerprint: None,
             });
         }
 
@@ -1,6 +1,14 @@
 //! Payment part of the Yagna API
+pub mod amount;
 pub mod api;
+pub mod cost;
+pub mod plan;
+pub mod status;
 
+pub use amount::{amounts_equal, normalize_amount};
 pub use api::PaymentApi;
+pub use cost::{agreement_cost, AgreementCost};
+pub use plan::{plan_acceptances, AcceptOrder, AcceptancePlan};
+pub use status::{status_changes, StatusChange};
 
 pub(crate) const PAYMENT_URL_ENV_VAR: &str = "YAGNA_PAYMENT_URL";
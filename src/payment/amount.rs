@@ -0,0 +1,55 @@
+//! Canonical handling of money amounts.
+use bigdecimal::BigDecimal;
+
+/// Compares amounts numerically, regardless of their scale (`1.0 == 1.00`).
+pub fn amounts_equal(a: &BigDecimal, b: &BigDecimal) -> bool {
+    normalize_amount(a) == normalize_amount(b)
+}
+
+/// Canonical form of an amount, suitable for storing and hashing.
+///
+/// Trailing fractional zeros are stripped, while integers keep their
+/// zeros, so that the textual form never switches to exponent notation.
+pub fn normalize_amount(amount: &BigDecimal) -> BigDecimal {
+    let normalized = amount.normalized();
+    let (_, scale) = normalized.as_bigint_and_exponent();
+    if scale < 0 {
+        normalized.with_scale(0)
+    } else {
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn amount(v: &str) -> BigDecimal {
+        BigDecimal::from_str(v).unwrap()
+    }
+
+    #[test]
+    fn test_trailing_zeros() {
+        assert!(amounts_equal(&amount("1.0"), &amount("1.00")));
+        assert!(amounts_equal(&amount("0.000"), &amount("0")));
+        assert!(!amounts_equal(&amount("1.01"), &amount("1.10")));
+
+        assert_eq!(normalize_amount(&amount("1.2300")).to_string(), "1.23");
+        assert_eq!(normalize_amount(&amount("-0.50")).to_string(), "-0.5");
+        assert_eq!(normalize_amount(&amount("0.000")).to_string(), "0");
+    }
+
+    #[test]
+    fn test_scale_differences() {
+        assert!(amounts_equal(&amount("100"), &amount("1e2")));
+        assert!(amounts_equal(&amount("0.1"), &amount("1e-1")));
+
+        assert_eq!(normalize_amount(&amount("1e2")).to_string(), "100");
+        assert_eq!(normalize_amount(&amount("100.00")).to_string(), "100");
+        assert_eq!(
+            normalize_amount(&amount("2.50")),
+            normalize_amount(&amount("25e-1"))
+        );
+    }
+}
@@ -1,14 +1,18 @@
 //!  part of the Payment API
 use chrono::{DateTime, TimeZone, Utc};
+use futures::{Future, FutureExt, Stream};
 use std::borrow::Borrow;
 use std::fmt::Display;
 use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 
 use crate::{
-    web::{default_on_timeout, url_format_obj, WebClient, WebInterface},
-    Result,
+    events::{EventCursor, EventPoller, COLLECTOR_MIN_INTERVAL},
+    web::{url_format_obj, WebClient, WebInterface},
+    Error, Result,
 };
+use awc::http::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -75,9 +79,60 @@ impl PaymentApi {
         self.client.get("providerAccounts").send().json().await
     }
 
+    /// Picks the first of the `preferred` platforms the node has a requestor
+    /// account able to send payments on, to be used for a new allocation.
+    ///
+    /// Unset fields of a platform match any value and names are compared
+    /// case-insensitively. The returned platform has all of them filled in
+    /// from the account. Fails with [`Error::InvalidArgument`] listing the
+    /// available accounts if none matches.
+    pub async fn resolve_platform(
+        &self,
+        preferred: &[PaymentPlatform],
+    ) -> Result<(PaymentPlatform, Account)> {
+        let accounts = self.get_requestor_accounts().await?;
+        let found = preferred.iter().find_map(|platform| {
+            accounts
+                .iter()
+                .find(|account| account.send && platform_matches(platform, account))
+        });
+        match found {
+            Some(account) => Ok((
+                PaymentPlatform {
+                    driver: Some(account.driver.clone()),
+                    network: Some(account.network.clone()),
+                    token: Some(account.token.clone()),
+                },
+                account.clone(),
+            )),
+            None => Err(Error::InvalidArgument(format!(
+                "no account for any of the preferred platforms {:?}, sending accounts: [{}]",
+                preferred,
+                accounts
+                    .iter()
+                    .filter(|account| account.send)
+                    .map(|account| account.platform.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
     // allocations
 
+    /// Fails with [`Error::AllocationLimitExceeded`], without sending anything,
+    /// if the client has a lower [`max_allocation_amount`] set.
+    ///
+    /// [`max_allocation_amount`]: crate::web::WebClientBuilder::max_allocation_amount
     pub async fn create_allocation(&self, allocation: &NewAllocation) -> Result<Allocation> {
+        if let Some(limit) = self.client.max_allocation_amount() {
+            if &allocation.total_amount > limit {
+                return Err(Error::AllocationLimitExceeded {
+                    amount: allocation.total_amount.clone(),
+                    limit: limit.clone(),
+                });
+            }
+        }
         self.client
             .post("allocations")
             .send_json(allocation)
@@ -121,6 +176,122 @@ impl PaymentApi {
         self.client.delete(&url).send().json().await
     }
 
+    /// Releases the allocation, logging the reason together with its amounts.
+    ///
+    /// The API has no place for the reason, so it is only logged. An allocation
+    /// which does not exist (anymore) is considered already released.
+    pub async fn release_allocation_with_reason(
+        &self,
+        allocation_id: &str,
+        reason: &str,
+    ) -> Result<()> {
+        let allocation = match self.get_allocation(allocation_id).await {
+            Ok(allocation) => allocation,
+            Err(e) if is_not_found(&e) => {
+                log::info!("allocation {} already released", allocation_id);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        log::info!(
+            "releasing allocation {} (total: {}, spent: {}, remaining: {}): {}",
+            allocation_id,
+            allocation.total_amount,
+            allocation.spent_amount,
+            allocation.remaining_amount,
+            reason
+        );
+        match self.release_allocation(allocation_id).await {
+            Err(e) if is_not_found(&e) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Creates an allocation, runs `f` with it and releases it afterwards,
+    /// however `f` finishes.
+    ///
+    /// An error of `f` takes precedence over a failed release, which is then
+    /// only logged. If `f` panics, the release is still attempted (ignoring
+    /// its outcome) before the panic is resumed. Dropping the returned future
+    /// while `f` is running leaves the allocation in place.
+    pub async fn with_allocation<F, Fut, T>(&self, new: NewAllocation, f: F) -> Result<T>
+    where
+        F: FnOnce(Allocation) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let allocation = self.create_allocation(&new).await?;
+        let allocation_id = allocation.allocation_id.clone();
+        let outcome = AssertUnwindSafe(f(allocation)).catch_unwind().await;
+        let released = match self.release_allocation(&allocation_id).await {
+            Err(e) if is_not_found(&e) => Ok(()),
+            result => result,
+        };
+        match (outcome, released) {
+            (Err(panic), released) => {
+                if let Err(e) = released {
+                    log::warn!("failed to release allocation {}: {}", allocation_id, e);
+                }
+                panic::resume_unwind(panic)
+            }
+            (Ok(Err(e)), Err(release_err)) => {
+                log::warn!(
+                    "failed to release allocation {}: {}",
+                    allocation_id,
+                    release_err
+                );
+                Err(e)
+            }
+            (Ok(result), released) => released.and(result),
+        }
+    }
+
+    /// Checks whether the allocation, made from the given `account` address,
+    /// would be accepted, without reserving any funds.
+    pub async fn validate_allocation(
+        &self,
+        new: &NewAllocation,
+        account: &str,
+    ) -> Result<AllocationValidation> {
+        let allocation = NewAllocation {
+            address: Some(account.to_string()),
+            ..new.clone()
+        };
+        self.client
+            .post("allocations/validate")
+            .send_json(&allocation)
+            .json()
+            .await
+    }
+
+    /// Creates all the given allocations, one after another.
+    ///
+    /// If any of them fails, the ones already created are released (best-effort)
+    /// before the error is returned. This is not truly atomic: the server has no
+    /// batch endpoint, so a failed release leaves that allocation behind.
+    pub async fn create_allocations(&self, news: Vec<NewAllocation>) -> Result<Vec<Allocation>> {
+        let mut created = Vec::with_capacity(news.len());
+        for new in &news {
+            match self.create_allocation(new).await {
+                Ok(allocation) => created.push(allocation),
+                Err(e) => {
+                    for allocation in &created {
+                        if let Err(release_err) =
+                            self.release_allocation(&allocation.allocation_id).await
+                        {
+                            log::warn!(
+                                "failed to release allocation {}: {}",
+                                allocation.allocation_id,
+                                release_err
+                            );
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(created)
+    }
+
     #[rustfmt::skip]
     pub async fn get_demand_decorations(
         &self,
@@ -217,10 +388,40 @@ impl PaymentApi {
     ///     }
     /// }
     /// ```
-    pub fn events<Evtype: PaymentEvent>(&self) -> EventsBuilder<Evtype> {
+    pub fn events<Evtype: PaymentEvent>(&self) -> EventsBuilder<'_, Evtype> {
         EventsBuilder::with_client(&self.client)
     }
 
+    /// Long-polls for up to `timeout` for debit note events after `cursor`,
+    /// an empty page meaning none have arrived in that time.
+    pub async fn debit_note_events(
+        &self,
+        cursor: Option<EventCursor>,
+        timeout: Duration,
+    ) -> Result<Vec<DebitNoteEvent>> {
+        self.get_debit_note_events(cursor.map(|c| c.0).as_ref(), Some(timeout), None, None)
+            .await
+    }
+
+    /// All debit note events after `cursor`, long-polled with `timeout` by an
+    /// [`adaptive`](EventPoller::adaptive) [`EventPoller`] starting at 100ms.
+    pub fn debit_note_event_stream(
+        &self,
+        cursor: Option<EventCursor>,
+        timeout: Duration,
+    ) -> impl Stream<Item = Result<DebitNoteEvent>> {
+        let api = self.clone();
+        let mut poller = EventPoller::new(timeout, move |cursor, timeout| {
+            let api = api.clone();
+            async move { api.debit_note_events(cursor, timeout).await }
+        })
+        .adaptive(COLLECTOR_MIN_INTERVAL);
+        if let Some(cursor) = cursor {
+            poller = poller.after(cursor);
+        }
+        poller.into_stream()
+    }
+
     pub async fn get_debit_note_events<Tz>(
         &self,
         after_timestamp: Option<&DateTime<Tz>>,
@@ -241,12 +442,7 @@ impl PaymentApi {
             app_session_id,
         };
         let url = url_format_obj("debitNoteEvents", &input);
-        self.client
-            .get(&url)
-            .send()
-            .json()
-            .await
-            .or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 
     // debit_notes
@@ -332,6 +528,41 @@ impl PaymentApi {
         self.client.get(&url).send().json().await
     }
 
+    /// Polls the invoice until its status is one of `targets`.
+    ///
+    /// Polling starts every [`WAIT_POLL_MIN_INTERVAL`] and backs off up to
+    /// [`WAIT_POLL_MAX_INTERVAL`]. Returns early with the status the invoice
+    /// got stuck in once it is [terminal](DocumentStatus::is_terminal) but not
+    /// among `targets`, and fails with [`Error::TimeoutError`] after `timeout`.
+    pub async fn wait_for_invoice_status(
+        &self,
+        invoice_id: &str,
+        targets: &[DocumentStatus],
+        timeout: Duration,
+    ) -> Result<DocumentStatus> {
+        let deadline = actix_rt::time::Instant::now() + timeout;
+        let mut interval = WAIT_POLL_MIN_INTERVAL;
+        loop {
+            let status = self.get_invoice(invoice_id).await?.status;
+            if targets.contains(&status) || status.is_terminal() {
+                return Ok(status);
+            }
+
+            let now = actix_rt::time::Instant::now();
+            if now >= deadline {
+                let url = url_format!("invoices/{invoice_id}");
+                return Err(Error::TimeoutError {
+                    msg: format!("invoice still {} after {:?}", status, timeout),
+                    method: awc::http::Method::GET,
+                    url,
+                    fingerprint: None,
+                });
+            }
+            actix_rt::time::sleep(interval.min(deadline - now)).await;
+            interval = (interval * 2).min(WAIT_POLL_MAX_INTERVAL);
+        }
+    }
+
     pub async fn get_payments_for_invoice<Tz>(
         &self,
         invoice_id: &str,
@@ -373,12 +604,7 @@ impl PaymentApi {
         };
 
         let url = url_format_obj("invoiceEvents", &input);
-        self.client
-            .get(&url)
-            .send()
-            .json()
-            .await
-            .or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 
     // invoices
@@ -449,12 +675,7 @@ impl PaymentApi {
             app_session_id,
         };
         let url = url_format_obj("payments", &input);
-        self.client
-            .get(&url)
-            .send()
-            .json()
-            .await
-            .or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 
     pub async fn get_signed_payments<Tz>(
@@ -477,12 +698,7 @@ impl PaymentApi {
             app_session_id,
         };
         let url = url_format_obj("payments", &input);
-        self.client
-            .get(&url)
-            .send()
-            .json()
-            .await
-            .or_else(default_on_timeout)
+        self.client.get(&url).send().json_events().await
     }
 
     pub async fn get_payment(&self, payment_id: &str) -> Result<Payment> {
@@ -500,6 +716,52 @@ impl PaymentApi {
 
         self.client.get(&url).send().json().await
     }
+
+    /// Fetches synchronization state of the given driver on the given network.
+    ///
+    /// Fails with [`Error::UnknownDriver`] when the pair is not known to the server.
+    pub async fn get_driver_status(&self, driver: &str, network: &str) -> Result<DriverStatus> {
+        let url = url_format!("drivers/{driver}/networks/{network}/status");
+        self.client
+            .get(&url)
+            .send()
+            .json()
+            .await
+            .map_err(|e| match e {
+                Error::HttpError {
+                    code: StatusCode::NOT_FOUND,
+                    ..
+                } => Error::UnknownDriver {
+                    driver: driver.to_string(),
+                    network: network.to_string(),
+                },
+                e => e,
+            })
+    }
+}
+
+pub const WAIT_POLL_MIN_INTERVAL: Duration = Duration::from_millis(100);
+pub const WAIT_POLL_MAX_INTERVAL: Duration = Duration::from_secs(5);
+
+fn platform_matches(platform: &PaymentPlatform, account: &Account) -> bool {
+    let matches = |wanted: &Option<String>, actual: &str| {
+        wanted
+            .as_deref()
+            .map_or(true, |wanted| wanted.eq_ignore_ascii_case(actual))
+    };
+    matches(&platform.driver, &account.driver)
+        && matches(&platform.network, &account.network)
+        && matches(&platform.token, &account.token)
+}
+
+fn is_not_found(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::HttpError {
+            code: StatusCode::NOT_FOUND,
+            ..
+        }
+    )
 }
 
 pub trait PaymentEvent: DeserializeOwned {
@@ -610,6 +872,463 @@ impl<'a, EvType: PaymentEvent> EventsBuilder<'a, EvType> {
             req = req.add_header("X-Provider-Events", provider_events.as_str())
         }
 
-        req.send().json().await.or_else(default_on_timeout)
+        req.send().json_events().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::{LogCapture, MockResponse, MockServer};
+    use bigdecimal::BigDecimal;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn api(server: &MockServer) -> PaymentApi {
+        PaymentApi::from_client(server.client())
+    }
+
+    #[actix_rt::test]
+    async fn driver_status_parses_response() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!({
+                "syncing": true,
+                "lastBlock": 1234,
+                "issues": [{"kind": "TxStuck", "driver": "erc20", "network": "holesky"}],
+            }))
+        });
+
+        let status = api(&server)
+            .get_driver_status("erc20", "holesky")
+            .await
+            .unwrap();
+        assert!(status.syncing);
+        assert_eq!(status.last_block, Some(1234_u32.into()));
+        assert!(status.is_blocked());
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/drivers/erc20/networks/holesky/status");
+    }
+
+    fn account(driver: &str, network: &str, token: &str, send: bool) -> serde_json::Value {
+        json!({
+            "platform": format!("{}-{}-{}", driver, network, token),
+            "address": "0xbabe",
+            "driver": driver,
+            "network": network,
+            "token": token,
+            "send": send,
+            "receive": true,
+        })
+    }
+
+    fn debit_note_event(id: &str, date: &str) -> serde_json::Value {
+        json!({
+            "debitNoteId": id,
+            "eventDate": date,
+            "eventType": "DebitNoteReceivedEvent",
+        })
+    }
+
+    #[actix_rt::test]
+    async fn debit_note_event_stream_advances_cursor() {
+        use futures::StreamExt;
+
+        let server = MockServer::start(|req| {
+            if req.path.contains("afterTimestamp") {
+                MockResponse::json(json!([debit_note_event("dn-3", "2024-01-01T00:00:30Z")]))
+            } else {
+                MockResponse::json(json!([
+                    debit_note_event("dn-2", "2024-01-01T00:00:20Z"),
+                    debit_note_event("dn-1", "2024-01-01T00:00:10Z"),
+                ]))
+            }
+        });
+
+        let events: Vec<_> = api(&server)
+            .debit_note_event_stream(None, Duration::from_secs(5))
+            .take(3)
+            .map(|e| e.unwrap().debit_note_id)
+            .collect()
+            .await;
+        assert_eq!(events, ["dn-2", "dn-1", "dn-3"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].path.contains("afterTimestamp"));
+        assert!(
+            requests[1]
+                .path
+                .contains("afterTimestamp=2024-01-01T00%3A00%3A20Z"),
+            "{}",
+            requests[1].path
+        );
+    }
+
+    #[actix_rt::test]
+    async fn debit_note_events_timeout_is_empty_page() {
+        let server = MockServer::start(|_| MockResponse::new(408));
+
+        let events = api(&server)
+            .debit_note_events(None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn resolve_platform_picks_first_preferred_with_account() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!([
+                account("erc20", "holesky", "tglm", true),
+                account("erc20", "polygon", "glm", true),
+                account("erc20", "mainnet", "glm", false),
+            ]))
+        });
+        let platform = |network: &str| PaymentPlatform {
+            driver: None,
+            network: Some(network.to_string()),
+            token: None,
+        };
+
+        let (resolved, account) = api(&server)
+            .resolve_platform(&[
+                platform("mainnet"),
+                platform("Polygon"),
+                platform("holesky"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(account.platform, "erc20-polygon-glm");
+        assert_eq!(
+            resolved,
+            PaymentPlatform {
+                driver: Some("erc20".into()),
+                network: Some("polygon".into()),
+                token: Some("glm".into()),
+            }
+        );
+        assert_eq!(server.requests()[0].path, "/requestorAccounts");
+
+        let err = api(&server)
+            .resolve_platform(&[platform("mainnet")])
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::InvalidArgument(msg) if msg.contains("erc20-holesky-tglm")));
+    }
+
+    #[actix_rt::test]
+    async fn driver_status_maps_not_found() {
+        let server = MockServer::start(|_| MockResponse::new(404).body("no such driver"));
+
+        match api(&server).get_driver_status("zksync", "mainnet").await {
+            Err(Error::UnknownDriver { driver, network }) => {
+                assert_eq!(driver, "zksync");
+                assert_eq!(network, "mainnet");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn invoice_json(status: &str) -> serde_json::Value {
+        json!({
+            "invoiceId": "invoice-1",
+            "issuerId": "0xbabe000000000000000000000000000000000000",
+            "recipientId": "0xcafe000000000000000000000000000000000000",
+            "payeeAddr": "0xbabe000000000000000000000000000000000000",
+            "payerAddr": "0xcafe000000000000000000000000000000000000",
+            "paymentPlatform": "erc20-holesky-tglm",
+            "timestamp": "2022-01-01T00:00:00Z",
+            "agreementId": "agreement-1",
+            "activityIds": [],
+            "amount": "10",
+            "paymentDueDate": "2022-01-02T00:00:00Z",
+            "status": status,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn wait_for_invoice_status_polls_until_target() {
+        let polls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| match polls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => MockResponse::json(invoice_json("ACCEPTED")),
+            _ => MockResponse::json(invoice_json("SETTLED")),
+        });
+
+        let status = api(&server)
+            .wait_for_invoice_status(
+                "invoice-1",
+                &[DocumentStatus::Settled, DocumentStatus::Failed],
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, DocumentStatus::Settled);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.path == "/invoices/invoice-1"));
+    }
+
+    #[actix_rt::test]
+    async fn wait_for_invoice_status_short_circuits_and_times_out() {
+        let server = MockServer::start(|_| MockResponse::json(invoice_json("CANCELLED")));
+        let status = api(&server)
+            .wait_for_invoice_status("invoice-1", &[DocumentStatus::Settled], Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(status, DocumentStatus::Cancelled);
+
+        let server = MockServer::start(|_| MockResponse::json(invoice_json("ACCEPTED")));
+        let result = api(&server)
+            .wait_for_invoice_status(
+                "invoice-1",
+                &[DocumentStatus::Settled],
+                Duration::from_millis(250),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::TimeoutError { .. })));
+        assert!(server.requests().len() >= 2);
+    }
+
+    fn new_allocation(amount: u32) -> NewAllocation {
+        NewAllocation {
+            address: None,
+            payment_platform: None,
+            total_amount: BigDecimal::from(amount),
+            timeout: None,
+            make_deposit: false,
+        }
+    }
+
+    fn capped_api(server: &MockServer, limit: u32) -> PaymentApi {
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .max_allocation_amount(BigDecimal::from(limit))
+            .build();
+        PaymentApi::from_client(client)
+    }
+
+    #[actix_rt::test]
+    async fn create_allocation_under_cap_is_sent() {
+        let server = MockServer::start(|_| MockResponse::json(allocation_json("alloc-1")));
+
+        let allocation = capped_api(&server, 10)
+            .create_allocation(&new_allocation(10))
+            .await
+            .unwrap();
+        assert_eq!(allocation.allocation_id, "alloc-1");
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn create_allocation_over_cap_is_rejected() {
+        let server = MockServer::start(|_| MockResponse::json(allocation_json("alloc-1")));
+
+        let result = capped_api(&server, 10)
+            .create_allocation(&new_allocation(11))
+            .await;
+        match result {
+            Err(Error::AllocationLimitExceeded { amount, limit }) => {
+                assert_eq!(amount, BigDecimal::from(11));
+                assert_eq!(limit, BigDecimal::from(10));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn create_allocations_releases_on_failure() {
+        let posts = AtomicUsize::new(0);
+        let server = MockServer::start(move |req| match req.method.as_str() {
+            "POST" if posts.fetch_add(1, Ordering::SeqCst) == 0 => MockResponse::json(json!({
+                "allocationId": "alloc-1",
+                "address": "0xbabe000000000000000000000000000000000000",
+                "paymentPlatform": "erc20-holesky-tglm",
+                "totalAmount": "10",
+                "spentAmount": "0",
+                "remainingAmount": "10",
+                "timestamp": "2022-01-01T00:00:00Z",
+                "makeDeposit": false,
+            })),
+            "POST" => MockResponse::new(400).body("insufficient funds"),
+            _ => MockResponse::new(204),
+        });
+
+        let result = api(&server)
+            .create_allocations(vec![new_allocation(10), new_allocation(20)])
+            .await;
+        match result {
+            Err(Error::HttpError { code, .. }) => assert_eq!(code, StatusCode::BAD_REQUEST),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].method, "DELETE");
+        assert_eq!(requests[2].path, "/allocations/alloc-1");
+    }
+
+    fn allocation_json(id: &str) -> serde_json::Value {
+        json!({
+            "allocationId": id,
+            "address": "0xbabe000000000000000000000000000000000000",
+            "paymentPlatform": "erc20-holesky-tglm",
+            "totalAmount": "10",
+            "spentAmount": "4",
+            "remainingAmount": "6",
+            "timestamp": "2022-01-01T00:00:00Z",
+            "makeDeposit": false,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn release_allocation_with_reason_logs_and_deletes() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "GET" => MockResponse::json(allocation_json("alloc-1")),
+            _ => MockResponse::new(204),
+        });
+        let logs = LogCapture::start();
+
+        api(&server)
+            .release_allocation_with_reason("alloc-1", "job finished early")
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[1].method, "DELETE");
+        assert_eq!(requests[1].path, "/allocations/alloc-1");
+        assert!(logs.records().iter().any(|(level, msg)| {
+            *level == log::Level::Info
+                && msg.contains("alloc-1")
+                && msg.contains("remaining: 6")
+                && msg.contains("job finished early")
+        }));
+    }
+
+    #[actix_rt::test]
+    async fn release_allocation_with_reason_tolerates_missing() {
+        let server = MockServer::start(|_| MockResponse::new(404));
+
+        api(&server)
+            .release_allocation_with_reason("alloc-1", "cleanup")
+            .await
+            .unwrap();
+    }
+
+    fn allocation_server() -> MockServer {
+        MockServer::start(|req| match req.method.as_str() {
+            "POST" => MockResponse::json(allocation_json("alloc-1")),
+            "GET" => MockResponse::json(json!("paid")),
+            _ => MockResponse::new(204),
+        })
+    }
+
+    fn assert_released(server: &MockServer) {
+        let last = server.requests().pop().unwrap();
+        assert_eq!(last.method, "DELETE");
+        assert_eq!(last.path, "/allocations/alloc-1");
+    }
+
+    #[actix_rt::test]
+    async fn with_allocation_releases_on_success() {
+        let server = allocation_server();
+
+        let id = api(&server)
+            .with_allocation(new_allocation(10), |allocation| async move {
+                Ok(allocation.allocation_id)
+            })
+            .await
+            .unwrap();
+        assert_eq!(id, "alloc-1");
+        assert_released(&server);
+    }
+
+    #[actix_rt::test]
+    async fn with_allocation_releases_on_error() {
+        let server = allocation_server();
+
+        let result: Result<()> = api(&server)
+            .with_allocation(new_allocation(10), |_| async {
+                Err(Error::internal("job failed"))
+            })
+            .await;
+        assert!(matches!(result, Err(Error::InternalError(_))));
+        assert_released(&server);
+    }
+
+    #[actix_rt::test]
+    async fn with_allocation_releases_on_early_return() {
+        let server = allocation_server();
+        let client = server.client();
+
+        let result: Result<String> = api(&server)
+            .with_allocation(new_allocation(10), |_| async move {
+                let status: String = client.get("status").send().json().await?;
+                if status == "paid" {
+                    return Ok(status);
+                }
+                client.get("unreachable").send().json().await
+            })
+            .await;
+        assert_eq!(result.unwrap(), "paid");
+        assert_eq!(server.requests().len(), 3);
+        assert_released(&server);
+    }
+
+    #[actix_rt::test]
+    async fn with_allocation_releases_on_panic() {
+        let server = allocation_server();
+        let api = api(&server);
+
+        let result = AssertUnwindSafe(api.with_allocation(new_allocation(10), |_| async {
+            if true {
+                panic!("job crashed");
+            }
+            Ok(())
+        }))
+        .catch_unwind()
+        .await;
+        assert!(result.is_err());
+        assert_released(&server);
+    }
+
+    #[actix_rt::test]
+    async fn validate_allocation_valid() {
+        let server = MockServer::start(|_| MockResponse::json(json!({"valid": true})));
+
+        let validation = api(&server)
+            .validate_allocation(&new_allocation(10), "0xbabe")
+            .await
+            .unwrap();
+        assert!(validation.valid);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/allocations/validate");
+        assert_eq!(request.json()["address"], "0xbabe");
+        assert_eq!(request.json()["totalAmount"], "10");
+    }
+
+    #[actix_rt::test]
+    async fn validate_allocation_invalid() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!({
+                "valid": false,
+                "reason": "insufficient funds",
+                "requiredAmount": "10",
+                "availableAmount": "2.5",
+            }))
+        });
+
+        let validation = api(&server)
+            .validate_allocation(&new_allocation(10), "0xbabe")
+            .await
+            .unwrap();
+        assert!(!validation.valid);
+        assert_eq!(validation.reason.as_deref(), Some("insufficient funds"));
+        assert_eq!(validation.required_amount, Some(BigDecimal::from(10)));
     }
 }
@@ -0,0 +1,165 @@
+//! Agreement cost summaries built from already fetched payment documents.
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+
+use ya_client_model::payment::{DebitNote, DocumentStatus, Invoice};
+
+use super::amount::amounts_equal;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgreementCost {
+    pub agreement_id: String,
+    /// Sum of the latest debit note totals of every activity in the agreement.
+    pub debit_note_total: BigDecimal,
+    /// Amount of the most recent invoice, unless it was cancelled.
+    pub invoice_amount: Option<BigDecimal>,
+}
+
+impl AgreementCost {
+    /// Checks if the invoice disagrees with the debit notes sent before it.
+    pub fn is_mismatch(&self) -> bool {
+        match &self.invoice_amount {
+            Some(amount) => !amounts_equal(amount, &self.debit_note_total),
+            None => false,
+        }
+    }
+
+    /// Final cost: the invoiced amount if already invoiced, debit note total otherwise.
+    pub fn total(&self) -> &BigDecimal {
+        self.invoice_amount
+            .as_ref()
+            .unwrap_or(&self.debit_note_total)
+    }
+}
+
+/// Summarizes the cost of the given agreement.
+///
+/// Documents of other agreements are ignored. Debit notes carry an accumulated
+/// amount per activity, so only the latest one of each activity is counted.
+pub fn agreement_cost(
+    agreement_id: &str,
+    debit_notes: &[DebitNote],
+    invoices: &[Invoice],
+) -> AgreementCost {
+    let mut latest: HashMap<&str, &DebitNote> = HashMap::new();
+    for debit_note in debit_notes
+        .iter()
+        .filter(|d| d.agreement_id == agreement_id)
+    {
+        let entry = latest.entry(&debit_note.activity_id).or_insert(debit_note);
+        if debit_note.timestamp > entry.timestamp {
+            *entry = debit_note;
+        }
+    }
+    let debit_note_total = latest
+        .values()
+        .map(|d| &d.total_amount_due)
+        .fold(BigDecimal::from(0), |acc, amount| acc + amount);
+
+    let invoice_amount = invoices
+        .iter()
+        .filter(|i| i.agreement_id == agreement_id && i.status != DocumentStatus::Cancelled)
+        .max_by_key(|i| i.timestamp)
+        .map(|i| i.amount.clone());
+
+    AgreementCost {
+        agreement_id: agreement_id.to_string(),
+        debit_note_total,
+        invoice_amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+    use std::str::FromStr;
+    use ya_client_model::NodeId;
+
+    fn amount(v: &str) -> BigDecimal {
+        BigDecimal::from_str(v).unwrap()
+    }
+
+    fn debit_note(activity_id: &str, total: &str, at: DateTime<Utc>) -> DebitNote {
+        DebitNote {
+            debit_note_id: format!("{}-{}", activity_id, total),
+            issuer_id: NodeId::default(),
+            recipient_id: NodeId::default(),
+            payee_addr: String::new(),
+            payer_addr: String::new(),
+            payment_platform: "erc20-holesky-tglm".to_string(),
+            previous_debit_note_id: None,
+            timestamp: at,
+            agreement_id: "agreement".to_string(),
+            activity_id: activity_id.to_string(),
+            total_amount_due: amount(total),
+            usage_counter_vector: None,
+            payment_due_date: None,
+            status: DocumentStatus::Accepted,
+        }
+    }
+
+    fn invoice(total: &str, status: DocumentStatus, at: DateTime<Utc>) -> Invoice {
+        Invoice {
+            invoice_id: format!("invoice-{}", total),
+            issuer_id: NodeId::default(),
+            recipient_id: NodeId::default(),
+            payee_addr: String::new(),
+            payer_addr: String::new(),
+            payment_platform: "erc20-holesky-tglm".to_string(),
+            timestamp: at,
+            agreement_id: "agreement".to_string(),
+            activity_ids: vec!["a1".to_string(), "a2".to_string()],
+            amount: amount(total),
+            payment_due_date: at,
+            status,
+        }
+    }
+
+    fn debit_notes(now: DateTime<Utc>) -> Vec<DebitNote> {
+        vec![
+            debit_note("a1", "0.1", now),
+            debit_note("a1", "0.25", now + Duration::seconds(60)),
+            debit_note("a2", "0.5", now),
+        ]
+    }
+
+    #[test]
+    fn matching_invoice() {
+        let now = Utc::now();
+        let invoices = vec![invoice("0.75", DocumentStatus::Received, now)];
+
+        let cost = agreement_cost("agreement", &debit_notes(now), &invoices);
+        assert_eq!(cost.debit_note_total, amount("0.75"));
+        assert_eq!(cost.invoice_amount, Some(amount("0.75")));
+        assert!(!cost.is_mismatch());
+    }
+
+    #[test]
+    fn mismatching_invoice() {
+        let now = Utc::now();
+        let invoices = vec![
+            invoice("0.9", DocumentStatus::Received, now),
+            invoice("5", DocumentStatus::Cancelled, now + Duration::seconds(1)),
+        ];
+
+        let cost = agreement_cost("agreement", &debit_notes(now), &invoices);
+        assert_eq!(cost.invoice_amount, Some(amount("0.9")));
+        assert!(cost.is_mismatch());
+        assert_eq!(cost.total(), &amount("0.9"));
+    }
+
+    #[test]
+    fn not_invoiced_yet() {
+        let now = Utc::now();
+
+        let cost = agreement_cost("agreement", &debit_notes(now), &[]);
+        assert_eq!(cost.invoice_amount, None);
+        assert!(!cost.is_mismatch());
+        assert_eq!(cost.total(), &amount("0.75"));
+        assert_eq!(
+            agreement_cost("other", &debit_notes(now), &[]).debit_note_total,
+            amount("0")
+        );
+    }
+}
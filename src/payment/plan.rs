@@ -0,0 +1,140 @@
+//! Planning which invoices an allocation can pay for, before accepting any.
+use bigdecimal::BigDecimal;
+
+use ya_client_model::payment::{Allocation, Invoice};
+
+/// Order in which [`plan_acceptances`] considers the invoices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcceptOrder {
+    /// Earliest `payment_due_date` first.
+    OldestDue,
+    /// Lowest `amount` first, covering as many invoices as possible.
+    SmallestFirst,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AcceptancePlan<'a> {
+    /// Invoices the allocation can cover, in the order to accept them.
+    pub covered: Vec<&'a Invoice>,
+    /// Invoices left over, in the same order.
+    pub uncovered: Vec<&'a Invoice>,
+    /// Amount of the allocation left after accepting all the `covered` ones.
+    pub remaining: BigDecimal,
+}
+
+/// Splits the invoices into the ones the allocation's remaining amount can
+/// cover and the rest, without sending anything.
+///
+/// Invoices are taken in the given order; one which does not fit is skipped,
+/// so a later, smaller one may still be covered. Invoices on a different
+/// payment platform than the allocation are never covered. Ties keep the
+/// order of `invoices`.
+pub fn plan_acceptances<'a>(
+    allocation: &Allocation,
+    invoices: &'a [Invoice],
+    order: AcceptOrder,
+) -> AcceptancePlan<'a> {
+    let mut ordered: Vec<&Invoice> = invoices.iter().collect();
+    match order {
+        AcceptOrder::OldestDue => ordered.sort_by_key(|invoice| invoice.payment_due_date),
+        AcceptOrder::SmallestFirst => ordered.sort_by(|a, b| a.amount.cmp(&b.amount)),
+    }
+
+    let mut plan = AcceptancePlan {
+        covered: Vec::new(),
+        uncovered: Vec::new(),
+        remaining: allocation.remaining_amount.clone(),
+    };
+    for invoice in ordered {
+        if invoice.payment_platform == allocation.payment_platform
+            && invoice.amount <= plan.remaining
+        {
+            plan.remaining -= &invoice.amount;
+            plan.covered.push(invoice);
+        } else {
+            plan.uncovered.push(invoice);
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::str::FromStr;
+    use ya_client_model::payment::DocumentStatus;
+    use ya_client_model::NodeId;
+
+    const PLATFORM: &str = "erc20-holesky-tglm";
+
+    fn amount(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn allocation(remaining: &str) -> Allocation {
+        Allocation {
+            allocation_id: "alloc-1".to_string(),
+            address: String::new(),
+            payment_platform: PLATFORM.to_string(),
+            total_amount: amount("10"),
+            spent_amount: amount("0"),
+            remaining_amount: amount(remaining),
+            timestamp: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            timeout: None,
+            make_deposit: false,
+        }
+    }
+
+    fn invoice(id: &str, total: &str, due: DateTime<Utc>) -> Invoice {
+        Invoice {
+            invoice_id: id.to_string(),
+            issuer_id: NodeId::default(),
+            recipient_id: NodeId::default(),
+            payee_addr: String::new(),
+            payer_addr: String::new(),
+            payment_platform: PLATFORM.to_string(),
+            timestamp: due,
+            agreement_id: "agreement".to_string(),
+            activity_ids: Vec::new(),
+            amount: amount(total),
+            payment_due_date: due,
+            status: DocumentStatus::Received,
+        }
+    }
+
+    fn invoices() -> Vec<Invoice> {
+        let now = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        vec![
+            invoice("late-small", "1", now + Duration::days(3)),
+            invoice("early-big", "4", now),
+            invoice("middle", "3", now + Duration::days(1)),
+            invoice("late-medium", "2", now + Duration::days(2)),
+        ]
+    }
+
+    fn ids(invoices: &[&Invoice]) -> Vec<String> {
+        invoices.iter().map(|i| i.invoice_id.clone()).collect()
+    }
+
+    #[test]
+    fn oldest_due_first() {
+        let invoices = invoices();
+        let plan = plan_acceptances(&allocation("6"), &invoices, AcceptOrder::OldestDue);
+
+        assert_eq!(ids(&plan.covered), vec!["early-big", "late-medium"]);
+        assert_eq!(ids(&plan.uncovered), vec!["middle", "late-small"]);
+        assert_eq!(plan.remaining, amount("0"));
+    }
+
+    #[test]
+    fn smallest_first() {
+        let mut invoices = invoices();
+        invoices[2].payment_platform = "erc20-polygon-glm".to_string();
+        let plan = plan_acceptances(&allocation("6"), &invoices, AcceptOrder::SmallestFirst);
+
+        assert_eq!(ids(&plan.covered), vec!["late-small", "late-medium"]);
+        assert_eq!(ids(&plan.uncovered), vec!["middle", "early-big"]);
+        assert_eq!(plan.remaining, amount("3"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Change detection between consecutive snapshots of invoice lists.
+use std::collections::HashMap;
+
+use ya_client_model::payment::{DocumentStatus, Invoice};
+
+/// Status change of a single invoice between two snapshots.
+///
+/// `from` is `None` for invoices which newly appeared, `to` is `None` for
+/// the ones which disappeared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusChange {
+    pub id: String,
+    pub from: Option<DocumentStatus>,
+    pub to: Option<DocumentStatus>,
+}
+
+impl StatusChange {
+    /// Checks if the observed change is allowed by the invoice lifecycle.
+    pub fn is_legal(&self) -> bool {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => from.can_transition_to(to),
+            _ => true,
+        }
+    }
+}
+
+/// Lists invoices, matched by id, whose status differs between `prev` and `curr`.
+///
+/// Invoices present only in `curr` or only in `prev` are reported as well.
+/// Transitions which should not be possible are logged as warnings.
+pub fn status_changes(prev: &[Invoice], curr: &[Invoice]) -> Vec<StatusChange> {
+    let previous: HashMap<&str, &DocumentStatus> = prev
+        .iter()
+        .map(|i| (i.invoice_id.as_str(), &i.status))
+        .collect();
+    let current: HashMap<&str, &DocumentStatus> = curr
+        .iter()
+        .map(|i| (i.invoice_id.as_str(), &i.status))
+        .collect();
+
+    let mut changes = Vec::new();
+    for invoice in curr {
+        let from = previous.get(invoice.invoice_id.as_str()).copied();
+        if from == Some(&invoice.status) {
+            continue;
+        }
+        let change = StatusChange {
+            id: invoice.invoice_id.clone(),
+            from: from.cloned(),
+            to: Some(invoice.status.to_owned()),
+        };
+        if !change.is_legal() {
+            log::warn!(
+                "invoice {} changed status from {} to {}, which is not a valid transition",
+                invoice.invoice_id,
+                from.unwrap(),
+                invoice.status
+            );
+        }
+        changes.push(change);
+    }
+    for invoice in prev {
+        if !current.contains_key(invoice.invoice_id.as_str()) {
+            changes.push(StatusChange {
+                id: invoice.invoice_id.clone(),
+                from: Some(invoice.status.to_owned()),
+                to: None,
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::LogCapture;
+    use bigdecimal::BigDecimal;
+    use chrono::Utc;
+    use ya_client_model::NodeId;
+
+    fn invoice(id: &str, status: DocumentStatus) -> Invoice {
+        Invoice {
+            invoice_id: id.to_string(),
+            issuer_id: NodeId::default(),
+            recipient_id: NodeId::default(),
+            payee_addr: String::new(),
+            payer_addr: String::new(),
+            payment_platform: "erc20-holesky-tglm".to_string(),
+            timestamp: Utc::now(),
+            agreement_id: "agreement".to_string(),
+            activity_ids: vec![],
+            amount: BigDecimal::from(1),
+            payment_due_date: Utc::now(),
+            status,
+        }
+    }
+
+    #[test]
+    fn legal_change() {
+        let prev = vec![
+            invoice("a", DocumentStatus::Received),
+            invoice("b", DocumentStatus::Received),
+        ];
+        let curr = vec![
+            invoice("a", DocumentStatus::Accepted),
+            invoice("b", DocumentStatus::Received),
+        ];
+
+        let changes = status_changes(&prev, &curr);
+        assert_eq!(
+            changes,
+            vec![StatusChange {
+                id: "a".to_string(),
+                from: Some(DocumentStatus::Received),
+                to: Some(DocumentStatus::Accepted),
+            }]
+        );
+        assert!(changes[0].is_legal());
+    }
+
+    #[test]
+    fn illegal_change() {
+        let logs = LogCapture::start();
+        let prev = vec![invoice("a", DocumentStatus::Accepted)];
+        let curr = vec![invoice("a", DocumentStatus::Rejected)];
+
+        let changes = status_changes(&prev, &curr);
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].is_legal());
+        assert!(logs
+            .records()
+            .iter()
+            .any(|(level, msg)| *level == log::Level::Warn && msg.contains("invoice a")));
+    }
+
+    #[test]
+    fn appeared_and_disappeared() {
+        let prev = vec![invoice("gone", DocumentStatus::Settled)];
+        let curr = vec![invoice("new", DocumentStatus::Received)];
+
+        assert_eq!(
+            status_changes(&prev, &curr),
+            vec![
+                StatusChange {
+                    id: "new".to_string(),
+                    from: None,
+                    to: Some(DocumentStatus::Received),
+                },
+                StatusChange {
+                    id: "gone".to_string(),
+                    from: Some(DocumentStatus::Settled),
+                    to: None,
+                },
+            ]
+        );
+    }
+}
@@ -5,27 +5,84 @@ use awc::{
     http::header::{HeaderMap, HeaderName, HeaderValue},
     http::{header, Method, StatusCode},
     ws::Codec,
-    BoxedSocket, ClientRequest, ClientResponse, SendClientRequest,
+    BoxedSocket, ClientRequest, ClientResponse, ConnectResponse, SendClientRequest,
 };
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bigdecimal::BigDecimal;
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future::{self, Either};
 use futures::stream::Peekable;
-use futures::{Stream, StreamExt, TryStreamExt};
+use futures::{Stream, StreamExt, TryFutureExt, TryStreamExt};
 use heck::ToLowerCamelCase;
+use md5::Md5;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_qs;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
 use std::cmp::max;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use std::{env, rc::Rc, str::FromStr, time::Duration};
 use url::{form_urlencoded, Url};
 
-use crate::model::ErrorMessage;
+use crate::error::EventStreamErrorReason;
+use crate::model::time::invalid_timestamp_value;
+use crate::model::{ErrorMessage, VersionInfo, YagnaVersionResponse};
 use crate::{Error, Result};
 
+pub mod compress;
+pub mod config;
+mod json_array;
+pub mod limit;
+pub mod metrics;
+pub mod middleware;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod observer;
+pub mod paginate;
+pub mod retry;
+mod sse;
+pub mod telemetry;
+
+use compress::Compress;
+pub use compress::Encoding;
+pub use config::ClientConfig;
+use json_array::ArraySplitter;
+use limit::Limiter;
+pub use limit::Priority;
+pub use metrics::{EndpointMetrics, LatencyBucket, MetricsSnapshot, WebClientMetrics};
+pub use middleware::{
+    BuiltinLayer, Middleware, MiddlewareRequest, MiddlewareResult, Next, RequestLogger,
+};
+use middleware::{Fingerprint, Layers, RequestInfo, SlowRequests};
+use observer::Observer;
+pub use observer::{NoopObserver, RequestObserver};
+pub use paginate::{PageCursor, Paginated};
+use retry::Retry;
+pub use retry::RetryPolicy;
+use sse::SseDecoder;
+use telemetry::Counters;
+pub use telemetry::{StatusClasses, Telemetry};
+
 pub const YAGNA_API_URL_ENV_VAR: &str = "YAGNA_API_URL";
 pub const DEFAULT_YAGNA_API_URL: &str = "http://127.0.0.1:7465";
-const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+pub const YAGNA_APPKEY_ENV_VAR: &str = "YAGNA_APPKEY";
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+const DEFAULT_LOG_BODY_MAX_LEN: usize = 512;
+const DEFAULT_STREAM_HIGH_WATER_MARK: usize = 64;
+const DEFAULT_COMPRESS_MIN_SIZE: usize = 1024;
+const CONTENT_SNIPPET_LEN: usize = 128;
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const VERSION_PATH: &str = "version/get";
+const READY_POLL_MIN_INTERVAL: Duration = Duration::from_millis(50);
+const READY_POLL_MAX_INTERVAL: Duration = Duration::from_secs(2);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub fn rest_api_url() -> Url {
     let api_url = env::var(YAGNA_API_URL_ENV_VAR).unwrap_or(DEFAULT_YAGNA_API_URL.into());
@@ -34,17 +91,160 @@ pub fn rest_api_url() -> Url {
         .unwrap_or_else(|_| panic!("invalid API URL: {}", api_url))
 }
 
+/// Appends the slash missing at the end of the path of a base URL, without
+/// which [`Url::join`] would replace its last segment, eg. `v1` of
+/// `http://127.0.0.1:7465/market-api/v1`.
+pub(crate) fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        log::warn!("base URL {} does not end with a slash, appending one", url);
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+/// Length of app keys generated by yagna (a hyphen-less UUID).
+pub const APPKEY_LEN: usize = 32;
+
+/// Checks if the token looks like a yagna app key before it is sent.
+///
+/// Keys are expected to consist of ASCII letters, digits, `-` and `_` only;
+/// anything else (whitespace, quotes, control characters) is rejected. An
+/// unexpected length is only warned about, so that keys in formats adopted
+/// later still work. Use [`validate_appkey_strict`] to reject those as well.
+pub fn validate_appkey(token: &str) -> Result<()> {
+    check_appkey(token, false)
+}
+
+/// Like [`validate_appkey`], but also fails on length other than [`APPKEY_LEN`].
+pub fn validate_appkey_strict(token: &str) -> Result<()> {
+    check_appkey(token, true)
+}
+
+fn check_appkey(token: &str, strict: bool) -> Result<()> {
+    if token.is_empty() {
+        return Err(Error::InvalidAppKey("app key is empty".into()));
+    }
+    if let Some(c) = token
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        return Err(Error::InvalidAppKey(format!(
+            "app key contains invalid character {:?}",
+            c
+        )));
+    }
+    if token.len() != APPKEY_LEN {
+        let msg = format!(
+            "app key has {} characters, expected {}",
+            token.len(),
+            APPKEY_LEN
+        );
+        if strict {
+            return Err(Error::InvalidAppKey(msg));
+        }
+        log::warn!("{}", msg);
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub enum WebAuth {
     Bearer(String),
+    /// HTTP Basic auth, eg. for a proxy in front of the daemon.
+    Basic {
+        user: String,
+        password: String,
+    },
+    /// Arbitrary header carrying the credentials.
+    Custom {
+        header: HeaderName,
+        value: HeaderValue,
+    },
+}
+
+/// Lowest TLS protocol version accepted when talking to `https` endpoints.
+///
+/// Enforced only with the `tls` feature, which enables the OpenSSL backend.
+/// Without it the client cannot connect over TLS at all.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum TlsVersion {
+    #[default]
+    Tls1_2,
+    Tls1_3,
+}
+
+/// How [`WebRequest::json`] reacts to a successful response which is not
+/// JSON, eg. an HTML error page of a proxy in front of the daemon.
+///
+/// Responses without any `content-type` are always parsed as JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentTypePolicy {
+    /// Fail with [`Error::UnexpectedContentType`].
+    #[default]
+    Error,
+    /// Send the request again, up to `attempts` more times, `delay` apart,
+    /// see [`BuiltinLayer::Retry`]. Fails like [`Error`](Self::Error) once
+    /// the attempts run out, and right away for the methods [`RetryPolicy`]
+    /// does not retry by default, eg. `POST`.
+    Retry {
+        attempts: u32,
+        /// In seconds when (de)serialized.
+        #[serde(with = "config::duration_secs")]
+        delay: Duration,
+    },
+    /// Deserialize the result from the raw body given as a JSON string, which
+    /// only succeeds for string-like results.
+    ReturnRaw,
 }
 
 /// Convenient wrapper for the [`awc::Client`](
 /// https://docs.rs/awc/1.0/awc/struct.Client.html) with builder.
+///
+/// Request timeouts are resolved in the following order, the first one set wins:
+/// 1. per-request, via [`WebRequest::timeout`],
+/// 2. per-interface, via [`WebClientBuilder::service_timeout`],
+/// 3. client-wide, via [`WebClientBuilder::timeout`].
 #[derive(Clone)]
 pub struct WebClient {
     base_url: Rc<Url>,
     awc: awc::Client,
+    options: Rc<ClientOptions>,
+    /// Default timeout of the interface this client has been created for.
+    timeout: Option<Duration>,
+}
+
+/// Settings shared by all the interfaces created from a single [`WebClient`].
+#[derive(Clone, Debug)]
+struct ClientOptions {
+    service_timeouts: HashMap<String, Duration>,
+    log_body_max_len: usize,
+    default_query: Vec<(String, String)>,
+    stream_high_water_mark: usize,
+    max_response_size: usize,
+    layers: Rc<Layers>,
+    content_type_policy: ContentTypePolicy,
+    max_allocation_amount: Option<BigDecimal>,
+    telemetry: Rc<Counters>,
+    draining: Cell<bool>,
+    /// Requests [`WebClient::drain`] waits for.
+    pending: Rc<Cell<usize>>,
+    stable_body_key_order: bool,
+    /// Told of the errors outside the middleware chain, if the built-in
+    /// [`BuiltinLayer::Observer`] is installed.
+    observer: Option<Observer>,
 }
 
 pub trait WebInterface {
@@ -53,7 +253,7 @@ pub trait WebInterface {
 
     fn rebase_service_url(base_url: Rc<Url>) -> Result<Rc<Url>> {
         if let Ok(url) = std::env::var(Self::API_URL_ENV_VAR) {
-            return Ok(Url::from_str(&url)?.into());
+            return Ok(with_trailing_slash(Url::from_str(&url)?).into());
         }
         let suffix = if Self::API_SUFFIX.starts_with('/') {
             Self::API_SUFFIX[1..].to_string()
@@ -72,19 +272,38 @@ pub trait WebInterface {
 pub struct WebRequestMeta {
     method: Method,
     url: String,
+    options: Rc<ClientOptions>,
+    info: Rc<RequestInfo>,
 }
 
 impl WebRequestMeta {
-    fn new(method: Method, url: String) -> Self {
-        WebRequestMeta { method, url }
+    fn new(method: Method, url: String, options: Rc<ClientOptions>) -> Self {
+        WebRequestMeta {
+            method,
+            url,
+            options,
+            info: Default::default(),
+        }
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.info.fingerprint.borrow().clone()
     }
 
     fn as_request_err(&self, err: SendRequestError) -> Error {
-        Error::from_request(err, self.method.clone(), self.url.clone())
+        let err = Error::from_request(err, self.method.clone(), self.url.clone());
+        match self.fingerprint() {
+            Some(fingerprint) => err.with_fingerprint(&fingerprint),
+            None => err,
+        }
     }
 
-    fn as_response_err(&self, code: StatusCode, msg: String) -> Error {
-        Error::from_response(code, msg, self.method.clone(), self.url.clone())
+    /// Reports an error which did not pass the [`BuiltinLayer::Observer`].
+    fn observe(&self, err: Error) -> Error {
+        if let Some(observer) = &self.options.observer {
+            observer.on_error(&self.method, &self.url, &err);
+        }
+        err
     }
 }
 
@@ -98,23 +317,96 @@ impl WebClient {
         WebClientBuilder::default()
     }
 
+    /// Client sending requests with `awc` to `base_url`, eg. a local mock
+    /// server, with the default options of [`WebClientBuilder`]. Neither the
+    /// environment nor the app key are read, `awc` is used as it is.
+    ///
+    /// `base_url` should end with a slash, as the paths of the requests are
+    /// [joined](Url::join) to it. A missing one is appended with a warning.
+    pub fn from_parts(base_url: Url, awc: awc::Client) -> WebClient {
+        WebClient {
+            base_url: Rc::new(with_trailing_slash(base_url)),
+            awc,
+            options: Rc::new(WebClientBuilder::default().client_options()),
+            timeout: None,
+        }
+    }
+
     pub fn with_token(token: &str) -> WebClient {
         WebClientBuilder::default().auth_token(token).build()
     }
 
+    /// Current values of the request counters, shared by all the interfaces
+    /// created from this client.
+    pub fn telemetry_snapshot(&self) -> Telemetry {
+        self.options.telemetry.snapshot()
+    }
+
+    /// Stops sending new requests, which fail with [`Error::ShuttingDown`],
+    /// and waits up to `grace` for the ones already sent to be done: until
+    /// their response body is read to the end or dropped, so also for the
+    /// event streams still being listened to.
+    ///
+    /// Applies to all the interfaces created from this client and can not be
+    /// undone. Requests still waiting after `grace` are left running, their
+    /// number is logged.
+    pub async fn drain(&self, grace: Duration) -> Result<()> {
+        self.options.draining.set(true);
+        let deadline = Instant::now() + grace;
+        let pending = &self.options.pending;
+        while pending.get() > 0 && Instant::now() < deadline {
+            actix_rt::time::sleep(
+                DRAIN_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+            )
+            .await;
+        }
+        match pending.get() {
+            0 => log::debug!("drained all requests to {}", self.base_url),
+            n => log::warn!(
+                "{} requests to {} still in flight after {:?}",
+                n,
+                self.base_url,
+                grace
+            ),
+        }
+        Ok(())
+    }
+
+    /// Cap on the total amount of new allocations, see
+    /// [`WebClientBuilder::max_allocation_amount`].
+    pub fn max_allocation_amount(&self) -> Option<&BigDecimal> {
+        self.options.max_allocation_amount.as_ref()
+    }
+
     /// constructs endpoint url in form of `<base_url>/<suffix>`.
     ///
     /// suffix should not have leading slash ie. `offer` not `/offer`
+    ///
+    /// Default query parameters not already present in the suffix are appended.
     fn url<T: AsRef<str>>(&self, suffix: T) -> Result<url::Url> {
-        Ok(self.base_url.join(suffix.as_ref())?)
+        let mut url = self.base_url.join(suffix.as_ref())?;
+        let missing: Vec<_> = self
+            .options
+            .default_query
+            .iter()
+            .filter(|(name, _)| !url.query_pairs().any(|(k, _)| k == name.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            url.query_pairs_mut().extend_pairs(missing);
+        }
+        Ok(url)
     }
 
     pub fn request(&self, method: Method, url: &str) -> WebRequest<ClientRequest> {
         let url = self.url(url).unwrap().to_string();
         log::debug!("doing {} on {}", method, url);
+        let mut inner_request = self.awc.request(method.clone(), &url);
+        if let Some(timeout) = self.timeout {
+            inner_request = inner_request.timeout(timeout);
+        }
         WebRequest {
-            inner_request: self.awc.request(method.clone(), &url),
-            meta: WebRequestMeta::new(method, url),
+            inner_request,
+            meta: WebRequestMeta::new(method, url, self.options.clone()),
         }
     }
 
@@ -126,20 +418,40 @@ impl WebClient {
             .awc
             .request(method.clone(), &url)
             .insert_header((header::ACCEPT, mime::TEXT_EVENT_STREAM));
-        let stream = request
+        let response = request
             .send()
             .await
-            .map_err(|e| Error::from_request(e, method, url))?
+            .map_err(|e| Error::from_request(e, method, url.clone()))?;
+        if !response.status().is_success() {
+            let reason = EventStreamErrorReason::UnexpectedStatus(response.status());
+            return Err(Error::event_stream(url, reason));
+        }
+        let stream = response
             .into_stream()
             .map_err(Error::from)
-            .event_stream();
-        Ok(stream)
+            .event_stream()
+            .map_err(move |e| e.at_stream(&url));
+        Ok(bounded_stream(stream, self.options.stream_high_water_mark))
+    }
+
+    /// Lists all the elements of a paged endpoint, requesting the next page
+    /// as the previous one is consumed.
+    ///
+    /// `url` builds the address without the paging parameters, which are
+    /// appended as described by `paging`. The walk stops at an empty page, a
+    /// page shorter than requested, or one without a cursor to continue from.
+    pub fn paginate<T, F>(&self, url: F, paging: Paginated<T>) -> impl Stream<Item = Result<T>>
+    where
+        T: DeserializeOwned + 'static,
+        F: Fn() -> String + 'static,
+    {
+        paginate::pages(self.clone(), url, paging)
     }
 
     pub async fn ws(&self, url: &str) -> Result<(ClientResponse, Framed<BoxedSocket, Codec>)> {
         let mut url = self.base_url.join(url).unwrap();
         url.set_scheme("ws")
-            .map_err(|_| Error::InternalError(format!("Invalid URL: {}", url)))?;
+            .map_err(|_| Error::internal(format!("Invalid URL: {}", url)))?;
         Ok(self.awc.ws(url.to_string()).connect().await?)
     }
 
@@ -159,18 +471,109 @@ impl WebClient {
         self.request(Method::DELETE, url)
     }
 
+    pub fn patch(&self, url: &str) -> WebRequest<ClientRequest> {
+        self.request(Method::PATCH, url)
+    }
+
+    /// Sends the JSON body of the request made by `build` and, if it is
+    /// rejected with a client error (4xx), lets `recover` adjust the body
+    /// and sends it once more.
+    ///
+    /// The request is retried at most once: `recover` returning `None`, a
+    /// different error than 4xx and any failure of the retry are returned as
+    /// they are. `build` is called again for the retry, its body is ignored
+    /// then in favor of the recovered one.
+    pub async fn send_with_recovery<B, R, F, T>(&self, build: B, recover: F) -> Result<R>
+    where
+        B: Fn() -> (WebRequest<ClientRequest>, T),
+        F: FnOnce(&Error, T) -> Option<T>,
+        T: Serialize + std::fmt::Debug,
+        R: DeserializeOwned,
+    {
+        let (request, body) = build();
+        let err = match request.send_json(&body).json().await {
+            Err(e @ Error::HttpError { code, .. }) if code.is_client_error() => e,
+            result => return result,
+        };
+        let body = match recover(&err, body) {
+            Some(body) => body,
+            None => return Err(err),
+        };
+        log::debug!("retrying with recovered body after: {}", err);
+        let (request, _) = build();
+        request.send_json(&body).json().await
+    }
+
+    /// Checks if the daemon answers requests.
+    ///
+    /// Failing to connect, or the daemon responding with a server error while
+    /// it is still starting up, means it is not alive (yet). Any other error,
+    /// eg. a rejected app key, is returned as is.
+    pub async fn is_alive(&self) -> Result<bool> {
+        match self.probe().await {
+            Ok(()) => Ok(true),
+            Err(e) if is_not_ready(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits until the daemon is [alive](Self::is_alive), checking with
+    /// exponential backoff.
+    ///
+    /// Fails with [`Error::TimeoutError`] carrying the last failure if it does
+    /// not come up within `timeout`.
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        let deadline = actix_rt::time::Instant::now() + timeout;
+        let mut interval = READY_POLL_MIN_INTERVAL;
+        loop {
+            let err = match self.probe().await {
+                Ok(()) => return Ok(()),
+                Err(e) if is_not_ready(&e) => e,
+                Err(e) => return Err(e),
+            };
+
+            let now = actix_rt::time::Instant::now();
+            if now >= deadline {
+                return Err(Error::TimeoutError {
+                    msg: format!("daemon not ready after {:?}, last error: {}", timeout, err),
+                    method: Method::GET,
+                    url: self.url(VERSION_PATH)?.to_string(),
+                    fingerprint: None,
+                });
+            }
+            log::debug!("daemon not ready yet: {}", err);
+            actix_rt::time::sleep(interval.min(deadline - now)).await;
+            interval = (interval * 2).min(READY_POLL_MAX_INTERVAL);
+        }
+    }
+
+    /// Fetches the version of the daemon and whether a newer release is
+    /// pending.
+    pub async fn version_info(&self) -> Result<VersionInfo> {
+        let response: YagnaVersionResponse = self.get(VERSION_PATH).send().json().await?;
+        Ok(response.into())
+    }
+
+    async fn probe(&self) -> Result<()> {
+        self.get(VERSION_PATH).send().bytes().await.map(|_| ())
+    }
+
     pub fn interface<T: WebInterface>(&self) -> Result<T> {
         self.interface_at(None)
     }
 
     pub fn interface_at<T: WebInterface>(&self, base_url: impl Into<Option<Url>>) -> Result<T> {
         let base_url = match base_url.into() {
-            Some(url) => url.into(),
+            Some(url) => with_trailing_slash(url).into(),
             None => T::rebase_service_url(self.base_url.clone())?,
         };
 
-        let awc = self.awc.clone();
-        Ok(T::from_client(WebClient { base_url, awc }))
+        Ok(T::from_client(WebClient {
+            base_url,
+            awc: self.awc.clone(),
+            timeout: self.options.service_timeouts.get(T::API_SUFFIX).copied(),
+            options: self.options.clone(),
+        }))
     }
 }
 
@@ -180,537 +583,3481 @@ impl WebRequest<ClientRequest> {
         value: &T,
     ) -> WebRequest<SendClientRequest> {
         log::trace!("sending payload: {:?}", value);
-        WebRequest {
-            inner_request: self.inner_request.send_json(value),
-            meta: self.meta,
-        }
+        self.send_json_body(value)
     }
 
-    pub fn send_bytes(self, bytes: Vec<u8>) -> WebRequest<SendClientRequest> {
-        let inner_request = self
-            .inner_request
-            .content_type("application/octet-stream")
-            .send_body(bytes);
-        WebRequest {
-            inner_request,
-            meta: self.meta,
+    /// Sends a dynamically built JSON body, logged in its compact form.
+    pub fn send_value(self, value: &serde_json::Value) -> WebRequest<SendClientRequest> {
+        log::trace!("sending payload: {}", value);
+        self.send_json_body(value)
+    }
+
+    fn send_json_body<T: Serialize>(self, value: &T) -> WebRequest<SendClientRequest> {
+        if !self.meta.options.stable_body_key_order {
+            return self.send_json_as_is(value);
+        }
+        match canonical_json(value) {
+            Ok(value) => self.send_json_as_is(&value),
+            Err(e) => WebRequest {
+                inner_request: SendRequestError::Custom(Box::new(e), Box::new("json")).into(),
+                meta: self.meta,
+            },
         }
     }
 
+    fn send_json_as_is<T: Serialize>(mut self, value: &T) -> WebRequest<SendClientRequest> {
+        let body = match serde_json::to_vec(value) {
+            Ok(body) => body,
+            Err(e) => {
+                return WebRequest {
+                    inner_request: SendRequestError::Custom(Box::new(e), Box::new("json")).into(),
+                    meta: self.meta,
+                }
+            }
+        };
+        self.inner_request = self.inner_request.content_type("application/json");
+        self.dispatch(Some(body.into()))
+    }
+
+    pub fn send_bytes(mut self, bytes: Vec<u8>) -> WebRequest<SendClientRequest> {
+        self.inner_request = self.inner_request.content_type("application/octet-stream");
+        self.dispatch(Some(bytes.into()))
+    }
+
+    /// Overrides both the per-interface and the client-wide timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.inner_request = self.inner_request.timeout(timeout);
+        self
+    }
+
     pub fn add_header(mut self, name: &str, value: &str) -> Self {
         self.inner_request = self.inner_request.append_header((name, value));
         self
     }
 
+    /// Appends a query built at runtime, eg. from filters chosen by the user,
+    /// after any query the url already has. An empty query changes nothing.
+    ///
+    /// As with the parameters of the url itself, the
+    /// [default ones](WebClientBuilder::default_query_param) of the same
+    /// names are dropped.
+    pub fn query(mut self, query: QueryParamsBuilder) -> Self {
+        let query = query.build();
+        if query.is_empty() {
+            return self;
+        }
+        let overridden: Vec<_> = self
+            .meta
+            .options
+            .default_query
+            .iter()
+            .filter(|(name, _)| {
+                form_urlencoded::parse(query.as_bytes()).any(|(k, _)| k == name.as_str())
+            })
+            .map(|(name, value)| {
+                form_urlencoded::Serializer::new(String::new())
+                    .append_pair(name, value)
+                    .finish()
+            })
+            .collect();
+        let url = match self.meta.url.split_once('?') {
+            Some((path, current)) => {
+                let kept: Vec<_> = current
+                    .split('&')
+                    .filter(|pair| !overridden.iter().any(|default| default == pair))
+                    .collect();
+                if kept.is_empty() {
+                    format!("{}?", path)
+                } else {
+                    format!("{}?{}&", path, kept.join("&"))
+                }
+            }
+            None => format!("{}?", self.meta.url),
+        };
+        self.meta.url = format!("{}{}", url, query);
+        self.inner_request = self.inner_request.uri(self.meta.url.as_str());
+        self
+    }
+
+    /// Sets the place of the request in the queue for a free slot when the
+    /// client has a [`max_concurrency`](WebClientBuilder::max_concurrency)
+    /// limit, [`Priority::Normal`] by default. Without a limit it has no effect.
+    pub fn priority(self, priority: Priority) -> Self {
+        self.meta.info.priority.set(priority);
+        self
+    }
+
+    /// Marks the request as a long poll, which the daemon may hold until its
+    /// timeout runs out: it is then neither reported as
+    /// [slow](WebClientBuilder::slow_request_threshold) nor retried after
+    /// a timeout. See [`json_events`](WebRequest::json_events) for reading its response.
+    pub fn long_poll(self) -> Self {
+        self.meta.info.long_poll.set(true);
+        self
+    }
+
+    /// Sets the `Idempotency-Key` header to a hash of the method, path (with
+    /// the query) and canonical JSON form of `body`, so a retry of a logically
+    /// identical request is recognized as such, also by another process.
+    ///
+    /// `body` is only hashed, it still has to be sent with eg. [`send_json`].
+    ///
+    /// [`send_json`]: Self::send_json
+    pub fn auto_idempotency_key(self, body: &impl Serialize) -> Result<Self> {
+        let url = Url::parse(&self.meta.url)?;
+        let body = serde_json::to_vec(&canonical_json(body)?)?;
+
+        let mut hasher = Sha256::new();
+        for part in [
+            self.meta.method.as_str().as_bytes(),
+            url.path().as_bytes(),
+            url.query().unwrap_or_default().as_bytes(),
+            &body,
+        ] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let key = hex::encode(hasher.finalize());
+        Ok(self.add_header(IDEMPOTENCY_KEY_HEADER, &key))
+    }
+
     pub fn send(self) -> WebRequest<SendClientRequest> {
+        self.dispatch(None)
+    }
+
+    fn dispatch(self, body: Option<Bytes>) -> WebRequest<SendClientRequest> {
+        let inner_request = match self.inner_request.freeze() {
+            Ok(frozen) => send_frozen(&self.meta, &frozen, body),
+            Err(e) => SendRequestError::from(e).into(),
+        };
         WebRequest {
-            inner_request: self.inner_request.send(),
+            inner_request,
             meta: self.meta,
         }
     }
-}
-
-impl WebRequest<SendClientRequest> {
-    async fn request(
-        self,
-    ) -> Result<ClientResponse<impl Stream<Item = std::result::Result<Bytes, PayloadError>>>> {
-        let meta = self.meta.clone();
-        let mut response = self
-            .inner_request
-            .await
-            .map_err(|e| meta.as_request_err(e))?;
 
-        log::trace!("{:?}", response.headers());
-        if response.status().is_success() {
-            Ok(response)
-        } else {
-            let msg = if response
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .map(|v| v.as_bytes() == b"application/json")
-                .unwrap_or_default()
-            {
-                let err_msg = response.json().await;
-                err_msg
-                    .map(|e: ErrorMessage| e.message.unwrap_or_default())
-                    .unwrap_or_else(|e| format!("error parsing error msg: {}", e))
-            } else {
-                match response.body().limit(MAX_BODY_SIZE).await {
-                    Ok(ref bytes) => String::from_utf8_lossy(bytes).to_string(),
-                    Err(e) => e.to_string(),
+    /// Sends the request and, if no response has arrived within `after`, fires
+    /// an identical second one. Whichever responds first wins, the other one
+    /// is dropped (and thereby cancelled).
+    ///
+    /// Only idempotent reads (`GET`, `HEAD`, `OPTIONS`) can be hedged. For any
+    /// other method the returned request fails without being sent.
+    pub fn hedge(self, after: Duration) -> WebRequest<SendClientRequest> {
+        let meta = self.meta;
+        if !matches!(meta.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            let err = SendRequestError::Custom(
+                Box::new(Error::internal(format!(
+                    "{} is not idempotent and cannot be hedged",
+                    meta.method
+                ))),
+                Box::new("hedge"),
+            );
+            return WebRequest {
+                inner_request: err.into(),
+                meta,
+            };
+        }
+        let frozen = match self.inner_request.freeze() {
+            Ok(frozen) => frozen,
+            Err(e) => {
+                return WebRequest {
+                    inner_request: SendRequestError::from(e).into(),
+                    meta,
                 }
+            }
+        };
+
+        let sender = meta.clone();
+        let race = async move {
+            let first = send_frozen(&sender, &frozen, None);
+            let delay = Box::pin(actix_rt::time::sleep(after));
+            let first = match future::select(first, delay).await {
+                Either::Left((result, _)) => return result,
+                Either::Right((_, first)) => first,
             };
-            Err(meta.as_response_err(response.status(), msg))
+            log::debug!(
+                "no response after {:?}, hedging {} on {}",
+                after,
+                sender.method,
+                sender.url
+            );
+            match future::select(first, send_frozen(&sender, &frozen, None)).await {
+                Either::Left((result, _)) | Either::Right((result, _)) => result,
+            }
+        };
+        WebRequest {
+            inner_request: SendClientRequest::Fut(
+                Box::pin(race.map_ok(ConnectResponse::Client)),
+                None,
+                false,
+            ),
+            meta,
         }
     }
+}
 
-    pub async fn bytes(self) -> Result<Vec<u8>> {
-        Ok(self.request().await?.body().await?.to_vec())
-    }
+/// Sends an already built request through the middlewares.
+fn send_frozen(
+    meta: &WebRequestMeta,
+    frozen: &awc::FrozenClientRequest,
+    body: Option<Bytes>,
+) -> SendClientRequest {
+    let request = MiddlewareRequest::new(frozen.clone(), body, meta.info.clone());
+    meta.options.layers.dispatch(request)
+}
 
-    pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
-        let meta = self.meta.clone();
-        let mut response = self.request().await?;
+/// Chunk of a response body.
+type PayloadResult = std::result::Result<Bytes, PayloadError>;
 
-        // allow empty body and no content (204) to pass smoothly
-        if StatusCode::NO_CONTENT == response.status()
-            || Some("0")
-                == response
-                    .headers()
-                    .get(header::CONTENT_LENGTH)
-                    .and_then(|h| h.to_str().ok())
-        {
-            return Ok(serde_json::from_value(serde_json::json!(()))?);
-        }
-        let raw_body = response.body().limit(MAX_BODY_SIZE).await?;
-        let body = std::str::from_utf8(&raw_body)?;
-        log::debug!(
-            "WebRequest.json(). method={} url={}, resp='{}'",
-            meta.method,
-            meta.url,
-            body.split_at(512.min(body.len())).0
-        );
-        Ok(serde_json::from_str(body)?)
+/// Counts a request for [`WebClient::drain`] while alive.
+struct Pending(Rc<Cell<usize>>);
+
+impl Pending {
+    fn new(count: &Rc<Cell<usize>>) -> Self {
+        count.set(count.get() + 1);
+        Pending(count.clone())
     }
 }
 
-// this is used internally to translate from HTTP Timeout into default result
-// (empty vec most of the time)
-pub(crate) fn default_on_timeout<T: Default>(err: Error) -> Result<T> {
-    match err {
-        Error::TimeoutError { msg, url, .. } => {
-            log::trace!("timeout getting url {}: {}", url, msg);
-            Ok(Default::default())
-        }
-        _ => Err(err),
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct WebClientBuilder {
-    pub(crate) api_url: Option<Url>,
-    pub(crate) auth: Option<WebAuth>,
-    pub(crate) headers: HeaderMap,
-    pub(crate) timeout: Option<Duration>,
+/// Response body keeping its request [`Pending`] until the end of it.
+struct PendingBody<S> {
+    inner: S,
+    pending: Option<Pending>,
 }
 
-impl WebClientBuilder {
-    pub fn auth_token(mut self, token: &str) -> Self {
-        self.auth = Some(WebAuth::Bearer(token.to_string()));
-        self
-    }
+impl<S: Stream + Unpin> Stream for PendingBody<S> {
+    type Item = S::Item;
 
-    pub fn api_url(mut self, url: Url) -> Self {
-        self.api_url = Some(url);
-        self
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = futures::ready!(this.inner.poll_next_unpin(cx));
+        if item.is_none() {
+            this.pending = None;
+        }
+        Poll::Ready(item)
     }
+}
 
-    pub fn timeout(mut self, timeout: Duration) -> Self {
-        self.timeout = Some(timeout);
-        self
+impl WebRequest<SendClientRequest> {
+    async fn request(self) -> Result<ClientResponse<impl Stream<Item = PayloadResult> + Unpin>> {
+        let meta = self.meta;
+        if meta.options.draining.get() {
+            return Err(meta.observe(Error::ShuttingDown));
+        }
+        let pending = Some(Pending::new(&meta.options.pending));
+        let response = match self.inner_request.await {
+            Ok(response) => response,
+            Err(e) => {
+                return Err(match middleware::chain_error(e) {
+                    Ok(err) => err,
+                    Err(e) => meta.observe(meta.as_request_err(e)),
+                })
+            }
+        };
+        Ok(response.map_body(|_, inner| {
+            let body: Pin<Box<dyn Stream<Item = PayloadResult>>> =
+                Box::pin(PendingBody { inner, pending });
+            body.into()
+        }))
     }
 
-    pub fn header(mut self, name: String, value: String) -> Result<Self> {
-        let name = HeaderName::from_str(name.as_str())?;
-        let value = HeaderValue::from_str(value.as_str())?;
-
-        self.headers.insert(name, value);
-        Ok(self)
+    pub async fn bytes(self) -> Result<Vec<u8>> {
+        let limit = self.meta.options.max_response_size;
+        Ok(self.request().await?.body().limit(limit).await?.to_vec())
     }
 
-    pub fn build(self) -> WebClient {
-        let mut builder = awc::ClientBuilder::new();
-
-        if let Some(timeout) = self.timeout {
-            builder = builder.timeout(timeout);
-        } else {
-            builder = builder.disable_timeout();
+    /// Downloads the body and checks it against the `Content-MD5` header and
+    /// the MD5 or SHA-256 digests of the `Digest` header, whichever are sent.
+    /// A body without any of them is returned unverified.
+    pub async fn bytes_verified(self) -> Result<Bytes> {
+        let meta = self.meta.clone();
+        let mut response = self.request().await?;
+        let expected = expected_digests(response.headers());
+        let body = response
+            .body()
+            .limit(meta.options.max_response_size)
+            .await?;
+        if expected.is_empty() {
+            log::debug!("no digest of {} {}, not verified", meta.method, meta.url);
         }
-        if let Some(auth) = &self.auth {
-            builder = match auth {
-                WebAuth::Bearer(token) => builder.bearer_auth(token),
+        for (algorithm, expected) in expected {
+            let actual = match algorithm {
+                "MD5" => BASE64.encode(Md5::digest(&body)),
+                _ => BASE64.encode(Sha256::digest(&body)),
+            };
+            let matches = BASE64
+                .decode(&expected)
+                .is_ok_and(|digest| BASE64.encode(digest) == actual);
+            if !matches {
+                return Err(Error::IntegrityError {
+                    algorithm,
+                    expected,
+                    actual,
+                });
             }
         }
-        for (key, value) in self.headers.iter() {
-            builder = builder.add_default_header((key.clone(), value.clone()));
-        }
+        Ok(body)
+    }
 
-        WebClient {
-            base_url: Rc::new(self.api_url.unwrap_or_else(rest_api_url)),
-            awc: builder.finish(),
-        }
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
+        Ok(self.json_with_headers().await?.0)
     }
-}
 
-impl Default for WebClientBuilder {
-    fn default() -> Self {
-        WebClientBuilder {
-            api_url: None,
-            auth: None,
-            headers: HeaderMap::new(),
-            timeout: None,
-        }
+    /// Same as [`json`](Self::json), but also returns the headers of the
+    /// response, eg. for its `ETag` or pagination totals.
+    pub async fn json_with_headers<T: DeserializeOwned>(self) -> Result<(T, HeaderMap)> {
+        self.json_response().await
     }
-}
 
-/// Builder for the query part of the URLs.
-pub struct QueryParamsBuilder<'a> {
-    serializer: form_urlencoded::Serializer<'a, String>,
-}
+    /// Same as [`json`](Self::json), but a `404 Not Found` yields `None`.
+    pub async fn json_found<T: DeserializeOwned>(self) -> Result<Option<T>> {
+        match self.json().await {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::HttpError {
+                code: StatusCode::NOT_FOUND,
+                ..
+            }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 
-impl<'a> Default for QueryParamsBuilder<'a> {
-    fn default() -> Self {
-        let serializer = form_urlencoded::Serializer::new("".into());
-        QueryParamsBuilder { serializer }
+    /// Same as [`json`](Self::json), but a timeout yields `T::default()`, as
+    /// for a long poll which got no events in time.
+    pub async fn json_or_default<T: DeserializeOwned + Default>(self) -> Result<T> {
+        self.json().await.or_else(default_on_timeout)
     }
-}
 
-impl<'a> QueryParamsBuilder<'a> {
-    pub fn put<N: ToString, V: ToString>(mut self, name: N, value: Option<V>) -> Self {
-        if let Some(v) = value {
-            self.serializer
-                .append_pair(&name.to_string().to_lower_camel_case(), &v.to_string());
-        };
-        self
+    /// Reads the response to a [long poll](WebRequest::long_poll), eg. for
+    /// events, where a timeout only means nothing has happened yet and
+    /// yields `T::default()`. Implies `long_poll`.
+    pub async fn json_events<T: DeserializeOwned + Default>(self) -> Result<T> {
+        self.meta.info.long_poll.set(true);
+        self.json_or_default().await
     }
 
-    pub fn build(mut self) -> String {
-        self.serializer.finish()
+    /// Reads a response holding a JSON array element by element, as the body
+    /// arrives, instead of buffering it whole like [`json`](Self::json).
+    ///
+    /// A failed request, non-2xx status or malformed body ends the stream
+    /// with an error item; a `204 No Content` yields no elements. Single
+    /// elements are limited to the
+    /// [`max_response_size`](WebClientBuilder::max_response_size). Requests
+    /// are not retried.
+    pub fn json_stream<T: DeserializeOwned>(self) -> impl Stream<Item = Result<T>> {
+        let limit = self.meta.options.max_response_size;
+        self.request()
+            .map_ok(move |response| {
+                let splitter = match response.status() {
+                    StatusCode::NO_CONTENT => ArraySplitter::finished(),
+                    _ => ArraySplitter::default(),
+                };
+                futures::stream::try_unfold((response, splitter, limit), next_array_element)
+            })
+            .try_flatten_stream()
     }
-}
 
-#[derive(Debug)]
-pub struct Event {
-    pub id: Option<u64>,
-    pub event: String,
-    pub data: String,
-}
-
-impl TryFrom<String> for Event {
-    type Error = Error;
+    /// Reads a `text/event-stream` response event by event, parsing the
+    /// `data` of each of them as JSON.
+    ///
+    /// Any other content type fails with [`Error::EventStreamError`], as do
+    /// malformed events; the stream ends with the response body.
+    pub fn sse_stream<T: DeserializeOwned>(self) -> impl Stream<Item = Result<T>> {
+        let url = self.meta.url.clone();
+        let stream_url = url.clone();
+        let limit = self.meta.options.max_response_size;
+        self.request()
+            .and_then(|response| {
+                let content_type = response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default();
+                let essence = content_type.split(';').next().unwrap_or_default().trim();
+                future::ready(if essence.eq_ignore_ascii_case("text/event-stream") {
+                    Ok(response)
+                } else {
+                    let reason = EventStreamErrorReason::UnexpectedContentType(content_type.into());
+                    Err(Error::event_stream(url, reason))
+                })
+            })
+            .map_ok(move |response| {
+                let state = (response, SseDecoder::default(), stream_url, limit);
+                futures::stream::try_unfold(state, next_sse_event)
+            })
+            .try_flatten_stream()
+    }
 
-    fn try_from(string: String) -> Result<Self> {
-        let mut id = None;
-        let mut event = String::new();
-        let mut data = Vec::<String>::new();
+    /// Reads the response to a resource creation, with the `Location` of the
+    /// new resource and the id taken from its last path segment.
+    pub async fn created(self) -> Result<CreatedResource> {
+        let meta = self.meta.clone();
+        let response = self.request().await?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response_json(&meta, response).await?;
+        let id = location.as_deref().and_then(location_id);
+        Ok(CreatedResource { body, location, id })
+    }
 
-        for line in string.split('\n') {
-            let split = line.splitn(2, ':').collect::<Vec<_>>();
-            if split.len() < 2 {
-                continue;
-            }
+    /// Same as [`json`](Self::json), but also returns the rate limits
+    /// the server (or a gateway in front of it) reported, if any.
+    pub async fn json_with_rate_limit<T: DeserializeOwned>(
+        self,
+    ) -> Result<(T, Option<RateLimitInfo>)> {
+        let (value, headers) = self.json_response().await?;
+        Ok((value, RateLimitInfo::from_headers(&headers)))
+    }
 
-            let value = split[1].trim_start();
-            match split[0] {
-                "event" => event = value.into(),
-                "data" => data.push(value.into()),
-                "id" => {
-                    id = match value.parse::<u64>() {
-                        Ok(id) => Some(id),
-                        _ => None,
-                    }
-                }
-                _ => (),
-            }
-        }
-        if event.is_empty() {
-            return Err(Error::EventStreamError("Missing event entry".into()));
+    async fn json_response<T: DeserializeOwned>(self) -> Result<(T, HeaderMap)> {
+        self.meta.info.reads_json.set(true);
+        let meta = self.meta.clone();
+        let response = self.request().await?;
+        let headers = response.headers().clone();
+        match response_json(&meta, response).await {
+            Ok(value) => Ok((value, headers)),
+            Err(err) => Err(meta.observe(err)),
         }
-        let data = data.join("\n");
-        Ok(Event { id, event, data })
     }
 }
 
-pub trait EventStreamExt<S, E>
+async fn next_array_element<T, S>(
+    (mut body, mut splitter, limit): (S, ArraySplitter, usize),
+) -> Result<Option<(T, (S, ArraySplitter, usize))>>
 where
-    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
-    E: Into<Error>,
+    T: DeserializeOwned,
+    S: Stream<Item = std::result::Result<Bytes, PayloadError>> + Unpin,
 {
-    fn event_stream(self) -> EventStream<S, E>;
+    loop {
+        if let Some(element) = splitter.next_element()? {
+            let element = parse_json(std::str::from_utf8(&element)?)?;
+            return Ok(Some((element, (body, splitter, limit))));
+        }
+        if splitter.is_done() {
+            return Ok(None);
+        }
+        if splitter.buffered() > limit {
+            return Err(PayloadError::Overflow.into());
+        }
+        match body.next().await {
+            Some(chunk) => splitter.push(&chunk?),
+            None => return Err(PayloadError::Incomplete(None).into()),
+        }
+    }
 }
 
-impl<S, E> EventStreamExt<S, E> for S
+async fn next_sse_event<T, S>(
+    (mut body, mut decoder, url, limit): (S, SseDecoder, String, usize),
+) -> Result<Option<(T, (S, SseDecoder, String, usize))>>
 where
-    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
-    E: Into<Error>,
+    T: DeserializeOwned,
+    S: Stream<Item = std::result::Result<Bytes, PayloadError>> + Unpin,
 {
-    fn event_stream(self) -> EventStream<S, E> {
-        EventStream::new(self)
+    loop {
+        if let Some(data) = decoder.next_data()? {
+            return match serde_json::from_str(&data) {
+                Ok(event) => Ok(Some((event, (body, decoder, url, limit)))),
+                Err(e) => {
+                    let reason = EventStreamErrorReason::Parse(e);
+                    Err(Error::event_stream(url, reason))
+                }
+            };
+        }
+        if decoder.buffered() > limit {
+            return Err(PayloadError::Overflow.into());
+        }
+        match body.next().await {
+            Some(chunk) => decoder.push(&chunk?),
+            None => return Ok(None),
+        }
     }
 }
 
-pub struct EventStream<S, E>
-where
-    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
-{
-    inner: Peekable<S>,
-    buffer: BytesMut,
+/// Response to a request creating a resource, see [`WebRequest::created`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreatedResource {
+    /// Parsed response body, `None` if empty.
+    pub body: Option<serde_json::Value>,
+    /// Raw `Location` header, either an absolute or a relative URL.
+    pub location: Option<String>,
+    /// Last non-empty path segment of the `Location`.
+    pub id: Option<String>,
 }
 
-impl<S, E> EventStream<S, E>
+fn location_id(location: &str) -> Option<String> {
+    let path = location.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/')
+        .find(|segment| !segment.is_empty())
+        .filter(|segment| !segment.contains(':'))
+        .map(str::to_string)
+}
+
+/// Rate limits as reported by the `X-RateLimit-*` response headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// Maximum number of requests allowed in the current window.
+    pub limit: u64,
+    /// Number of requests left in the current window.
+    pub remaining: u64,
+    /// When the window resets, as sent by the server. Depending on the gateway
+    /// it is either a number of seconds or a UNIX timestamp.
+    pub reset: u64,
+}
+
+impl RateLimitInfo {
+    /// Parses the rate limit headers, `None` unless all three are present and valid.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        Some(RateLimitInfo {
+            limit: value("x-ratelimit-limit")?,
+            remaining: value("x-ratelimit-remaining")?,
+            reset: value("x-ratelimit-reset")?,
+        })
+    }
+}
+
+async fn response_json<T, S>(meta: &WebRequestMeta, mut response: ClientResponse<S>) -> Result<T>
 where
-    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
-    E: Into<Error>,
+    T: DeserializeOwned,
+    S: Stream<Item = std::result::Result<Bytes, PayloadError>>,
 {
-    pub fn new(stream: S) -> Self {
-        EventStream {
-            inner: stream.peekable(),
-            buffer: BytesMut::new(),
+    // allow empty body and no content (204) to pass smoothly
+    if StatusCode::NO_CONTENT == response.status()
+        || Some("0")
+            == response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|h| h.to_str().ok())
+    {
+        return empty_body(meta);
+    }
+    let content_type = non_json_content_type(&response);
+    let raw_body = response
+        .body()
+        .limit(meta.options.max_response_size)
+        .await?;
+    if let Some(content_type) = content_type {
+        let body = String::from_utf8_lossy(&raw_body);
+        if meta.options.content_type_policy == ContentTypePolicy::ReturnRaw {
+            return Ok(serde_json::from_value(body.into_owned().into())?);
         }
+        return Err(Error::UnexpectedContentType {
+            content_type,
+            snippet: truncate_body(&body, CONTENT_SNIPPET_LEN).into_owned(),
+            method: meta.method.clone(),
+            url: meta.url.clone(),
+        });
+    }
+    let body = std::str::from_utf8(&raw_body)?;
+    let max_len = meta.options.log_body_max_len;
+    if max_len > 0 {
+        log::debug!(
+            "WebRequest.json(). method={} url={} fingerprint={}, resp='{}'",
+            meta.method,
+            meta.url,
+            meta.fingerprint().unwrap_or_default(),
+            truncate_body(body, max_len)
+        );
     }
+    parse_json(body)
+}
 
-    fn next_event(&mut self, start_idx: usize) -> Option<Result<Event>> {
-        let idx = max(0, start_idx as i64 - 1) as usize;
-        if let Some(idx) = Self::find(&self.buffer, b"\n\n", idx) {
-            let bytes = self.buffer.split_to(idx);
-            return String::from_utf8(bytes.to_vec())
-                .map(Event::try_from)
-                .map_err(Error::from)
-                .ok();
+/// Value of an empty response body: `null` for types like `()` or `Option`,
+/// otherwise an empty array for collections.
+fn empty_body<T: DeserializeOwned>(meta: &WebRequestMeta) -> Result<T> {
+    serde_json::from_value(serde_json::Value::Null)
+        .or_else(|_| serde_json::from_value(serde_json::Value::Array(Vec::new())))
+        .map_err(|_| {
+            Error::internal(format!(
+                "empty response to {} {} cannot be read as {}",
+                meta.method,
+                meta.url,
+                std::any::type_name::<T>()
+            ))
+        })
+}
+
+/// Short hash of a request for telling apart requests, or recognizing
+/// repeated ones, in the logs. It is also attached to the errors of a
+/// request, eg. [`Error::HttpError`].
+///
+/// Not meant for anything but logging, see
+/// [`WebRequest::auto_idempotency_key`] for a key recognized by the daemon.
+pub fn request_fingerprint(method: &Method, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    for part in [method.as_str().as_bytes(), path.as_bytes(), body] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hex::encode(&hasher.finalize()[..6])
+}
+
+/// The part of `url` following the host, the whole `url` if it has none.
+pub(crate) fn path_and_query(url: &str) -> &str {
+    match url.find("://") {
+        Some(scheme_end) => {
+            let rest = &url[scheme_end + 3..];
+            rest.find('/').map_or("/", |host_end| &rest[host_end..])
         }
-        None
+        None => url,
     }
+}
 
-    fn find(source: &[u8], find: &[u8], start_idx: usize) -> Option<usize> {
-        let mut find_idx = 0;
-        for (i, b) in source.iter().enumerate().skip(start_idx) {
-            if *b == find[find_idx] {
-                find_idx += 1;
-                if find_idx == find.len() {
-                    return Some(i);
-                }
-            } else {
-                find_idx = 0;
+/// JSON form of `value` with the keys of all objects sorted.
+fn canonical_json<T: Serialize>(value: &T) -> serde_json::Result<serde_json::Value> {
+    fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, sort_keys(v)))
+                    .collect::<serde_json::Map<_, _>>()
+                    .into()
             }
+            serde_json::Value::Array(items) => items.into_iter().map(sort_keys).collect(),
+            other => other,
         }
-        None
     }
+    serde_json::to_value(value).map(sort_keys)
 }
 
-impl<S, E> Stream for EventStream<S, E>
-where
-    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
-    E: Into<Error>,
-{
-    type Item = std::result::Result<Event, Error>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.get_mut();
-        if let Some(result) = this.next_event(0) {
-            return Poll::Ready(Some(result));
+/// Parses a response body, reporting malformed timestamps along with their
+/// path as [`Error::TimestampParse`].
+fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T> {
+    let de = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let field = e.path().to_string();
+        let inner = e.into_inner();
+        match invalid_timestamp_value(&inner.to_string()) {
+            Some(value) => Error::TimestampParse { field, value },
+            None => inner.into(),
         }
+    })
+}
 
-        match Pin::new(&mut this.inner).poll_next(cx) {
-            Poll::Ready(Some(Ok(bytes))) => {
-                let idx = this.buffer.len();
-                this.buffer.extend(bytes);
+/// Delay advertised by the `Retry-After` header, given either in seconds or
+/// as an HTTP date. A date in the past gives a zero delay.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
 
-                if let Some(result) = this.next_event(idx) {
-                    Poll::Ready(Some(result))
-                } else {
-                    if Pin::new(&mut this.inner).poll_peek(cx).is_ready() {
-                        cx.waker().wake_by_ref();
-                    }
-                    Poll::Pending
-                }
-            }
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
-        }
+fn is_not_ready(err: &Error) -> bool {
+    match err {
+        Error::SendRequestError { .. } | Error::TimeoutError { .. } => true,
+        Error::RateLimited { .. } => true,
+        Error::HttpError { code, .. } => code.is_server_error(),
+        _ => false,
     }
 }
 
-/// Macro to facilitate URL formatting for REST API async bindings
-///
-/// Supports query parameters, in addition to working similarly to format!(..).
-/// The only exception being the ident=value syntax, which is not supported.
-///
-/// url_format!("foo") => "foo"
-/// url_format!("foo/{bar}") => "foo" + bar
-/// url_format!("foo/{}", bar) => "foo" + bar
-/// url_format!("foo/{bar}", bar="expr") => not supported
-/// url_format!("foo", #[query] bar) => "foo?bar=" + bar
-macro_rules! url_format {
-    {
-        $path:expr $(,$var:ident)* $(,#[query] $varq:ident)* $(,)?
-    } => {{
-        let mut url = format!( $path $(, $var)* );
-        let query = crate::web::QueryParamsBuilder::default()
-            $( .put( stringify!($varq), $varq ) )*
-            .build();
-        if query.len() > 1 {
-            url = format!("{}?{}", url, query)
+// this is used internally to translate from HTTP Timeout into default result
+// (empty vec most of the time)
+pub(crate) fn default_on_timeout<T: Default>(err: Error) -> Result<T> {
+    match err {
+        Error::TimeoutError { msg, url, .. } => {
+            log::trace!("timeout getting url {}: {}", url, msg);
+            Ok(Default::default())
         }
-        url
-    }};
+        _ => Err(err),
+    }
 }
 
-pub fn url_format_obj<T>(base: &str, params: &T) -> String
-where
-    T: Serialize,
-{
-    let qs = serde_qs::to_string(params).unwrap_or("".to_string());
-    if !qs.is_empty() {
-        format!("{}?{}", base, qs)
-    } else {
-        base.to_string()
-    }
+#[derive(Clone, Debug)]
+pub struct WebClientBuilder {
+    pub(crate) api_url: Option<Url>,
+    pub(crate) auth: Option<WebAuth>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) min_tls_version: TlsVersion,
+    #[cfg(feature = "tls")]
+    pub(crate) tls_root_certs: Vec<openssl::x509::X509>,
+    #[cfg(feature = "tls")]
+    pub(crate) accept_invalid_certs: bool,
+    pub(crate) service_timeouts: HashMap<String, Duration>,
+    pub(crate) log_body_max_len: usize,
+    pub(crate) default_query: Vec<(String, String)>,
+    pub(crate) stream_high_water_mark: usize,
+    pub(crate) max_response_size: usize,
+    pub(crate) builtin_layers: Vec<BuiltinLayer>,
+    pub(crate) replaced_layers: HashMap<BuiltinLayer, Rc<dyn Middleware>>,
+    pub(crate) layers: Vec<Rc<dyn Middleware>>,
+    pub(crate) content_type_policy: ContentTypePolicy,
+    pub(crate) max_allocation_amount: Option<BigDecimal>,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) max_concurrency: Option<usize>,
+    pub(crate) stable_body_key_order: bool,
+    pub(crate) slow_request_threshold: Option<Duration>,
+    pub(crate) compress_requests: Option<Encoding>,
+    pub(crate) compress_min_size: usize,
+    pub(crate) connector: Option<ConnectorConfig>,
+    pub(crate) observer: Observer,
+    /// Reads environment variables, replaced in tests.
+    pub(crate) env_var: fn(&str) -> Option<String>,
 }
 
-#[cfg(test)]
-#[rustfmt::skip]
-mod tests {
-    use bytes::Bytes;
-    use crate::web::EventStream;
-    use futures::{StreamExt, FutureExt, Stream};
-    use crate::Error;
+/// Connection pool settings of the underlying `awc::Connector`, see
+/// [`WebClientBuilder::connector_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ConnectorConfig {
+    max_connections: usize,
+    keep_alive: Duration,
+    conn_lifetime: Duration,
+}
 
-    #[test]
-    fn static_url() {
-        assert_eq!(url_format!("foo"), "foo");
+impl WebClientBuilder {
+    pub fn auth_token(mut self, token: &str) -> Self {
+        self.auth = Some(WebAuth::Bearer(token.to_string()));
+        self
     }
 
-    #[test]
-    fn single_placeholder_url() {
-        let bar = "qux";
-        assert_eq!(url_format!("foo/{}", bar), "foo/qux");
+    pub fn basic_auth(mut self, user: &str, password: &str) -> Self {
+        self.auth = Some(WebAuth::Basic {
+            user: user.to_string(),
+            password: password.to_string(),
+        });
+        self
     }
 
-    #[test]
-    fn single_var_url() {
-        let bar = "qux";
-        assert_eq!(url_format!("foo/{bar}"), "foo/qux");
+    /// Authenticates with a header of the given name instead of
+    /// `Authorization`. It is sent in addition to any [`header`](Self::header),
+    /// even one of the same name.
+    pub fn custom_auth(mut self, header: HeaderName, value: HeaderValue) -> Self {
+        self.auth = Some(WebAuth::Custom { header, value });
+        self
     }
 
-    // compilation error when wrong var name given
-    //    #[test]
-    //    fn wrong_single_var_url() {
-    //        let bar="qux";
-    //        assert_eq!(url_format!("foo/{baz}", bar), "foo/{}");
-    //    }
-
-    #[test]
-    fn multi_var_url() {
-        let bar = "qux";
-        let baz = "quz";
-        assert_eq!(
-            url_format!("foo/{}/fuu/{baz}", bar),
-            "foo/qux/fuu/quz"
-        );
+    /// Authenticates with the app key from the `YAGNA_APPKEY` environment
+    /// variable, failing with [`Error::InvalidAppKey`] if it is not set.
+    ///
+    /// Auth set explicitly, eg. with [`auth_token`](Self::auth_token), takes
+    /// precedence and is kept. Note that [`build`](Self::build) falls back to the same
+    /// variable on its own, this only makes a missing key detectable.
+    pub fn auth_from_env(mut self) -> Result<Self> {
+        if self.auth.is_none() {
+            let token = (self.env_var)(YAGNA_APPKEY_ENV_VAR)
+                .ok_or_else(|| Error::InvalidAppKey(format!("{} not set", YAGNA_APPKEY_ENV_VAR)))?;
+            self.auth = Some(WebAuth::Bearer(token));
+        }
+        Ok(self)
     }
 
-    #[test]
-    fn empty_query_url() {
-        let bar = Option::<String>::None;
-        assert_eq!(url_format!("foo", #[query] bar), "foo");
+    /// Same as [`auth_token`](Self::auth_token), but checks the key with
+    /// [`validate_appkey`] first.
+    pub fn checked_auth_token(self, token: &str) -> Result<Self> {
+        validate_appkey(token)?;
+        Ok(self.auth_token(token))
     }
 
-    #[test]
-    #[rustfmt::skip]
-    fn single_query_url() {
-        let bar= Some("qux");
-        assert_eq!(url_format!("foo", #[query] bar), "foo?bar=qux");
+    pub fn api_url(mut self, url: Url) -> Self {
+        self.api_url = Some(url);
+        self
     }
 
-    #[test]
-    fn mix_query_url() {
-        let bar = Option::<String>::None;
-        let baz = Some("quz");
-        assert_eq!(url_format!("foo", #[query] bar, #[query] baz), "foo?baz=quz");
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the default timeout of requests issued through interfaces of the
+    /// given service, identified by its API path (eg. [`MARKET_API_PATH`]).
+    ///
+    /// Takes precedence over [`timeout`](Self::timeout), while per-request
+    /// timeouts take precedence over it.
+    ///
+    /// [`MARKET_API_PATH`]: crate::model::market::MARKET_API_PATH
+    pub fn service_timeout(mut self, service: &str, timeout: Duration) -> Self {
+        self.service_timeouts.insert(service.to_string(), timeout);
+        self
+    }
+
+    /// Adds a query parameter to the URL of every request made by the client.
+    ///
+    /// Parameters given explicitly for a request take precedence: a default
+    /// is not added when the request URL, or a query added with
+    /// [`WebRequest::query`], already contains a parameter of the same name.
+    pub fn default_query_param(mut self, name: &str, value: &str) -> Self {
+        self.default_query
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets how many events streams like [`WebClient::event_stream`] fetch
+    /// ahead of the consumer, 64 by default.
+    ///
+    /// Once that many are waiting, fetching pauses until the consumer drains
+    /// some of them, so the server is slowed down rather than events dropped.
+    pub fn stream_high_water_mark(mut self, high_water_mark: usize) -> Self {
+        self.stream_high_water_mark = high_water_mark;
+        self
+    }
+
+    /// Fails reading a response body larger than `max` bytes with
+    /// [`PayloadError::Overflow`], 64MiB by default. Streamed responses are
+    /// not limited as a whole, only each of their elements.
+    pub fn max_response_size(mut self, max: usize) -> Self {
+        self.max_response_size = max;
+        self
+    }
+
+    /// Limits response bodies logged at debug level to `max_len` characters,
+    /// 512 by default. Zero disables body logging altogether.
+    pub fn log_body_max_len(mut self, max_len: usize) -> Self {
+        self.log_body_max_len = max_len;
+        self
+    }
+
+    /// Refuses TLS handshakes below the given version, `TLS 1.2` by default.
+    ///
+    /// Connecting to a server offering only older protocols fails with
+    /// [`Error::SendRequestError`] carrying the handshake failure.
+    pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    /// Trusts the PEM encoded certificate in addition to the system roots,
+    /// eg. the private CA of a proxy serving the API over HTTPS. May be
+    /// called more than once.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if the certificate cannot be
+    /// parsed.
+    #[cfg(feature = "tls")]
+    pub fn tls_root_cert(mut self, pem: Vec<u8>) -> Result<Self> {
+        let cert = openssl::x509::X509::from_pem(&pem)
+            .map_err(|e| Error::InvalidArgument(format!("invalid PEM certificate: {}", e)))?;
+        self.tls_root_certs.push(cert);
+        Ok(self)
+    }
+
+    /// Skips verification of server certificates, and thereby any
+    /// protection against a man in the middle. Only meant for development
+    /// against a local daemon with a self-signed certificate.
+    #[cfg(feature = "tls")]
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Adds a middleware wrapping the sending of every request.
+    ///
+    /// Middlewares run in the order they were added, inside the
+    /// [`builtin_layers`](Self::builtin_layers): the first one is the
+    /// outermost, it sees the request first and the response last. The whole
+    /// stack runs after the client has already applied its own settings (URL
+    /// and default query, auth, default headers and timeouts), and ends with
+    /// sending the request and checking the response status. See
+    /// [`Middleware`].
+    pub fn layer(mut self, layer: impl Middleware) -> Self {
+        self.layers.push(Rc::new(layer));
+        self
+    }
+
+    /// Installs only the given built-in layers, in this order, outermost
+    /// first. [`BuiltinLayer::DEFAULT_ORDER`] by default.
+    ///
+    /// Leaving a layer out disables what it does, even if configured, eg.
+    /// the [`retry`](Self::retry) policy without [`BuiltinLayer::Retry`].
+    pub fn builtin_layers(mut self, layers: impl IntoIterator<Item = BuiltinLayer>) -> Self {
+        self.builtin_layers = layers.into_iter().collect();
+        self
+    }
+
+    /// Installs `layer` in place of the built-in one, at its position among
+    /// the [`builtin_layers`](Self::builtin_layers), the settings of which it
+    /// then ignores.
+    pub fn replace_layer(mut self, builtin: BuiltinLayer, layer: impl Middleware) -> Self {
+        self.replaced_layers.insert(builtin, Rc::new(layer));
+        self
+    }
+
+    /// Reports every request to `observer`, replacing the default
+    /// [`NoopObserver`].
+    pub fn observer(mut self, observer: Arc<dyn RequestObserver>) -> Self {
+        self.observer = Observer::new(observer);
+        self
+    }
+
+    /// Collects per-endpoint request metrics into `metrics`, a handle kept
+    /// by the caller to take [`snapshot`](WebClientMetrics::snapshot)s from.
+    /// Takes the place of any [`observer`](Self::observer).
+    pub fn metrics(self, metrics: WebClientMetrics) -> Self {
+        self.observer(Arc::new(metrics))
+    }
+
+    /// Sets how responses are handled which succeed but are not JSON,
+    /// [`ContentTypePolicy::Error`] by default.
+    pub fn on_unexpected_content_type(mut self, policy: ContentTypePolicy) -> Self {
+        self.content_type_policy = policy;
+        self
+    }
+
+    /// Retries requests as the policy allows, see [`BuiltinLayer::Retry`].
+    /// Requests are not retried by default.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Allows at most `max` requests of the client (and all the interfaces
+    /// created from it) to wait for a response at a time. The others queue
+    /// by their [`priority`](WebRequest::priority), then in order of sending.
+    /// Unlimited by default, and when `max` is `0`.
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        self.max_concurrency = Some(max).filter(|max| *max > 0);
+        self
+    }
+
+    /// Keeps at most `max_connections` connections open at a time (`0` for
+    /// no limit), closing those idle for `keep_alive` or open for
+    /// `conn_lifetime`. Requests over the limit wait for a free connection.
+    ///
+    /// Uses the `awc` defaults of 100 connections, kept alive for 15s up to
+    /// 75s, unless set.
+    pub fn connector_config(
+        mut self,
+        max_connections: usize,
+        keep_alive: Duration,
+        conn_lifetime: Duration,
+    ) -> Self {
+        self.connector = Some(ConnectorConfig {
+            max_connections,
+            keep_alive,
+            conn_lifetime,
+        });
+        self
+    }
+
+    /// Serializes the bodies of [`WebRequest::send_json`] and
+    /// [`WebRequest::send_value`] with the keys of all objects sorted, so the
+    /// same value always gives the same bytes, eg. for auditing or golden
+    /// tests. Off by default, as it costs an extra conversion of each body.
+    pub fn stable_body_key_order(mut self, enabled: bool) -> Self {
+        self.stable_body_key_order = enabled;
+        self
+    }
+
+    /// Logs a warning for every request which waits longer than `threshold`
+    /// for its response, eg. to spot a struggling daemon before requests
+    /// start timing out. A zero `threshold` disables it, as does the default.
+    pub fn slow_request_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_request_threshold = Some(threshold).filter(|t| !t.is_zero());
+        self
+    }
+
+    /// Compresses the bodies of [`WebRequest::send_json`] and
+    /// [`WebRequest::send_value`] with `encoding`, unless they are shorter
+    /// than [`compress_min_size`](Self::compress_min_size). Off by default.
+    pub fn compress_requests(mut self, encoding: Encoding) -> Self {
+        self.compress_requests = Some(encoding);
+        self
+    }
+
+    /// Size in bytes below which bodies are sent uncompressed, as
+    /// compressing them would gain little. 1KB by default.
+    pub fn compress_min_size(mut self, bytes: usize) -> Self {
+        self.compress_min_size = bytes;
+        self
+    }
+
+    /// Makes `PaymentApi::create_allocation` refuse, without sending, any
+    /// allocation with a `total_amount` above `amount`. Unlimited by default.
+    pub fn max_allocation_amount(mut self, amount: BigDecimal) -> Self {
+        self.max_allocation_amount = Some(amount);
+        self
+    }
+
+    pub fn header(mut self, name: String, value: String) -> Result<Self> {
+        let name = HeaderName::from_str(name.as_str())?;
+        let value = HeaderValue::from_str(value.as_str())?;
+
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn build(self) -> WebClient {
+        let connector = awc::Connector::new();
+        #[cfg(feature = "tls")]
+        let connector = connector.openssl(
+            tls_connector(
+                self.min_tls_version,
+                &self.tls_root_certs,
+                self.accept_invalid_certs,
+            )
+            .expect("unable to initialize TLS connector"),
+        );
+        let connector = match self.connector {
+            Some(config) => connector
+                .limit(config.max_connections)
+                .conn_keep_alive(config.keep_alive)
+                .conn_lifetime(config.conn_lifetime),
+            None => connector,
+        };
+        let mut builder = awc::ClientBuilder::new().connector(connector);
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        } else {
+            builder = builder.disable_timeout();
+        }
+        let auth = self
+            .auth
+            .clone()
+            .or_else(|| (self.env_var)(YAGNA_APPKEY_ENV_VAR).map(WebAuth::Bearer));
+        if let Some(auth) = &auth {
+            builder = match auth {
+                WebAuth::Bearer(token) => builder.bearer_auth(token),
+                WebAuth::Basic { user, password } => builder.basic_auth(user, Some(password)),
+                WebAuth::Custom { header, value } => {
+                    builder.add_default_header((header.clone(), value.clone()))
+                }
+            }
+        }
+        for (key, value) in self.headers.iter() {
+            builder = builder.add_default_header((key.clone(), value.clone()));
+        }
+
+        WebClient {
+            base_url: Rc::new(with_trailing_slash(
+                self.api_url.clone().unwrap_or_else(rest_api_url),
+            )),
+            awc: builder.finish(),
+            options: Rc::new(self.client_options()),
+            timeout: None,
+        }
+    }
+
+    fn client_options(self) -> ClientOptions {
+        let telemetry = Rc::new(Counters::default());
+        let layers = Layers::new(self.layer_stack(&telemetry), self.max_response_size);
+        let observes = self.builtin_layers.contains(&BuiltinLayer::Observer)
+            && !self.replaced_layers.contains_key(&BuiltinLayer::Observer);
+        ClientOptions {
+            service_timeouts: self.service_timeouts,
+            log_body_max_len: self.log_body_max_len,
+            default_query: self.default_query,
+            stream_high_water_mark: self.stream_high_water_mark,
+            max_response_size: self.max_response_size,
+            layers: Rc::new(layers),
+            content_type_policy: self.content_type_policy,
+            max_allocation_amount: self.max_allocation_amount,
+            telemetry,
+            draining: Cell::new(false),
+            pending: Default::default(),
+            stable_body_key_order: self.stable_body_key_order,
+            observer: Some(self.observer).filter(|_| observes),
+        }
+    }
+
+    /// The built-in layers with something to do, followed by the added ones.
+    fn layer_stack(&self, telemetry: &Rc<Counters>) -> Vec<Rc<dyn Middleware>> {
+        let mut stack = Vec::new();
+        for builtin in &self.builtin_layers {
+            if let Some(layer) = self.replaced_layers.get(builtin) {
+                stack.push(layer.clone());
+                continue;
+            }
+            let layer: Rc<dyn Middleware> = match builtin {
+                BuiltinLayer::Fingerprint => Rc::new(Fingerprint),
+                BuiltinLayer::Retry => {
+                    let unexpected_content_type = match self.content_type_policy {
+                        ContentTypePolicy::Retry { attempts, delay } => Some((attempts, delay)),
+                        _ => None,
+                    };
+                    if self.retry.is_none() && unexpected_content_type.is_none() {
+                        continue;
+                    }
+                    Rc::new(Retry {
+                        policy: self.retry.clone(),
+                        unexpected_content_type,
+                    })
+                }
+                BuiltinLayer::ConcurrencyLimit => match self.max_concurrency {
+                    Some(max) => Rc::new(Limiter::new(max)),
+                    None => continue,
+                },
+                BuiltinLayer::Observer => Rc::new(self.observer.clone()),
+                BuiltinLayer::Telemetry => telemetry.clone(),
+                BuiltinLayer::SlowRequests => match self.slow_request_threshold {
+                    Some(threshold) => Rc::new(SlowRequests(threshold)),
+                    None => continue,
+                },
+                BuiltinLayer::Compression => match self.compress_requests {
+                    Some(encoding) => Rc::new(Compress {
+                        encoding,
+                        min_size: self.compress_min_size,
+                    }),
+                    None => continue,
+                },
+            };
+            stack.push(layer);
+        }
+        stack.extend(self.layers.iter().cloned());
+        stack
+    }
+}
+
+impl Default for WebClientBuilder {
+    fn default() -> Self {
+        WebClientBuilder {
+            api_url: None,
+            auth: None,
+            headers: HeaderMap::new(),
+            timeout: None,
+            min_tls_version: TlsVersion::default(),
+            #[cfg(feature = "tls")]
+            tls_root_certs: Vec::new(),
+            #[cfg(feature = "tls")]
+            accept_invalid_certs: false,
+            service_timeouts: HashMap::new(),
+            log_body_max_len: DEFAULT_LOG_BODY_MAX_LEN,
+            default_query: Vec::new(),
+            stream_high_water_mark: DEFAULT_STREAM_HIGH_WATER_MARK,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            builtin_layers: BuiltinLayer::DEFAULT_ORDER.to_vec(),
+            replaced_layers: HashMap::new(),
+            layers: Vec::new(),
+            content_type_policy: ContentTypePolicy::default(),
+            max_allocation_amount: None,
+            retry: None,
+            max_concurrency: None,
+            stable_body_key_order: false,
+            slow_request_threshold: None,
+            compress_requests: None,
+            compress_min_size: DEFAULT_COMPRESS_MIN_SIZE,
+            connector: None,
+            observer: Observer::default(),
+            env_var: |name| env::var(name).ok(),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn tls_connector(
+    min_version: TlsVersion,
+    root_certs: &[openssl::x509::X509],
+    accept_invalid_certs: bool,
+) -> std::result::Result<openssl::ssl::SslConnector, openssl::error::ErrorStack> {
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode, SslVersion};
+
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    builder.set_min_proto_version(Some(match min_version {
+        TlsVersion::Tls1_2 => SslVersion::TLS1_2,
+        TlsVersion::Tls1_3 => SslVersion::TLS1_3,
+    }))?;
+    for cert in root_certs {
+        // older OpenSSL versions refuse a certificate already in the store
+        if let Err(e) = builder.cert_store_mut().add_cert(cert.clone()) {
+            log::debug!("root certificate not added: {}", e);
+        }
+    }
+    if accept_invalid_certs {
+        log::warn!("TLS certificate verification is disabled");
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+    builder.set_alpn_protos(b"\x02h2\x08http/1.1")?;
+    Ok(builder.build())
+}
+
+/// Builder for the query part of the URLs.
+pub struct QueryParamsBuilder<'a> {
+    serializer: form_urlencoded::Serializer<'a, String>,
+}
+
+impl<'a> Default for QueryParamsBuilder<'a> {
+    fn default() -> Self {
+        let serializer = form_urlencoded::Serializer::new("".into());
+        QueryParamsBuilder { serializer }
+    }
+}
+
+impl<'a> QueryParamsBuilder<'a> {
+    pub fn put<N: ToString, V: ToString>(mut self, name: N, value: Option<V>) -> Self {
+        if let Some(v) = value {
+            self.serializer
+                .append_pair(&name.to_string().to_lower_camel_case(), &v.to_string());
+        };
+        self
+    }
+
+    /// Appends `name` once for each of the values, as expected by filters
+    /// like `?state=Active&state=Pending`. Nothing is appended for no values.
+    pub fn put_all<N, V, I>(mut self, name: N, values: I) -> Self
+    where
+        N: ToString,
+        V: ToString,
+        I: IntoIterator<Item = V>,
+    {
+        let name = name.to_string().to_lower_camel_case();
+        for v in values {
+            self.serializer.append_pair(&name, &v.to_string());
+        }
+        self
+    }
+
+    /// Same as [`put`](Self::put), but in the RFC 3339 form with fractional
+    /// seconds and a `Z` suffix expected by eg. `afterTimestamp`, which the
+    /// `Display` of `DateTime` is not.
+    pub fn put_timestamp<N: ToString>(self, name: N, value: Option<DateTime<Utc>>) -> Self {
+        self.put(
+            name,
+            value.map(|v| v.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        )
+    }
+
+    pub fn build(mut self) -> String {
+        self.serializer.finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Event {
+    pub id: Option<u64>,
+    pub event: String,
+    pub data: String,
+}
+
+impl TryFrom<String> for Event {
+    type Error = Error;
+
+    fn try_from(string: String) -> Result<Self> {
+        let mut id = None;
+        let mut event = String::new();
+        let mut data = Vec::<String>::new();
+
+        for line in string.split('\n') {
+            let split = line.splitn(2, ':').collect::<Vec<_>>();
+            if split.len() < 2 {
+                continue;
+            }
+
+            let value = split[1].trim_start();
+            match split[0] {
+                "event" => event = value.into(),
+                "data" => data.push(value.into()),
+                "id" => id = value.parse::<u64>().ok(),
+                _ => (),
+            }
+        }
+        if event.is_empty() {
+            let err = serde::de::Error::custom("missing event entry");
+            return Err(EventStreamErrorReason::Parse(err).into());
+        }
+        let data = data.join("\n");
+        Ok(Event { id, event, data })
+    }
+}
+
+pub trait EventStreamExt<S, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
+    E: Into<Error>,
+{
+    fn event_stream(self) -> EventStream<S, E>;
+}
+
+impl<S, E> EventStreamExt<S, E> for S
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
+    E: Into<Error>,
+{
+    fn event_stream(self) -> EventStream<S, E> {
+        EventStream::new(self)
+    }
+}
+
+pub struct EventStream<S, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
+{
+    inner: Peekable<S>,
+    buffer: BytesMut,
+}
+
+impl<S, E> EventStream<S, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
+    E: Into<Error>,
+{
+    pub fn new(stream: S) -> Self {
+        EventStream {
+            inner: stream.peekable(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    fn next_event(&mut self, start_idx: usize) -> Option<Result<Event>> {
+        let idx = max(0, start_idx as i64 - 1) as usize;
+        if let Some(idx) = Self::find(&self.buffer, b"\n\n", idx) {
+            let bytes = self.buffer.split_to(idx);
+            return String::from_utf8(bytes.to_vec())
+                .map(Event::try_from)
+                .map_err(Error::from)
+                .ok();
+        }
+        None
+    }
+
+    fn find(source: &[u8], find: &[u8], start_idx: usize) -> Option<usize> {
+        let mut find_idx = 0;
+        for (i, b) in source.iter().enumerate().skip(start_idx) {
+            if *b == find[find_idx] {
+                find_idx += 1;
+                if find_idx == find.len() {
+                    return Some(i);
+                }
+            } else {
+                find_idx = 0;
+            }
+        }
+        None
+    }
+}
+
+impl<S, E> Stream for EventStream<S, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'static,
+    E: Into<Error>,
+{
+    type Item = std::result::Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(result) = this.next_event(0) {
+            return Poll::Ready(Some(result));
+        }
+
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                let idx = this.buffer.len();
+                this.buffer.extend(bytes);
+
+                if let Some(result) = this.next_event(idx) {
+                    Poll::Ready(Some(result))
+                } else {
+                    if Pin::new(&mut this.inner).poll_peek(cx).is_ready() {
+                        cx.waker().wake_by_ref();
+                    }
+                    Poll::Pending
+                }
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Fetches items of the stream in the background, at most `high_water_mark`
+/// ahead of the consumer.
+///
+/// When the buffer is full the fetching pauses, applying backpressure on the
+/// source. Dropping the returned stream drops the source as well. Must be
+/// called within an actix runtime.
+pub(crate) fn bounded_stream<S>(stream: S, high_water_mark: usize) -> impl Stream<Item = S::Item>
+where
+    S: Stream + 'static,
+{
+    use futures::SinkExt;
+
+    // the channel holds one item per sender on top of its buffer
+    let (mut tx, rx) = futures::channel::mpsc::channel(high_water_mark.saturating_sub(1));
+    actix_rt::spawn(async move {
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Macro to facilitate URL formatting for REST API async bindings
+///
+/// Supports query parameters, in addition to working similarly to format!(..).
+/// The only exception being the ident=value syntax, which is not supported.
+///
+/// url_format!("foo") => "foo"
+/// url_format!("foo/{bar}") => "foo" + bar
+/// url_format!("foo/{}", bar) => "foo" + bar
+/// url_format!("foo/{bar}", bar="expr") => not supported
+/// url_format!("foo", #[query] bar) => "foo?bar=" + bar
+/// url_format!("foo", #[query_multi] bar) => "foo?bar=" + bar[0] + "&bar=" + bar[1]...
+///
+/// `#[query_multi]` parameters must come after all the `#[query]` ones.
+macro_rules! url_format {
+    {
+        $path:expr $(,$var:ident)* $(,#[query] $varq:ident)* $(,#[query_multi] $varm:ident)* $(,)?
+    } => {{
+        let mut url = format!( $path $(, $var)* );
+        let query = crate::web::QueryParamsBuilder::default()
+            $( .put( stringify!($varq), $varq ) )*
+            $( .put_all( stringify!($varm), $varm ) )*
+            .build();
+        if query.len() > 1 {
+            url = format!("{}?{}", url, query)
+        }
+        url
+    }};
+}
+
+/// Base64 digests announced by the `Content-MD5` and `Digest` headers, by
+/// algorithm. Algorithms other than MD5 and SHA-256 are skipped.
+fn expected_digests(headers: &HeaderMap) -> Vec<(&'static str, String)> {
+    let mut digests = Vec::new();
+    if let Some(md5) = headers.get("content-md5").and_then(|v| v.to_str().ok()) {
+        digests.push(("MD5", md5.trim().to_string()));
+    }
+    let entries = headers
+        .get_all("digest")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+    for entry in entries {
+        let (algorithm, digest) = match entry.split_once('=') {
+            Some((algorithm, digest)) => (algorithm.trim(), digest.trim()),
+            None => continue,
+        };
+        let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+            "md5" => "MD5",
+            "sha-256" => "SHA-256",
+            _ => continue,
+        };
+        digests.push((algorithm, digest.to_string()));
+    }
+    digests
+}
+
+/// The content type of a response with a body, unless it is JSON.
+pub(crate) fn non_json_content_type<S>(response: &ClientResponse<S>) -> Option<String> {
+    let length = response.headers().get(header::CONTENT_LENGTH);
+    if response.status() == StatusCode::NO_CONTENT || length.map(|v| v.as_bytes()) == Some(b"0") {
+        return None;
+    }
+    let content_type = response.headers().get(header::CONTENT_TYPE)?;
+    Some(String::from_utf8_lossy(content_type.as_bytes()).into_owned())
+        .filter(|content_type| !is_json(content_type))
+}
+
+/// Fails on an error status, with the message read from the body of the
+/// response, as [`Error::RateLimited`] for `429` and `503`.
+pub(crate) async fn check_status(
+    mut response: ClientResponse,
+    method: &Method,
+    url: &str,
+    max_response_size: usize,
+) -> Result<ClientResponse> {
+    log::trace!("{:?}", response.headers());
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|v| v.as_bytes() == b"application/json")
+        .unwrap_or_default();
+    let retry_after = retry_after(response.headers());
+    let (method, url) = (method.clone(), url.to_string());
+    let bytes = match response.body().limit(max_response_size).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return Err(Error::from_response(
+                status,
+                e.to_string(),
+                None,
+                method,
+                url,
+            ))
+        }
+    };
+    let body = String::from_utf8_lossy(&bytes).to_string();
+    let msg = if is_json {
+        serde_json::from_slice(&bytes)
+            .map(|e: ErrorMessage| e.message.unwrap_or_default())
+            .unwrap_or_else(|e| format!("error parsing error msg: {}", e))
+    } else {
+        body.clone()
+    };
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        Err(Error::RateLimited {
+            code: status,
+            msg,
+            method,
+            url,
+            fingerprint: None,
+            retry_after,
+            body: Some(body),
+        })
+    } else {
+        Err(Error::from_response(status, msg, Some(body), method, url))
+    }
+}
+
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.eq_ignore_ascii_case("application/json") || essence.ends_with("+json")
+}
+
+/// Cuts the body down to `max_len` characters for logging.
+pub(crate) fn truncate_body(body: &str, max_len: usize) -> std::borrow::Cow<'_, str> {
+    match body.char_indices().nth(max_len) {
+        Some((end, _)) => {
+            format!("{}…(truncated, total {} bytes)", &body[..end], body.len()).into()
+        }
+        None => body.into(),
+    }
+}
+
+pub fn url_format_obj<T>(base: &str, params: &T) -> String
+where
+    T: Serialize,
+{
+    let qs = serde_qs::to_string(params).unwrap_or("".to_string());
+    if !qs.is_empty() {
+        format!("{}?{}", base, qs)
+    } else {
+        base.to_string()
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod tests {
+    use bytes::Bytes;
+    use crate::web::EventStream;
+    use crate::web::mock::{LogCapture, MockResponse, MockServer};
+    use futures::{StreamExt, FutureExt, Stream};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    use crate::Error;
+
+    #[test]
+    fn static_url() {
+        assert_eq!(url_format!("foo"), "foo");
+    }
+
+    #[test]
+    fn single_placeholder_url() {
+        let bar = "qux";
+        assert_eq!(url_format!("foo/{}", bar), "foo/qux");
+    }
+
+    #[test]
+    fn single_var_url() {
+        let bar = "qux";
+        assert_eq!(url_format!("foo/{bar}"), "foo/qux");
+    }
+
+    // compilation error when wrong var name given
+    //    #[test]
+    //    fn wrong_single_var_url() {
+    //        let bar="qux";
+    //        assert_eq!(url_format!("foo/{baz}", bar), "foo/{}");
+    //    }
+
+    #[test]
+    fn multi_var_url() {
+        let bar = "qux";
+        let baz = "quz";
+        assert_eq!(
+            url_format!("foo/{}/fuu/{baz}", bar),
+            "foo/qux/fuu/quz"
+        );
+    }
+
+    #[test]
+    fn empty_query_url() {
+        let bar = Option::<String>::None;
+        assert_eq!(url_format!("foo", #[query] bar), "foo");
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn single_query_url() {
+        let bar= Some("qux");
+        assert_eq!(url_format!("foo", #[query] bar), "foo?bar=qux");
+    }
+
+    #[test]
+    fn mix_query_url() {
+        let bar = Option::<String>::None;
+        let baz = Some("quz");
+        assert_eq!(url_format!("foo", #[query] bar, #[query] baz), "foo?baz=quz");
+    }
+
+    #[test]
+    fn multi_query_url() {
+        let bar = Some("qux");
+        let baz = Some("quz");
+        assert_eq!(url_format!("foo", #[query] bar, #[query] baz), "foo?bar=qux&baz=quz");
+    }
+
+    #[test]
+    fn repeated_query_url() {
+        let state: Vec<&str> = Vec::new();
+        assert_eq!(url_format!("foo", #[query_multi] state), "foo");
+        let state = ["Active"];
+        assert_eq!(url_format!("foo", #[query_multi] state), "foo?state=Active");
+        let max_events = Some(5);
+        let state = vec!["Active", "Pending", "Terminated"];
+        assert_eq!(
+            url_format!("foo", #[query] max_events, #[query_multi] state),
+            "foo?maxEvents=5&state=Active&state=Pending&state=Terminated"
+        );
+    }
+
+    #[test]
+    fn multi_var_and_query_url() {
+        let bar = "baara";
+        let baz = 0;
+        let qar = Some(true);
+        let qaz = Some(3);
+        assert_eq!(
+            url_format!(
+                "foo/{bar}/fuu/{baz}",
+                #[query] qar,
+                #[query] qaz
+            ),
+            "foo/baara/fuu/0?qar=true&qaz=3"
+        );
+    }
+
+    async fn verify_stream<S, F>(f: F) -> anyhow::Result<()>
+    where
+        S: Stream<Item = std::result::Result<Bytes, Error>> + Unpin + 'static,
+        F: Fn(&'static str) -> EventStream<S, Error>,
+    {
+        let src = r#"
+:ping
+event: stdout
+data: some
+data: output
+id: 1
+
+:ping
+
+event: stderr
+data:
+id: 2
+
+event: stdout
+data: 0
+id
+
+"#;
+        let stream = f(src);
+        let events = stream.collect::<Vec<_>>().await;
+
+        assert_eq!(events.len(), 4);
+        let mut iter = events.into_iter();
+
+        let event = iter.next().unwrap()?;
+        assert_eq!(event.event, "stdout".to_string());
+        assert_eq!(event.data, "some\noutput".to_string());
+        assert_eq!(event.id, Some(1));
+
+        assert!(iter.next().unwrap().is_err());
+
+        let event = iter.next().unwrap()?;
+        assert_eq!(event.event, "stderr".to_string());
+        assert_eq!(event.data, "".to_string());
+        assert_eq!(event.id, Some(2));
+
+        let event = iter.next().unwrap()?;
+        assert_eq!(event.event, "stdout".to_string());
+        assert_eq!(event.data, "0".to_string());
+        assert_eq!(event.id, None);
+
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn event_stream() {
+        verify_stream(|s| {
+            let stream = futures::stream::once(async move { Ok::<_, Error>(Bytes::from(s.to_string().into_bytes()))}.boxed_local());
+            EventStream::new(stream)
+        }).await.unwrap();
+
+        verify_stream(|s| {
+            let stream = futures::stream::iter(s.as_bytes()).chunks(5).map(|v| {
+                Ok::<_, Error>(Bytes::from(v.iter().map(|b| **b).collect::<Vec<_>>()))
+            });
+            EventStream::new(stream)
+        }).await.unwrap();
+
+        verify_stream(|s| {
+            let stream = futures::stream::iter(s.as_bytes()).chunks(1).map(|v| {
+                Ok::<_, Error>(Bytes::from(v.iter().map(|b| **b).collect::<Vec<_>>()))
+            });
+            EventStream::new(stream)
+        }).await.unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    mod tls {
+        use openssl::asn1::Asn1Time;
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::Rsa;
+        use openssl::ssl::{SslAcceptor, SslMethod};
+        use openssl::x509::extension::SubjectAlternativeName;
+        use openssl::x509::{X509NameBuilder, X509};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        use crate::web::WebClient;
+
+        /// Serves `true` over HTTPS with a fresh self-signed certificate for
+        /// `127.0.0.1`, returned in PEM together with the base url.
+        fn https_server() -> (Vec<u8>, url::Url) {
+            let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+            let mut name = X509NameBuilder::new().unwrap();
+            name.append_entry_by_text("CN", "127.0.0.1").unwrap();
+            let name = name.build();
+            let mut cert = X509::builder().unwrap();
+            cert.set_version(2).unwrap();
+            cert.set_subject_name(&name).unwrap();
+            cert.set_issuer_name(&name).unwrap();
+            cert.set_pubkey(&key).unwrap();
+            cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+                .unwrap();
+            cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+                .unwrap();
+            let san = SubjectAlternativeName::new()
+                .ip("127.0.0.1")
+                .build(&cert.x509v3_context(None, None))
+                .unwrap();
+            cert.append_extension(san).unwrap();
+            cert.sign(&key, MessageDigest::sha256()).unwrap();
+            let cert = cert.build();
+
+            let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+            acceptor.set_private_key(&key).unwrap();
+            acceptor.set_certificate(&cert).unwrap();
+            let acceptor = acceptor.build();
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("https://{}/", listener.local_addr().unwrap());
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let mut stream = match acceptor.accept(stream) {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => head.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                          content-length: 4\r\nconnection: close\r\n\r\ntrue",
+                    );
+                }
+            });
+            (cert.to_pem().unwrap(), url.parse().unwrap())
+        }
+
+        #[actix_rt::test]
+        async fn custom_root_cert_is_trusted() {
+            let (pem, url) = https_server();
+            let get =
+                |client: WebClient| async move { client.get("ok").send().json::<bool>().await };
+
+            let untrusted = WebClient::builder().api_url(url.clone()).build();
+            assert!(matches!(
+                get(untrusted).await,
+                Err(crate::Error::SendRequestError { .. })
+            ));
+            let trusted = WebClient::builder()
+                .api_url(url.clone())
+                .tls_root_cert(pem.clone())
+                .unwrap()
+                .tls_root_cert(pem)
+                .unwrap()
+                .build();
+            assert!(get(trusted).await.unwrap());
+            let unverified = WebClient::builder()
+                .api_url(url)
+                .danger_accept_invalid_certs(true)
+                .build();
+            assert!(get(unverified).await.unwrap());
+        }
+
+        #[test]
+        fn invalid_root_cert_is_rejected() {
+            let result = WebClient::builder().tls_root_cert(b"not a certificate".to_vec());
+            assert!(matches!(result, Err(crate::Error::InvalidArgument(_))));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn builder_stores_min_tls_version() {
+        use crate::web::{TlsVersion, WebClient};
+
+        let builder = WebClient::builder();
+        assert_eq!(builder.min_tls_version, TlsVersion::Tls1_2);
+
+        let builder = builder.min_tls_version(TlsVersion::Tls1_3);
+        assert_eq!(builder.min_tls_version, TlsVersion::Tls1_3);
+        let _client = builder.build();
+    }
+
+    #[actix_rt::test]
+    async fn service_timeout_applies_per_interface() {
+        use crate::market::MarketRequestorApi;
+        use crate::model::{market::MARKET_API_PATH, payment::PAYMENT_API_PATH};
+        use crate::payment::PaymentApi;
+        use crate::web::WebClient;
+
+        let server =
+            MockServer::start(|_| MockResponse::json(json!([])).delay(Duration::from_millis(300)));
+        let client = WebClient::builder()
+            .timeout(Duration::from_millis(50))
+            .service_timeout(MARKET_API_PATH, Duration::from_secs(5))
+            .service_timeout(PAYMENT_API_PATH, Duration::from_millis(100))
+            .build();
+
+        let market: MarketRequestorApi = client.interface_at(server.url()).unwrap();
+        assert!(market.get_demands().await.unwrap().is_empty());
+
+        let payment: PaymentApi = client.interface_at(server.url()).unwrap();
+        match payment.get_requestor_accounts().await {
+            Err(Error::TimeoutError { .. }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn request_timeout_overrides_service_timeout() {
+        use crate::web::{WebClient, WebInterface};
+
+        struct Probe(WebClient);
+        impl WebInterface for Probe {
+            const API_URL_ENV_VAR: &'static str = "YA_CLIENT_PROBE_URL";
+            const API_SUFFIX: &'static str = "/probe-api/v1";
+            fn from_client(client: WebClient) -> Self {
+                Probe(client)
+            }
+        }
+
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!("ok")).delay(Duration::from_millis(300))
+        });
+        let client = WebClient::builder()
+            .service_timeout(Probe::API_SUFFIX, Duration::from_millis(100))
+            .build();
+        let Probe(probe) = client.interface_at(server.url()).unwrap();
+
+        assert!(probe.get("status").send().json::<String>().await.is_err());
+        let body: String = probe
+            .get("status")
+            .timeout(Duration::from_secs(5))
+            .send()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+    }
+
+    #[actix_rt::test]
+    async fn request_timeout_maps_to_timeout_error() {
+        let server =
+            MockServer::start(|_| MockResponse::json(json!([1])).delay(Duration::from_millis(300)));
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .timeout(Duration::from_secs(5))
+            .build();
+
+        let result = client
+            .get("collect")
+            .timeout(Duration::from_millis(50))
+            .send()
+            .json::<Vec<u32>>()
+            .await;
+        assert!(
+            matches!(result, Err(Error::TimeoutError { .. })),
+            "{:?}",
+            result
+        );
+        let collected: Vec<u32> = result.or_else(super::default_on_timeout).unwrap();
+        assert!(collected.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn json_or_default_only_swallows_timeouts() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/events" => MockResponse::json(json!([1])).delay(Duration::from_millis(300)),
+            _ => MockResponse::new(500),
+        });
+        let client = server.client();
+
+        let events: Vec<u32> = client
+            .get("events")
+            .timeout(Duration::from_millis(50))
+            .send()
+            .json_or_default()
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+        let failed = client.get("broken").send().json_or_default::<Vec<u32>>().await;
+        assert!(matches!(failed, Err(Error::HttpError { .. })), "{:?}", failed);
+    }
+
+    #[actix_rt::test]
+    async fn long_poll_timeout_is_not_an_error() {
+        let server =
+            MockServer::start(|_| MockResponse::json(json!([1])).delay(Duration::from_millis(300)));
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .retry(super::RetryPolicy::new(3).base_delay(Duration::from_millis(10)))
+            .slow_request_threshold(Duration::from_millis(10))
+            .build();
+        let poll = || client.get("events").timeout(Duration::from_millis(50));
+
+        let logs = LogCapture::start();
+        let events: Vec<u32> = poll().long_poll().send().json_events().await.unwrap();
+        assert!(events.is_empty());
+        let events: Vec<u32> = poll().send().json_events().await.unwrap();
+        assert!(events.is_empty());
+        assert_eq!(server.requests().len(), 2);
+        assert!(!logs
+            .records()
+            .iter()
+            .any(|(_, msg)| msg.starts_with("slow request")));
+
+        let result = poll().send().json::<Vec<u32>>().await;
+        assert!(
+            matches!(result, Err(Error::TimeoutError { .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[test]
+    fn well_formed_appkey() {
+        use crate::web::{validate_appkey, validate_appkey_strict, WebClient};
+
+        assert!(validate_appkey("6f9b1d2a4c8e4e3f9a1b2c3d4e5f6a7b").is_ok());
+        assert!(validate_appkey_strict("6f9b1d2a4c8e4e3f9a1b2c3d4e5f6a7b").is_ok());
+        assert!(WebClient::builder()
+            .checked_auth_token("6f9b1d2a4c8e4e3f9a1b2c3d4e5f6a7b")
+            .is_ok());
+        // unexpected length is tolerated unless strict
+        assert!(validate_appkey("my-custom_key").is_ok());
+        assert!(validate_appkey_strict("my-custom_key").is_err());
+    }
+
+    #[test]
+    fn malformed_appkey() {
+        use crate::web::{validate_appkey, WebClient};
+
+        assert!(matches!(validate_appkey(""), Err(Error::InvalidAppKey(_))));
+        assert!(matches!(
+            validate_appkey("6f9b1d2a4c8e 4e3f9a1b2c3d4e5f6a7b\n"),
+            Err(Error::InvalidAppKey(_))
+        ));
+        assert!(matches!(
+            validate_appkey("\"6f9b1d2a4c8e4e3f9a1b2c3d4e5f6a7b\""),
+            Err(Error::InvalidAppKey(_))
+        ));
+        assert!(WebClient::builder()
+            .checked_auth_token("Bearer 6f9b1d2a")
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn logged_body_is_truncated() {
+        use crate::web::WebClient;
+
+        let long = "x".repeat(1000);
+        let body = json!(long).to_string();
+        let server = MockServer::start(move |_| MockResponse::json(json!(long)));
+
+        let client = WebClient::builder().api_url(server.url()).log_body_max_len(16).build();
+        let logs = LogCapture::start();
+        let _: String = client.get("big").send().json().await.unwrap();
+
+        let expected = format!("resp='{}…(truncated, total {} bytes)'", &body[..16], body.len());
+        assert!(logs.records().iter().any(|(_, msg)| msg.ends_with(&expected)));
+    }
+
+    #[actix_rt::test]
+    async fn zero_log_body_max_len_disables_body_log() {
+        use crate::web::WebClient;
+
+        let server = MockServer::start(|_| MockResponse::json(json!("secret")));
+        let client = WebClient::builder().api_url(server.url()).log_body_max_len(0).build();
+        let logs = LogCapture::start();
+        let _: String = client.get("small").send().json().await.unwrap();
+
+        assert!(!logs.records().iter().any(|(_, msg)| msg.contains("secret")));
+    }
+
+    #[actix_rt::test]
+    async fn slow_requests_are_logged() {
+        use crate::web::WebClient;
+
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/slow" => MockResponse::json(json!(1)).delay(Duration::from_millis(100)),
+            _ => MockResponse::json(json!(1)),
+        });
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .slow_request_threshold(Duration::from_millis(50))
+            .build();
+        let logs = LogCapture::start();
+        let _: u32 = client.get("fast").send().json().await.unwrap();
+        let _: u32 = client.get("slow").send().json().await.unwrap();
+
+        let warnings: Vec<_> = logs
+            .records()
+            .into_iter()
+            .filter(|(level, _)| *level == log::Level::Warn)
+            .map(|(_, msg)| msg)
+            .collect();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        let prefix = format!("slow request: GET {}slow [", server.url());
+        assert!(warnings[0].starts_with(&prefix), "{}", warnings[0]);
+        assert!(warnings[0].contains("] took "), "{}", warnings[0]);
+        assert!(warnings[0].ends_with("ms"), "{}", warnings[0]);
+    }
+
+    #[actix_rt::test]
+    async fn json_with_rate_limit_parses_headers() {
+        use crate::web::RateLimitInfo;
+
+        let server = MockServer::start(|req| {
+            let response = MockResponse::json(json!("ok"));
+            if req.path == "/limited" {
+                response
+                    .header("X-RateLimit-Limit", "100")
+                    .header("X-RateLimit-Remaining", "42")
+                    .header("X-RateLimit-Reset", "1700000000")
+            } else {
+                response
+            }
+        });
+        let client = server.client();
+
+        let (body, rate_limit): (String, _) = client
+            .get("limited")
+            .send()
+            .json_with_rate_limit()
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+        assert_eq!(
+            rate_limit,
+            Some(RateLimitInfo {
+                limit: 100,
+                remaining: 42,
+                reset: 1_700_000_000
+            })
+        );
+
+        let (_, rate_limit): (String, _) = client
+            .get("free")
+            .send()
+            .json_with_rate_limit()
+            .await
+            .unwrap();
+        assert_eq!(rate_limit, None);
+    }
+
+    #[actix_rt::test]
+    async fn json_found_maps_only_not_found_to_none() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/offers/1" => MockResponse::json(json!({"id": 1})),
+            "/offers/2" => MockResponse::new(404),
+            _ => MockResponse::new(500),
+        });
+        let client = server.client();
+        let get = |id: u32| client.get(&format!("offers/{}", id)).send();
+
+        let found: Option<serde_json::Value> = get(1).json_found().await.unwrap();
+        assert_eq!(found, Some(json!({"id": 1})));
+        let missing: Option<serde_json::Value> = get(2).json_found().await.unwrap();
+        assert_eq!(missing, None);
+        let failed = get(3).json_found::<serde_json::Value>().await;
+        assert!(matches!(
+            failed,
+            Err(Error::HttpError {
+                code: awc::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn base_url_without_trailing_slash_keeps_last_segment() {
+        use crate::web::{WebClient, WebInterface};
+
+        struct Probe(WebClient);
+        impl WebInterface for Probe {
+            const API_URL_ENV_VAR: &'static str = "YA_CLIENT_SLASH_PROBE_URL";
+            const API_SUFFIX: &'static str = "/probe-api/v1";
+            fn from_client(client: WebClient) -> Self {
+                Probe(client)
+            }
+        }
+
+        let logs = LogCapture::start();
+        for base in ["http://127.0.0.1:7465/api", "http://127.0.0.1:7465/api/"] {
+            let client = WebClient::builder().api_url(base.parse().unwrap()).build();
+            assert_eq!(
+                client.url("offer").unwrap().as_str(),
+                "http://127.0.0.1:7465/api/offer"
+            );
+            let Probe(rebased) = client.interface_at(None).unwrap();
+            assert_eq!(
+                rebased.url("offer").unwrap().as_str(),
+                "http://127.0.0.1:7465/api/probe-api/v1/offer"
+            );
+            let explicit: url::Url = "http://127.0.0.1:7465/other/v2".parse().unwrap();
+            let Probe(explicit) = client.interface_at(explicit).unwrap();
+            assert_eq!(
+                explicit.url("offer").unwrap().as_str(),
+                "http://127.0.0.1:7465/other/v2/offer"
+            );
+        }
+        let warnings = logs
+            .records()
+            .into_iter()
+            .filter(|(level, msg)| {
+                *level == log::Level::Warn && msg.contains("does not end with a slash")
+            })
+            .count();
+        assert_eq!(warnings, 3);
+    }
+
+    #[actix_rt::test]
+    async fn client_from_parts_sends_to_base_url() {
+        use crate::payment::PaymentApi;
+        use crate::web::WebClient;
+
+        let server = MockServer::start(|_| MockResponse::json(json!([])));
+        let client = WebClient::from_parts(server.url(), awc::Client::default());
+
+        let _: Vec<i32> = client.get("offers").send().json().await.unwrap();
+        let payment: PaymentApi = client
+            .interface_at(server.url().join("payment-api/v1/").unwrap())
+            .unwrap();
+        payment.get_requestor_accounts().await.unwrap();
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/offers", "/payment-api/v1/requestorAccounts"]);
+    }
+
+    #[actix_rt::test]
+    async fn event_stream_rejects_error_status() {
+        use crate::error::EventStreamErrorReason;
+
+        let server = MockServer::start(|_| MockResponse::new(404));
+        let result = server.client().event_stream("activity/a/exec/b").await;
+
+        match result {
+            Err(Error::EventStreamError {
+                url,
+                reason: EventStreamErrorReason::UnexpectedStatus(code),
+            }) => {
+                assert_eq!(code, awc::http::StatusCode::NOT_FOUND);
+                assert_eq!(url, format!("{}activity/a/exec/b", server.url()));
+            }
+            Err(e) => panic!("unexpected {:?}", e),
+            Ok(_) => panic!("unexpected stream"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn default_query_params_are_merged() {
+        use crate::web::WebClient;
+
+        let server = MockServer::start(|_| MockResponse::json(json!([])));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .default_query_param("tenant", "x")
+            .default_query_param("region", "eu")
+            .build();
+
+        let max_events = Some(5);
+        let region = Some("us");
+        let url = url_format!("events", #[query] max_events, #[query] region);
+        let _: Vec<i32> = client.get(&url).send().json().await.unwrap();
+        let _: Vec<i32> = client.get("offers").send().json().await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/events?maxEvents=5&region=us&tenant=x");
+        assert_eq!(requests[1].path, "/offers?tenant=x&region=eu");
+    }
+
+    #[actix_rt::test]
+    async fn runtime_query_is_appended() {
+        use crate::web::{QueryParamsBuilder, WebClient};
+
+        let server = MockServer::start(|_| MockResponse::json(json!([])));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .default_query_param("tenant", "x")
+            .build();
+        let filters = [("state", "Active"), ("state", "Pending")];
+        let query = filters
+            .iter()
+            .fold(QueryParamsBuilder::default(), |query, (name, value)| {
+                query.put(name, Some(value))
+            });
+
+        let _: Vec<i32> = client.get("activity").query(query).send().json().await.unwrap();
+        let _: Vec<i32> = client
+            .get("activity")
+            .query(QueryParamsBuilder::default())
+            .send()
+            .json()
+            .await
+            .unwrap();
+        let max_events = Some(2);
+        let _: Vec<i32> = client
+            .get(&url_format!("events", #[query] max_events))
+            .query(QueryParamsBuilder::default().put("appSessionId", Some("s")))
+            .send()
+            .json()
+            .await
+            .unwrap();
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/activity?tenant=x&state=Active&state=Pending",
+                "/activity?tenant=x",
+                "/events?maxEvents=2&tenant=x&appSessionId=s",
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn runtime_query_overrides_default_params() {
+        use crate::web::{QueryParamsBuilder, WebClient};
+
+        let server = MockServer::start(|_| MockResponse::json(json!([])));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .default_query_param("appSessionId", "a")
+            .default_query_param("tenant", "x")
+            .build();
+        let session = |id| QueryParamsBuilder::default().put("appSessionId", Some(id));
+
+        let _: Vec<i32> = client
+            .get("events")
+            .query(session("b"))
+            .send()
+            .json()
+            .await
+            .unwrap();
+        let _: Vec<i32> = client
+            .get("events?appSessionId=c")
+            .query(session("d"))
+            .send()
+            .json()
+            .await
+            .unwrap();
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/events?tenant=x&appSessionId=b",
+                "/events?appSessionId=c&tenant=x&appSessionId=d",
+            ]
+        );
+    }
+
+    #[test]
+    fn put_timestamp_formats_rfc3339() {
+        use crate::web::QueryParamsBuilder;
+        use chrono::{DateTime, Utc};
+
+        let timestamp: DateTime<Utc> = "2020-12-21T15:51:21.126645Z".parse().unwrap();
+        let query = QueryParamsBuilder::default()
+            .put_timestamp("after_timestamp", Some(timestamp))
+            .put_timestamp("before", None)
+            .build();
+
+        assert_eq!(query, "afterTimestamp=2020-12-21T15%3A51%3A21.126645Z");
+        let (_, value) = url::form_urlencoded::parse(query.as_bytes()).next().unwrap();
+        assert_eq!(value, "2020-12-21T15:51:21.126645Z");
+        assert_eq!(value.parse::<DateTime<Utc>>().unwrap(), timestamp);
+    }
+
+    #[actix_rt::test]
+    async fn default_query_params_are_encoded_like_builder_params() {
+        use crate::web::{QueryParamsBuilder, WebClient};
+
+        let server = MockServer::start(|_| MockResponse::json(json!([])));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .default_query_param("context", "a b&c=d/é")
+            .build();
+
+        let query = QueryParamsBuilder::default()
+            .put("owner", Some("x y&z"))
+            .build();
+        let _: Vec<i32> = client.get(&format!("offers?{}", query)).send().json().await.unwrap();
+        let _: Vec<i32> = client.get("offers").send().json().await.unwrap();
+        let _: Vec<i32> = client.get("offers?").send().json().await.unwrap();
+
+        let encoded = QueryParamsBuilder::default()
+            .put("context", Some("a b&c=d/é"))
+            .build();
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                format!("/offers?owner=x+y%26z&{}", encoded),
+                format!("/offers?{}", encoded),
+                format!("/offers?{}", encoded),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn bounded_stream_pauses_for_slow_consumer() {
+        use crate::web::bounded_stream;
+
+        let produced = std::rc::Rc::new(AtomicUsize::new(0));
+        let counter = produced.clone();
+        let source = futures::stream::iter(0..100).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut stream = Box::pin(bounded_stream(source, 4));
+        assert_eq!(stream.next().await, Some(0));
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        // the consumed one, the buffered ones and the one waiting to be sent
+        assert!(produced.load(Ordering::SeqCst) <= 1 + 4 + 1);
+
+        let rest: Vec<i32> = stream.collect().await;
+        assert_eq!(rest, (1..100).collect::<Vec<_>>());
+        assert_eq!(produced.load(Ordering::SeqCst), 100);
+    }
+
+    #[actix_rt::test]
+    async fn send_value_posts_json() {
+        let server = MockServer::start(|_| MockResponse::new(204));
+        let body = json!({"name": "job", "tags": ["a", "b"], "budget": 1.5});
+
+        server.client().post("jobs").send_value(&body).json::<()>().await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("content-type"), Some("application/json"));
+        assert_eq!(requests[0].json(), body);
+    }
+
+    #[actix_rt::test]
+    async fn compressed_json_round_trips() {
+        use flate2::read::{GzDecoder, ZlibDecoder};
+        use std::io::Read;
+
+        let server = MockServer::start(|req| {
+            let mut body = String::new();
+            match req.header("content-encoding") {
+                Some("gzip") => GzDecoder::new(&req.body[..]).read_to_string(&mut body),
+                Some("deflate") => ZlibDecoder::new(&req.body[..]).read_to_string(&mut body),
+                _ => (&req.body[..]).read_to_string(&mut body),
+            }
+            .unwrap();
+            MockResponse::new(200)
+                .header("content-type", "application/json")
+                .body(body)
+        });
+        let large = json!({"payments": vec!["0x1234567890abcdef"; 100]});
+        let small = json!({"payments": []});
+
+        for encoding in [super::Encoding::Gzip, super::Encoding::Deflate] {
+            let client = crate::web::WebClient::builder()
+                .api_url(server.url())
+                .compress_requests(encoding)
+                .build();
+            let echoed: serde_json::Value =
+                client.post("batch").send_json(&large).json().await.unwrap();
+            assert_eq!(echoed, large);
+            let echoed: serde_json::Value =
+                client.post("batch").send_json(&small).json().await.unwrap();
+            assert_eq!(echoed, small);
+        }
+
+        let requests = server.requests();
+        let encodings: Vec<_> = requests.iter().map(|r| r.header("content-encoding")).collect();
+        assert_eq!(encodings, [Some("gzip"), None, Some("deflate"), None]);
+        assert!(requests[0].body.len() < large.to_string().len());
+        assert_eq!(requests[0].header("content-type"), Some("application/json"));
+    }
+
+    #[actix_rt::test]
+    async fn hedged_request_wins_over_slow_one() {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                MockResponse::json(json!("slow")).delay(Duration::from_secs(2))
+            } else {
+                MockResponse::json(json!("fast"))
+            }
+        });
+
+        let started = Instant::now();
+        let body: String = server
+            .client()
+            .get("status")
+            .hedge(Duration::from_millis(50))
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(body, "fast");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn hedge_not_fired_for_fast_response() {
+        let server = MockServer::start(|_| MockResponse::json(json!("fast")));
+
+        let body: String = server
+            .client()
+            .get("status")
+            .hedge(Duration::from_secs(1))
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(body, "fast");
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn hedge_rejects_non_idempotent_method() {
+        let server = MockServer::start(|_| MockResponse::json(json!("created")));
+
+        let result = server
+            .client()
+            .post("allocations")
+            .hedge(Duration::from_millis(50))
+            .json::<String>()
+            .await;
+
+        assert!(matches!(result, Err(Error::SendRequestError { .. })));
+        assert!(server.requests().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn wait_until_ready_retries_until_healthy() {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => MockResponse::new(503).body("starting"),
+            _ => MockResponse::json(json!({"version": "0.15.0"})),
+        });
+
+        let client = server.client();
+        assert!(!client.is_alive().await.unwrap());
+        client.wait_until_ready(Duration::from_secs(5)).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.path == "/version/get"));
+    }
+
+    #[actix_rt::test]
+    async fn wait_until_ready_treats_refused_connection_as_not_ready() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = crate::web::WebClient::builder()
+            .api_url(format!("http://{}/", addr).parse().unwrap())
+            .build();
+
+        assert!(!client.is_alive().await.unwrap());
+        match client.wait_until_ready(Duration::from_millis(200)).await {
+            Err(Error::TimeoutError { msg, .. }) => assert!(msg.contains("not ready"), "{}", msg),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn wait_until_ready_fails_on_client_error() {
+        let server = MockServer::start(|_| MockResponse::new(401).body("invalid app key"));
+
+        let result = server.client().wait_until_ready(Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(Error::HttpError { .. })));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    fn html_page() -> MockResponse {
+        MockResponse::new(200)
+            .header("content-type", "text/html; charset=utf-8")
+            .body("<html><body>502 Bad Gateway</body></html>")
+    }
+
+    #[actix_rt::test]
+    async fn html_response_is_reported_with_snippet() {
+        let server = MockServer::start(|_| html_page());
+
+        let result: crate::Result<serde_json::Value> =
+            server.client().get("status").send().json().await;
+        match result {
+            Err(Error::UnexpectedContentType {
+                content_type,
+                snippet,
+                ..
+            }) => {
+                assert_eq!(content_type, "text/html; charset=utf-8");
+                assert!(snippet.contains("502 Bad Gateway"), "{}", snippet);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn html_response_is_retried() {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                html_page()
+            } else {
+                MockResponse::json(json!({"ok": true}))
+            }
+        });
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .on_unexpected_content_type(super::ContentTypePolicy::Retry {
+                attempts: 2,
+                delay: Duration::from_millis(10),
+            })
+            .build();
+
+        let body: serde_json::Value = client
+            .put("status")
+            .send_json(&json!({}))
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body, json!({"ok": true}));
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(server.requests()[1].json(), json!({}));
+        assert_eq!(client.telemetry_snapshot().retries, 1);
+    }
+
+    #[actix_rt::test]
+    async fn html_response_to_post_is_not_retried() {
+        let server = MockServer::start(|_| html_page());
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .on_unexpected_content_type(super::ContentTypePolicy::Retry {
+                attempts: 2,
+                delay: Duration::from_millis(10),
+            })
+            .build();
+
+        let result: crate::Result<serde_json::Value> =
+            client.post("status").send_json(&json!({})).json().await;
+        assert!(matches!(result, Err(Error::UnexpectedContentType { .. })));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn retries_of_html_response_run_out() {
+        let server = MockServer::start(|_| html_page());
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .on_unexpected_content_type(super::ContentTypePolicy::Retry {
+                attempts: 2,
+                delay: Duration::from_millis(10),
+            })
+            .build();
+
+        let result: crate::Result<serde_json::Value> = client.get("status").send().json().await;
+        assert!(matches!(result, Err(Error::UnexpectedContentType { .. })));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn html_response_is_returned_raw() {
+        let server = MockServer::start(|_| html_page());
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .on_unexpected_content_type(super::ContentTypePolicy::ReturnRaw)
+            .build();
+
+        let body: String = client.get("status").send().json().await.unwrap();
+        assert_eq!(body, "<html><body>502 Bad Gateway</body></html>");
+    }
+
+    #[actix_rt::test]
+    async fn auto_idempotency_key_follows_content() {
+        let server = MockServer::start(|_| MockResponse::json(json!(null)));
+        let client = server.client();
+        let send = |body: serde_json::Value| {
+            let request = client.post("allocations").auto_idempotency_key(&body).unwrap();
+            request.send_json(&body).json::<()>()
+        };
+
+        send(json!({"a": 1, "b": "x"})).await.unwrap();
+        send(json!({"b": "x", "a": 1})).await.unwrap();
+        send(json!({"a": 2, "b": "x"})).await.unwrap();
+
+        let keys: Vec<_> = server
+            .requests()
+            .iter()
+            .map(|r| r.header("idempotency-key").unwrap().to_string())
+            .collect();
+        assert_eq!(keys[0].len(), 64);
+        assert_eq!(keys[0], keys[1]);
+        assert_ne!(keys[0], keys[2]);
+    }
+
+    #[actix_rt::test]
+    async fn created_yields_location_id() {
+        let server = MockServer::start(|_| {
+            MockResponse::new(201)
+                .header("location", "/payment-api/v1/allocations/alloc-1")
+                .header("content-type", "application/json")
+                .body(r#"{"allocationId":"alloc-1"}"#)
+        });
+
+        let created = server
+            .client()
+            .post("allocations")
+            .send_json(&json!({}))
+            .created()
+            .await
+            .unwrap();
+        assert_eq!(created.id.as_deref(), Some("alloc-1"));
+        assert_eq!(
+            created.location.as_deref(),
+            Some("/payment-api/v1/allocations/alloc-1")
+        );
+        assert_eq!(created.body, Some(json!({"allocationId": "alloc-1"})));
     }
 
     #[test]
-    fn multi_query_url() {
-        let bar = Some("qux");
-        let baz = Some("quz");
-        assert_eq!(url_format!("foo", #[query] bar, #[query] baz), "foo?bar=qux&baz=quz");
+    fn location_id_takes_last_segment() {
+        use super::location_id;
+        assert_eq!(location_id("http://host:7465/offers/o-1/?x=1").as_deref(), Some("o-1"));
+        assert_eq!(location_id("http://host:7465"), None);
+        assert_eq!(location_id(""), None);
+    }
+
+    #[actix_rt::test]
+    async fn malformed_timestamp_names_field() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!([{
+                "invoiceId": "invoice-1",
+                "eventDate": "2020-13-21 15:51",
+                "eventType": "InvoiceReceivedEvent",
+            }]))
+        });
+
+        let result: crate::Result<Vec<crate::model::payment::InvoiceEvent>> =
+            server.client().get("invoiceEvents").send().json().await;
+        match result {
+            Err(Error::TimestampParse { field, value }) => {
+                assert_eq!(field, "[0].eventDate");
+                assert_eq!(value, "2020-13-21 15:51");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn bytes_verified_checks_digests() {
+        let server = MockServer::start(|req| {
+            let response = MockResponse::new(200).body("artifact");
+            match req.path.as_str() {
+                "/md5" => response.header("content-md5", "jluUikVFFdur/H63GNqlLw=="),
+                "/sha" => response.header(
+                    "digest",
+                    "unixsum=30637, sha-256=x8XB1wxd7EQWq2FYr9CyI+9Awpsdwfl+2UKLlNTK2xw=",
+                ),
+                "/bad" => response.header("digest", "md5=1B2M2Y8AsgTpgAmY7PhCfg=="),
+                _ => response,
+            }
+        });
+        let client = server.client();
+
+        for path in ["md5", "sha", "plain"] {
+            let body = client.get(path).send().bytes_verified().await.unwrap();
+            assert_eq!(body, Bytes::from_static(b"artifact"), "{}", path);
+        }
+        match client.get("bad").send().bytes_verified().await {
+            Err(Error::IntegrityError { algorithm, expected, .. }) => {
+                assert_eq!(algorithm, "MD5");
+                assert_eq!(expected, "1B2M2Y8AsgTpgAmY7PhCfg==");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn version_info_reads_build_metadata() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(json!({
+                "current": {
+                    "version": "0.15.0",
+                    "name": "v0.15.0 Some Release",
+                    "seen": true,
+                    "releaseTs": "2024-02-01T10:00:00",
+                },
+                "pending": {
+                    "version": "0.15.1",
+                    "name": "v0.15.1 Next Release",
+                    "seen": false,
+                    "releaseTs": "2024-03-01T10:00:00",
+                },
+            }))
+        });
+
+        let info = server.client().version_info().await.unwrap();
+        assert_eq!(info.version, "0.15.0");
+        assert_eq!(info.commit, None);
+        assert_eq!(info.build_date.as_deref(), Some("2024-02-01T10:00:00"));
+        assert_eq!(info.requires_update, Some(true));
+        assert_eq!(server.requests()[0].path, "/version/get");
+    }
+
+    #[actix_rt::test]
+    async fn telemetry_counts_requests() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/missing" => MockResponse::new(404),
+            "/slow" => MockResponse::json(json!(null)).delay(Duration::from_millis(300)),
+            _ => MockResponse::json(json!({})),
+        });
+        let client = server.client();
+        assert_eq!(client.telemetry_snapshot(), super::Telemetry::default());
+
+        let _: serde_json::Value = client.get("status").send().json().await.unwrap();
+        let _: serde_json::Value = client.post("items").send_json(&json!({})).json().await.unwrap();
+        let _ = client.delete("missing").send().json::<()>().await;
+        let _ = client.get("slow").timeout(Duration::from_millis(50)).send().json::<()>().await;
+
+        let snapshot = client.telemetry_snapshot();
+        let requests: Vec<_> = snapshot.requests.iter().map(|(m, n)| (m.as_str(), *n)).collect();
+        assert_eq!(requests, vec![("DELETE", 1), ("GET", 2), ("POST", 1)]);
+        assert_eq!(snapshot.responses.success, 2);
+        assert_eq!(snapshot.responses.client_error, 1);
+        assert_eq!(snapshot.timeouts, 1);
+        assert_eq!(snapshot.retries, 0);
+        assert_eq!(snapshot.in_flight, 0);
+    }
+
+    #[actix_rt::test]
+    async fn retry_policy_resends_until_success() {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                MockResponse::new(503).body("booting")
+            } else {
+                MockResponse::json(json!("ready"))
+            }
+        });
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .retry(super::RetryPolicy::new(3).base_delay(Duration::from_millis(10)))
+            .build();
+
+        let logs = LogCapture::start();
+        let body: String = client.get("status").send().json().await.unwrap();
+        assert_eq!(body, "ready");
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(client.telemetry_snapshot().retries, 2);
+        assert!(logs
+            .records()
+            .iter()
+            .any(|(level, msg)| *level == log::Level::Debug && msg.contains("attempt 2 of GET")));
+    }
+
+    #[actix_rt::test]
+    async fn retry_policy_surfaces_last_error() {
+        let server = MockServer::start(|_| MockResponse::new(503).body("still booting"));
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .retry(super::RetryPolicy::new(2).base_delay(Duration::from_millis(10)))
+            .build();
+
+        let result: crate::Result<String> = client.get("status").send().json().await;
+        match result {
+            Err(Error::RateLimited {
+                code,
+                msg,
+                retry_after,
+                ..
+            }) => {
+                assert_eq!(code, awc::http::StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(msg, "still booting");
+                assert_eq!(retry_after, None);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn rate_limited_honors_retry_after() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/seconds" => MockResponse::new(429)
+                .header("content-type", "application/json")
+                .header("retry-after", "5")
+                .body(r#"{"message":"slow down"}"#),
+            "/bare" => MockResponse::new(503),
+            "/garbled" => MockResponse::new(429).header("retry-after", "soon"),
+            _ => MockResponse::new(503).header("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        });
+        let client = server.client();
+
+        match client.get("seconds").send().json::<()>().await {
+            Err(Error::RateLimited {
+                code,
+                msg,
+                retry_after,
+                body,
+                ..
+            }) => {
+                assert_eq!(code, awc::http::StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(retry_after, Some(Duration::from_secs(5)));
+                assert_eq!(msg, "slow down");
+                assert_eq!(body.as_deref(), Some(r#"{"message":"slow down"}"#));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match client.get("date").send().json::<()>().await {
+            Err(Error::RateLimited {
+                code, retry_after, ..
+            }) => {
+                assert_eq!(code, awc::http::StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(retry_after, Some(Duration::ZERO));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        for (path, status) in [
+            ("bare", awc::http::StatusCode::SERVICE_UNAVAILABLE),
+            ("garbled", awc::http::StatusCode::TOO_MANY_REQUESTS),
+        ] {
+            match client.get(path).send().json::<()>().await {
+                Err(Error::RateLimited {
+                    code, retry_after, ..
+                }) => {
+                    assert_eq!(code, status);
+                    assert_eq!(retry_after, None);
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        let future = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let mut headers = super::HeaderMap::new();
+        headers.insert(super::header::RETRY_AFTER, future.parse().unwrap());
+        let delay = super::retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+    }
+
+    #[actix_rt::test]
+    async fn oversized_response_overflows() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/empty" => MockResponse::new(204),
+            _ => MockResponse::json(serde_json::json!({"data": "x".repeat(100)})),
+        });
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .max_response_size(64)
+            .build();
+
+        match client.get("large").send().json::<serde_json::Value>().await {
+            Err(Error::PayloadError(super::PayloadError::Overflow)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        client.get("empty").send().json::<()>().await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn patch_sends_json() {
+        let server = MockServer::start(|req| MockResponse::json(req.json()));
+        let client = server.client();
+
+        let update = json!({"timeout": "2030-01-01T00:00:00Z"});
+        let echoed: serde_json::Value = client
+            .patch("allocations/a-1")
+            .send_json(&update)
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(echoed, update);
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "PATCH");
+        assert_eq!(request.path, "/allocations/a-1");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+    }
+
+    #[actix_rt::test]
+    async fn empty_body_reads_as_null_or_empty() {
+        use ya_client_model::payment::Allocation;
+
+        let server = MockServer::start(|_| MockResponse::new(204));
+        let client = server.client();
+
+        let bytes: Vec<u8> = client.get("a").send().json().await.unwrap();
+        assert!(bytes.is_empty());
+        let allocation: Option<Allocation> = client.get("a").send().json().await.unwrap();
+        assert!(allocation.is_none());
+        match client.get("a").send().json::<Allocation>().await {
+            Err(Error::InternalError(msg)) => {
+                assert!(msg.starts_with("empty response to GET "), "{}", msg);
+                assert!(msg.ends_with("Allocation"), "{}", msg);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn error_keeps_raw_body() {
+        let page = "<html>502 Bad Gateway</html>";
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/text" => MockResponse::new(500).body(page),
+            _ => MockResponse::new(500)
+                .header("content-type", "application/json")
+                .body(page),
+        });
+        let client = server.client();
+
+        match client.get("text").send().json::<()>().await {
+            Err(Error::HttpError { msg, body, .. }) => {
+                assert_eq!(msg, page);
+                assert_eq!(body.as_deref(), Some(page));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let err = client.get("json").send().json::<()>().await.unwrap_err();
+        assert!(err.to_string().ends_with(&format!("; body: '{}'", page)), "{}", err);
+        match err {
+            Error::HttpError { msg, body, .. } => {
+                assert!(msg.starts_with("error parsing error msg"), "{}", msg);
+                assert_eq!(body.as_deref(), Some(page));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
     }
 
     #[test]
-    fn multi_var_and_query_url() {
-        let bar = "baara";
-        let baz = 0;
-        let qar = Some(true);
-        let qaz = Some(3);
+    fn request_fingerprint_identifies_logical_request() {
+        use super::{request_fingerprint, Method};
+
+        let fingerprint = request_fingerprint(&Method::POST, "/allocations?x=1", b"{}");
+        assert_eq!(fingerprint.len(), 12);
         assert_eq!(
-            url_format!(
-                "foo/{bar}/fuu/{baz}",
-                #[query] qar,
-                #[query] qaz
-            ),
-            "foo/baara/fuu/0?qar=true&qaz=3"
+            fingerprint,
+            request_fingerprint(&Method::POST, "/allocations?x=1", b"{}")
         );
+        for other in [
+            request_fingerprint(&Method::PUT, "/allocations?x=1", b"{}"),
+            request_fingerprint(&Method::POST, "/allocations?x=2", b"{}"),
+            request_fingerprint(&Method::POST, "/allocations?x=1", b"[]"),
+            request_fingerprint(&Method::POST, "/allocations?x=1{}", b""),
+        ] {
+            assert_ne!(fingerprint, other);
+        }
     }
 
-    async fn verify_stream<S, F>(f: F) -> anyhow::Result<()>
-    where
-        S: Stream<Item = std::result::Result<Bytes, Error>> + Unpin + 'static,
-        F: Fn(&'static str) -> EventStream<S, Error>,
-    {
-        let src = r#"
-:ping
-event: stdout
-data: some
-data: output
-id: 1
+    #[actix_rt::test]
+    async fn fingerprint_is_logged_and_attached_to_errors() {
+        use super::{request_fingerprint, Method};
+
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/limited" => MockResponse::new(429).header("retry-after", "0"),
+            _ => MockResponse::new(500),
+        });
+        let client = server.client();
+        let fingerprint = |err: Error| match err {
+            Error::HttpError { fingerprint, .. } | Error::RateLimited { fingerprint, .. } => {
+                fingerprint.unwrap()
+            }
+            other => panic!("unexpected {:?}", other),
+        };
 
-:ping
+        let logs = LogCapture::start();
+        let send = |body| client.post("jobs").send_json(&body).json::<()>();
+        let first = fingerprint(send(json!({"a": 1})).await.unwrap_err());
+        let again = fingerprint(send(json!({"a": 1})).await.unwrap_err());
+        let other = fingerprint(send(json!({"a": 2})).await.unwrap_err());
 
-event: stderr
-data:
-id: 2
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        let sent = format!("sending POST {}jobs [{}]", server.url(), first);
+        assert_eq!(
+            logs.records().iter().filter(|(_, msg)| *msg == sent).count(),
+            2
+        );
 
-event: stdout
-data: 0
-id
+        let retrying = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .retry(super::RetryPolicy::new(2).base_delay(Duration::from_millis(10)))
+            .build();
+        let limited = fingerprint(retrying.get("limited").send().json::<()>().await.unwrap_err());
+        assert_eq!(limited, request_fingerprint(&Method::GET, "/limited", b""));
+        let sent = format!("sending GET {}limited [{}]", server.url(), limited);
+        assert_eq!(
+            logs.records().iter().filter(|(_, msg)| *msg == sent).count(),
+            2
+        );
+    }
 
-"#;
-        let stream = f(src);
-        let events = stream.collect::<Vec<_>>().await;
+    #[actix_rt::test]
+    async fn retry_policy_skips_post_and_client_errors() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "POST" => MockResponse::new(503),
+            _ => MockResponse::new(400),
+        });
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .retry(super::RetryPolicy::new(3).base_delay(Duration::from_millis(10)))
+            .build();
 
-        assert_eq!(events.len(), 4);
-        let mut iter = events.into_iter();
+        assert!(client.post("items").send_json(&json!({})).json::<()>().await.is_err());
+        assert!(client.get("items").send().json::<()>().await.is_err());
+        assert_eq!(server.requests().len(), 2);
+    }
 
-        let event = iter.next().unwrap()?;
-        assert_eq!(event.event, "stdout".to_string());
-        assert_eq!(event.data, "some\noutput".to_string());
-        assert_eq!(event.id, Some(1));
+    #[actix_rt::test]
+    async fn retry_policy_retries_refused_connection() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = crate::web::WebClient::builder()
+            .api_url(format!("http://{}/", addr).parse().unwrap())
+            .retry(super::RetryPolicy::new(3).base_delay(Duration::from_millis(10)))
+            .build();
 
-        assert!(iter.next().unwrap().is_err());
+        let result: crate::Result<()> = client.get("status").send().json().await;
+        assert!(matches!(result, Err(Error::SendRequestError { .. })));
+        assert_eq!(client.telemetry_snapshot().retries, 2);
+    }
 
-        let event = iter.next().unwrap()?;
-        assert_eq!(event.event, "stderr".to_string());
-        assert_eq!(event.data, "".to_string());
-        assert_eq!(event.id, Some(2));
+    #[actix_rt::test]
+    async fn explicit_auth_token_takes_precedence_over_env() {
+        let server = &MockServer::start(|_| MockResponse::new(204));
+        let builder = |env_var: fn(&str) -> Option<String>| {
+            let mut builder = crate::web::WebClient::builder().api_url(server.url());
+            builder.env_var = env_var;
+            builder
+        };
+        let with_key = |name: &str| {
+            (name == super::YAGNA_APPKEY_ENV_VAR).then(|| "from-env".to_string())
+        };
+        let without_key = |_: &str| None;
+        let sent_auth = |client: crate::web::WebClient| async move {
+            client.get("me").send().bytes().await.unwrap();
+            server.requests().pop().unwrap().header("authorization").map(String::from)
+        };
 
-        let event = iter.next().unwrap()?;
-        assert_eq!(event.event, "stdout".to_string());
-        assert_eq!(event.data, "0".to_string());
-        assert_eq!(event.id, None);
+        assert_eq!(sent_auth(builder(with_key).build()).await.as_deref(), Some("Bearer from-env"));
+        let explicit = builder(with_key).auth_token("explicit");
+        assert_eq!(sent_auth(explicit.build()).await.as_deref(), Some("Bearer explicit"));
+        let explicit = builder(with_key).auth_token("explicit").auth_from_env().unwrap();
+        assert_eq!(sent_auth(explicit.build()).await.as_deref(), Some("Bearer explicit"));
+        let from_env = builder(with_key).auth_from_env().unwrap();
+        assert_eq!(sent_auth(from_env.build()).await.as_deref(), Some("Bearer from-env"));
 
-        Ok(())
+        assert_eq!(sent_auth(builder(without_key).build()).await, None);
+        assert!(matches!(builder(without_key).auth_from_env(), Err(Error::InvalidAppKey(_))));
     }
 
     #[actix_rt::test]
-    async fn event_stream() {
-        verify_stream(|s| {
-            let stream = futures::stream::once(async move { Ok::<_, Error>(Bytes::from(s.to_string().into_bytes()))}.boxed_local());
-            EventStream::new(stream)
-        }).await.unwrap();
+    async fn sse_stream_parses_event_data() {
+        use crate::error::EventStreamErrorReason;
+
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/events" => MockResponse::new(200)
+                .header("content-type", "text/event-stream; charset=utf-8")
+                .body(concat!(
+                    "data: {\"id\": 1,\ndata: \"tags\": [\"a\"]}\n\n",
+                    ": keep-alive\n\n",
+                    "event: x\ndata: {\"id\": 2}\n\n",
+                )),
+            _ => MockResponse::json(json!([{"id": 1}])),
+        });
+        let client = server.client();
+        let read = |path: &str| {
+            client
+                .get(path)
+                .send()
+                .sse_stream::<serde_json::Value>()
+                .collect::<Vec<_>>()
+        };
 
-        verify_stream(|s| {
-            let stream = futures::stream::iter(s.as_bytes()).chunks(5).map(|v| {
-                Ok::<_, Error>(Bytes::from(v.iter().map(|b| **b).collect::<Vec<_>>()))
-            });
-            EventStream::new(stream)
-        }).await.unwrap();
+        let events: Vec<_> = read("events")
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events, [json!({"id": 1, "tags": ["a"]}), json!({"id": 2})]);
+
+        let rejected = read("json").await;
+        assert_eq!(rejected.len(), 1);
+        assert!(matches!(
+            &rejected[0],
+            Err(Error::EventStreamError {
+                reason: EventStreamErrorReason::UnexpectedContentType(content_type),
+                ..
+            }) if content_type == "application/json"
+        ));
+    }
 
-        verify_stream(|s| {
-            let stream = futures::stream::iter(s.as_bytes()).chunks(1).map(|v| {
-                Ok::<_, Error>(Bytes::from(v.iter().map(|b| **b).collect::<Vec<_>>()))
-            });
-            EventStream::new(stream)
-        }).await.unwrap();
+    #[actix_rt::test]
+    async fn json_stream_yields_array_elements() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/events" => MockResponse::json(json!([{"id": 1}, {"id": 2}, {"id": 3}])),
+            "/none" => MockResponse::new(204),
+            "/cut" => MockResponse::new(200).body("[{\"id\": 1}, {\"id\""),
+            _ => MockResponse::new(404).body("no such log"),
+        });
+        let client = server.client();
+        let read = |path: &str| {
+            client
+                .get(path)
+                .send()
+                .json_stream::<serde_json::Value>()
+                .collect::<Vec<_>>()
+        };
+
+        let events = read("events").await;
+        let ids: Vec<_> = events.into_iter().map(|e| e.unwrap()["id"].clone()).collect();
+        assert_eq!(ids, [json!(1), json!(2), json!(3)]);
+        assert!(read("none").await.is_empty());
+
+        let cut = read("cut").await;
+        assert_eq!(cut.len(), 2);
+        assert!(matches!(cut[1], Err(Error::PayloadError(_))));
+
+        let missing = read("missing").await;
+        assert!(matches!(
+            missing[..],
+            [Err(Error::HttpError { code: awc::http::StatusCode::NOT_FOUND, .. })]
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn drain_rejects_new_requests_and_waits_for_sent_ones() {
+        let server =
+            MockServer::start(|_| MockResponse::new(204).delay(Duration::from_millis(100)));
+        let client = server.client();
+
+        let sent = client.get("slow").send().bytes();
+        let drain = async {
+            actix_rt::time::sleep(Duration::from_millis(20)).await;
+            let draining = client.drain(Duration::from_secs(5));
+            futures::pin_mut!(draining);
+            assert!(futures::poll!(draining.as_mut()).is_pending());
+            let rejected = client.get("late").send().bytes().await;
+            assert!(matches!(rejected, Err(Error::ShuttingDown)));
+            draining.await.unwrap();
+            assert_eq!(client.telemetry_snapshot().in_flight, 0);
+        };
+        let (sent, ()) = futures::join!(sent, drain);
+
+        sent.unwrap();
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/slow"]);
+    }
+
+    #[actix_rt::test]
+    async fn drain_waits_for_the_response_body() {
+        let server = MockServer::start(|_| {
+            MockResponse::new(200)
+                .body("slow body")
+                .body_delay(Duration::from_millis(100))
+        });
+        let client = server.client();
+
+        let read = client.get("download").send().bytes();
+        let drain = async {
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(client.telemetry_snapshot().in_flight, 0);
+            let draining = client.drain(Duration::from_secs(5));
+            futures::pin_mut!(draining);
+            assert!(futures::poll!(draining.as_mut()).is_pending());
+            draining.await.unwrap();
+        };
+        let (read, ()) = futures::join!(read, drain);
+        assert_eq!(read.unwrap(), b"slow body");
+
+        // a dropped body releases the request too
+        let server = MockServer::start(|_| MockResponse::new(200).body("unread"));
+        let client = server.client();
+        let response = client.get("unread").send().request().await.unwrap();
+        drop(response);
+        let started = Instant::now();
+        client.drain(Duration::from_secs(5)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[actix_rt::test]
+    async fn json_with_headers_returns_response_headers() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/invoices" => MockResponse::json(json!([1, 2])).header("x-paginate-total", "7"),
+            _ => MockResponse::new(204).header("etag", "\"v2\""),
+        });
+        let client = server.client();
+
+        let (page, headers) = client
+            .get("invoices")
+            .send()
+            .json_with_headers::<Vec<u32>>()
+            .await
+            .unwrap();
+        assert_eq!(page, [1, 2]);
+        assert_eq!(headers.get("x-paginate-total").unwrap(), "7");
+
+        let ((), headers) = client.put("invoices/1").send().json_with_headers().await.unwrap();
+        assert_eq!(headers.get("etag").unwrap(), "\"v2\"");
+    }
+
+    #[actix_rt::test]
+    async fn send_with_recovery_retries_adjusted_body_once() {
+        let server = MockServer::start(|req| match req.json()["price"].as_u64() {
+            Some(price) if price <= 10 => MockResponse::json(json!({"accepted": price})),
+            _ => MockResponse::new(400).body("price too high"),
+        });
+        let client = server.client();
+        let build = || (client.post("proposals"), json!({"price": 20}));
+
+        let accepted: serde_json::Value = client
+            .send_with_recovery(build, |err, mut body| {
+                assert!(err.to_string().contains("price too high"));
+                body["price"] = json!(10);
+                Some(body)
+            })
+            .await
+            .unwrap();
+        assert_eq!(accepted, json!({"accepted": 10}));
+
+        let rejected: crate::Result<serde_json::Value> = client
+            .send_with_recovery(build, |_, mut body| {
+                body["price"] = json!(15);
+                Some(body)
+            })
+            .await;
+        assert!(matches!(rejected, Err(Error::HttpError { .. })));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn stable_body_key_order_sorts_object_keys() {
+        #[derive(serde::Serialize, Debug)]
+        struct Body {
+            zone: &'static str,
+            amounts: std::collections::HashMap<String, u32>,
+        }
+        let body = || Body {
+            zone: "eu",
+            amounts: (0..16).map(|i| (format!("k{:02}", i), i)).collect(),
+        };
+        let server = MockServer::start(|_| MockResponse::new(204));
+        let client = |stable| {
+            crate::web::WebClient::builder()
+                .api_url(server.url())
+                .stable_body_key_order(stable)
+                .build()
+        };
+
+        for _ in 0..2 {
+            client(true).post("audit").send_json(&body()).bytes().await.unwrap();
+        }
+        client(false).post("audit").send_json(&body()).bytes().await.unwrap();
+
+        let bodies: Vec<_> = server.requests().into_iter().map(|r| r.body).collect();
+        assert_eq!(bodies[0], bodies[1]);
+        let sorted: Vec<_> = (0..16).map(|i| format!("\"k{:02}\":{}", i, i)).collect();
+        let expected = format!("{{\"amounts\":{{{}}},\"zone\":\"eu\"}}", sorted.join(","));
+        assert_eq!(String::from_utf8_lossy(&bodies[0]), expected);
+        assert!(String::from_utf8_lossy(&bodies[2]).starts_with("{\"zone\":"));
+    }
+
+    #[actix_rt::test]
+    async fn auth_schemes_set_their_headers() {
+        let server = MockServer::start(|_| MockResponse::new(204));
+        let builder = || crate::web::WebClient::builder().api_url(server.url());
+        let sent = |client: crate::web::WebClient| {
+            let server = &server;
+            async move {
+                client.get("me").send().bytes().await.unwrap();
+                server.requests().pop().unwrap()
+            }
+        };
+
+        let request = sent(builder().auth_token("token").build()).await;
+        assert_eq!(request.header("authorization"), Some("Bearer token"));
+
+        let request = sent(builder().basic_auth("user", "pass").build()).await;
+        assert_eq!(request.header("authorization"), Some("Basic dXNlcjpwYXNz"));
+
+        let client = builder()
+            .custom_auth(
+                super::HeaderName::from_static("x-proxy-key"),
+                super::HeaderValue::from_static("secret"),
+            )
+            .header("x-proxy-key".into(), "other".into())
+            .unwrap()
+            .header("x-trace".into(), "1".into())
+            .unwrap()
+            .build();
+        let request = sent(client).await;
+        let proxy_keys: Vec<_> = request
+            .headers
+            .iter()
+            .filter(|(name, _)| name == "x-proxy-key")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        assert_eq!(proxy_keys, ["secret", "other"]);
+        assert_eq!(request.header("x-trace"), Some("1"));
+        assert!(request.header("authorization").is_none());
+    }
+
+    #[actix_rt::test]
+    async fn high_priority_request_skips_the_queue() {
+        let server = MockServer::start(|_| MockResponse::new(204).delay(Duration::from_millis(50)));
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .max_concurrency(1)
+            .build();
+        let send = |path: &str, priority| client.get(path).priority(priority).send().bytes();
+
+        let (first, second, third, urgent) = futures::join!(
+            send("first", super::Priority::Normal),
+            send("second", super::Priority::Normal),
+            send("third", super::Priority::Low),
+            send("urgent", super::Priority::High),
+        );
+        for result in [first, second, third, urgent] {
+            result.unwrap();
+        }
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/first", "/urgent", "/second", "/third"]);
+    }
+
+    #[actix_rt::test]
+    async fn zero_max_concurrency_is_unlimited() {
+        let server = MockServer::start(|_| MockResponse::new(204).delay(Duration::from_millis(50)));
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .max_concurrency(0)
+            .build();
+
+        let requests = futures::future::join_all((0..3).map(|_| client.get("item").send().bytes()));
+        let results = actix_rt::time::timeout(Duration::from_secs(5), requests)
+            .await
+            .expect("requests should not wait for a slot");
+        for result in results {
+            result.unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn observer_sees_successes_and_failures() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct Counting {
+            requests: AtomicUsize,
+            responses: AtomicUsize,
+            errors: AtomicUsize,
+        }
+
+        impl super::RequestObserver for Counting {
+            fn on_request(&self, _method: &super::Method, _url: &str) {
+                self.requests.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn on_response(
+                &self,
+                _method: &super::Method,
+                url: &str,
+                status: super::StatusCode,
+                _elapsed: Duration,
+            ) {
+                assert!(url.ends_with("/missing") != status.is_success(), "{}", url);
+                self.responses.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn on_error(&self, _method: &super::Method, url: &str, _err: &Error) {
+                assert!(!url.ends_with("/ok"), "{}", url);
+                self.errors.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/ok" => MockResponse::json(serde_json::json!(1)),
+            "/html" => html_page(),
+            "/invalid" => MockResponse::json(serde_json::json!("one")),
+            _ => MockResponse::new(404),
+        });
+        let observer = std::sync::Arc::new(Counting::default());
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .observer(observer.clone())
+            .build();
+
+        let value: u32 = client.get("ok").send().json().await.unwrap();
+        assert_eq!(value, 1);
+        for path in ["missing", "html", "invalid"] {
+            client.get(path).send().json::<u32>().await.unwrap_err();
+        }
+
+        assert_eq!(observer.requests.load(Ordering::SeqCst), 4);
+        assert_eq!(observer.responses.load(Ordering::SeqCst), 4);
+        assert_eq!(observer.errors.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn connection_limit_still_completes_concurrent_requests() {
+        let server = MockServer::start(|req| {
+            MockResponse::json(serde_json::json!(req.path)).delay(Duration::from_millis(20))
+        });
+        let client = crate::web::WebClient::builder()
+            .api_url(server.url())
+            .connector_config(1, Duration::from_secs(5), Duration::from_secs(30))
+            .build();
+
+        let paths = futures::future::try_join_all(
+            (0..4).map(|i| client.get(&format!("item/{}", i)).send().json::<String>()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(paths, ["/item/0", "/item/1", "/item/2", "/item/3"]);
+        assert_eq!(server.requests().len(), 4);
     }
 }
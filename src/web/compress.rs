@@ -0,0 +1,78 @@
+//! Compression of request bodies, see [`WebClientBuilder::compress_requests`].
+//!
+//! [`WebClientBuilder::compress_requests`]: crate::web::WebClientBuilder::compress_requests
+use awc::http::header::{self, HeaderValue};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+use super::middleware::{Middleware, MiddlewareRequest, MiddlewareResult, Next};
+
+/// `Content-Encoding` of compressed request bodies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Encoding {
+    Gzip,
+    /// The zlib format, as `deflate` is defined for HTTP.
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub(crate) fn compress(&self, body: &[u8]) -> Vec<u8> {
+        // writing into a vector cannot fail
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+}
+
+/// [`BuiltinLayer::Compression`](super::BuiltinLayer::Compression), of JSON
+/// bodies of at least `min_size` bytes.
+pub(crate) struct Compress {
+    pub encoding: Encoding,
+    pub min_size: usize,
+}
+
+impl Middleware for Compress {
+    fn handle<'a>(
+        &'a self,
+        mut request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        let is_json = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.as_bytes() == b"application/json")
+            .unwrap_or_default();
+        let compressed = request
+            .body()
+            .filter(|body| is_json && body.len() >= self.min_size)
+            .map(|body| self.encoding.compress(body));
+        if let Some(body) = compressed {
+            request.set_body(body);
+            request.extra_headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(self.encoding.as_str()),
+            );
+        }
+        next.run(request)
+    }
+}
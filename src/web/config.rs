@@ -0,0 +1,301 @@
+//! Client configuration that can be deserialized as a whole, eg. from a file
+//! or environment.
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use url::Url;
+
+use crate::web::{
+    BuiltinLayer, ContentTypePolicy, Encoding, RetryPolicy, TlsVersion, WebClient, WebClientBuilder,
+};
+use crate::{Error, Result};
+
+/// The `awc` connector defaults, for the connector settings left unset.
+const DEFAULT_MAX_CONNECTIONS: usize = 100;
+const DEFAULT_KEEP_ALIVE: f64 = 15.0;
+const DEFAULT_CONN_LIFETIME: f64 = 75.0;
+
+/// Declarative counterpart of [`WebClientBuilder`].
+///
+/// Every field is optional; unset ones keep the builder defaults, so eg. a
+/// missing `api_url` still falls back to the `YAGNA_API_URL` env variable.
+///
+/// Proxies are not supported: the underlying `awc` client cannot connect
+/// through one, so there is no proxy setting here nor on the builder.
+/// Middlewares and observers are code, and are only set on the builder, eg.
+/// on the one returned by [`builder`](Self::builder); the order of the
+/// built-in ones can be set here, as `builtinLayers`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ClientConfig {
+    pub api_url: Option<Url>,
+    pub app_key: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    // All timeouts are given in seconds.
+    pub timeout: Option<f64>,
+    /// Keyed by the service API path, see [`WebClientBuilder::service_timeout`].
+    pub service_timeouts: HashMap<String, f64>,
+    pub default_query: BTreeMap<String, String>,
+    pub min_tls_version: Option<TlsVersion>,
+    pub log_body_max_len: Option<usize>,
+    pub stream_high_water_mark: Option<usize>,
+    pub max_response_size: Option<usize>,
+    pub retry: Option<RetryPolicy>,
+    pub max_concurrency: Option<usize>,
+    pub slow_request_threshold: Option<f64>,
+    pub compress_requests: Option<Encoding>,
+    pub compress_min_size: Option<usize>,
+    pub content_type_policy: Option<ContentTypePolicy>,
+    pub max_allocation_amount: Option<BigDecimal>,
+    pub stable_body_key_order: Option<bool>,
+    /// See [`WebClientBuilder::builtin_layers`].
+    pub builtin_layers: Option<Vec<BuiltinLayer>>,
+    /// See [`WebClientBuilder::connector_config`], the unset ones of the
+    /// three keep the `awc` defaults.
+    pub max_connections: Option<usize>,
+    pub keep_alive: Option<f64>,
+    pub conn_lifetime: Option<f64>,
+    /// PEM encoded certificates, see [`WebClientBuilder::tls_root_cert`].
+    #[cfg(feature = "tls")]
+    pub tls_root_certs: Vec<String>,
+    #[cfg(feature = "tls")]
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl ClientConfig {
+    pub fn builder(self) -> Result<WebClientBuilder> {
+        let mut builder = WebClient::builder();
+        if let Some(url) = self.api_url {
+            builder = builder.api_url(url);
+        }
+        if let Some(app_key) = &self.app_key {
+            builder = builder.checked_auth_token(app_key)?;
+        }
+        for (name, value) in self.headers {
+            builder = builder.header(name, value)?;
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(secs("timeout", timeout)?);
+        }
+        for (service, timeout) in &self.service_timeouts {
+            builder = builder.service_timeout(service, secs(service, *timeout)?);
+        }
+        for (name, value) in &self.default_query {
+            builder = builder.default_query_param(name, value);
+        }
+        if let Some(version) = self.min_tls_version {
+            builder = builder.min_tls_version(version);
+        }
+        if let Some(max_len) = self.log_body_max_len {
+            builder = builder.log_body_max_len(max_len);
+        }
+        if let Some(high_water_mark) = self.stream_high_water_mark {
+            builder = builder.stream_high_water_mark(high_water_mark);
+        }
+        if let Some(max) = self.max_response_size {
+            builder = builder.max_response_size(max);
+        }
+        if let Some(policy) = self.retry {
+            builder = builder.retry(policy);
+        }
+        if let Some(max) = self.max_concurrency {
+            builder = builder.max_concurrency(max);
+        }
+        if let Some(threshold) = self.slow_request_threshold {
+            builder = builder.slow_request_threshold(secs("slow request", threshold)?);
+        }
+        if let Some(encoding) = self.compress_requests {
+            builder = builder.compress_requests(encoding);
+        }
+        if let Some(min_size) = self.compress_min_size {
+            builder = builder.compress_min_size(min_size);
+        }
+        if let Some(policy) = self.content_type_policy {
+            builder = builder.on_unexpected_content_type(policy);
+        }
+        if let Some(amount) = self.max_allocation_amount {
+            builder = builder.max_allocation_amount(amount);
+        }
+        if let Some(enabled) = self.stable_body_key_order {
+            builder = builder.stable_body_key_order(enabled);
+        }
+        if let Some(layers) = self.builtin_layers {
+            builder = builder.builtin_layers(layers);
+        }
+        if self.max_connections.is_some()
+            || self.keep_alive.is_some()
+            || self.conn_lifetime.is_some()
+        {
+            builder = builder.connector_config(
+                self.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS),
+                secs("keep alive", self.keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE))?,
+                secs(
+                    "connection lifetime",
+                    self.conn_lifetime.unwrap_or(DEFAULT_CONN_LIFETIME),
+                )?,
+            );
+        }
+        #[cfg(feature = "tls")]
+        {
+            for pem in self.tls_root_certs {
+                builder = builder.tls_root_cert(pem.into_bytes())?;
+            }
+            builder = builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+        }
+        Ok(builder)
+    }
+}
+
+impl WebClient {
+    pub fn from_config(config: ClientConfig) -> Result<WebClient> {
+        Ok(config.builder()?.build())
+    }
+}
+
+fn secs(name: &str, secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|e| Error::InvalidArgument(format!("{} timeout {}: {}", name, secs, e)))
+}
+
+/// (De)serializes a `Duration` as seconds, like the timeouts of the config.
+pub(crate) mod duration_secs {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::market::MARKET_API_PATH;
+    use awc::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn test_builder_from_json() {
+        let config: ClientConfig = serde_json::from_value(json!({
+            "apiUrl": "http://yagna:7465/",
+            "appKey": "0123456789abcdef0123456789abcdef",
+            "headers": {"x-app": "test"},
+            "timeout": 30,
+            "serviceTimeouts": {MARKET_API_PATH: 1.5},
+            "defaultQuery": {"appSessionId": "session"},
+            "minTlsVersion": "Tls1_3",
+            "logBodyMaxLen": 0,
+            "maxResponseSize": 1024,
+            "retry": {"maxAttempts": 5, "baseDelay": 0.25, "retryStatuses": [502, 503]},
+            "maxConcurrency": 8,
+            "slowRequestThreshold": 2,
+            "compressRequests": "gzip",
+            "compressMinSize": 256,
+            "contentTypePolicy": {"retry": {"attempts": 2, "delay": 0.5}},
+            "maxAllocationAmount": "10.5",
+            "stableBodyKeyOrder": true,
+            "builtinLayers": ["retry", "telemetry"],
+            "maxConnections": 4,
+        }))
+        .unwrap();
+
+        let builder = config.builder().unwrap();
+        assert_eq!(builder.api_url, Some("http://yagna:7465/".parse().unwrap()));
+        assert!(builder.auth.is_some());
+        assert_eq!(builder.headers.get("x-app").unwrap(), "test");
+        assert_eq!(builder.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(
+            builder.service_timeouts[MARKET_API_PATH],
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            builder.default_query,
+            vec![("appSessionId".to_string(), "session".to_string())]
+        );
+        assert_eq!(builder.min_tls_version, TlsVersion::Tls1_3);
+        assert_eq!(builder.log_body_max_len, 0);
+        assert_eq!(
+            builder.stream_high_water_mark,
+            WebClient::builder().stream_high_water_mark
+        );
+        assert_eq!(builder.max_response_size, 1024);
+        assert_eq!(
+            builder.retry,
+            Some(
+                RetryPolicy::new(5)
+                    .base_delay(Duration::from_millis(250))
+                    .retry_statuses([StatusCode::BAD_GATEWAY, StatusCode::SERVICE_UNAVAILABLE])
+            )
+        );
+        assert_eq!(builder.max_concurrency, Some(8));
+        assert_eq!(builder.slow_request_threshold, Some(Duration::from_secs(2)));
+        assert_eq!(builder.compress_requests, Some(Encoding::Gzip));
+        assert_eq!(builder.compress_min_size, 256);
+        assert_eq!(
+            builder.content_type_policy,
+            ContentTypePolicy::Retry {
+                attempts: 2,
+                delay: Duration::from_millis(500)
+            }
+        );
+        assert_eq!(builder.max_allocation_amount, Some("10.5".parse().unwrap()));
+        assert!(builder.stable_body_key_order);
+        assert_eq!(
+            builder.builtin_layers,
+            [BuiltinLayer::Retry, BuiltinLayer::Telemetry]
+        );
+        assert_eq!(
+            builder.connector,
+            WebClient::builder()
+                .connector_config(4, Duration::from_secs(15), Duration::from_secs(75))
+                .connector
+        );
+    }
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = ClientConfig {
+            retry: Some(RetryPolicy::default()),
+            content_type_policy: Some(ContentTypePolicy::ReturnRaw),
+            compress_requests: Some(Encoding::Deflate),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["retry"]["maxDelay"], json!(10.0));
+        assert_eq!(json["retry"]["retryStatuses"], json!([429, 502, 503, 504]));
+        assert_eq!(
+            serde_json::from_value::<ClientConfig>(json).unwrap(),
+            config
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_from_config() {
+        assert!(WebClient::from_config(ClientConfig::default()).is_ok());
+
+        let config = ClientConfig {
+            timeout: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            WebClient::from_config(config),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let config = ClientConfig {
+            app_key: Some("not a key".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            WebClient::from_config(config),
+            Err(Error::InvalidAppKey(_))
+        ));
+    }
+}
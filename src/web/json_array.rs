@@ -0,0 +1,134 @@
+//! Incremental splitting of a JSON array body into its elements, see
+//! [`WebRequest::json_stream`](crate::web::WebRequest::json_stream).
+use bytes::{Buf, Bytes, BytesMut};
+use serde::de::Error as _;
+
+/// Collects body chunks and cuts complete top-level elements out of them,
+/// without parsing the elements themselves.
+#[derive(Debug, Default)]
+pub(crate) struct ArraySplitter {
+    buf: BytesMut,
+    /// How far `buf` has been scanned for the end of the current element.
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    elements: usize,
+    done: bool,
+}
+
+impl ArraySplitter {
+    /// A splitter which expects no elements, eg. for a `204 No Content`.
+    pub fn finished() -> Self {
+        ArraySplitter {
+            done: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        if !self.done {
+            self.buf.extend_from_slice(chunk);
+        }
+    }
+
+    /// Whether the closing bracket of the array has been reached.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Length of the buffered, not yet returned part of the body.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Next complete element, `None` if more of the body is needed.
+    pub fn next_element(&mut self) -> Result<Option<Bytes>, serde_json::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        if !self.started {
+            let start = match self.buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(start) => start,
+                None => return Ok(None),
+            };
+            if self.buf[start] != b'[' {
+                return Err(serde_json::Error::custom("expected a JSON array"));
+            }
+            self.buf.advance(start + 1);
+            self.started = true;
+        }
+        while self.scanned < self.buf.len() {
+            let b = self.buf[self.scanned];
+            self.scanned += 1;
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => (),
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' if self.depth > 0 => self.depth -= 1,
+                b']' => return self.cut(true),
+                b',' if self.depth == 0 => return self.cut(false),
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Splits off the element ending before the last scanned delimiter.
+    fn cut(&mut self, last: bool) -> Result<Option<Bytes>, serde_json::Error> {
+        let mut element = self.buf.split_to(self.scanned).freeze();
+        element.truncate(element.len() - 1);
+        self.scanned = 0;
+        self.done = last;
+        if element.iter().all(u8::is_ascii_whitespace) {
+            return if last && self.elements == 0 {
+                Ok(None)
+            } else {
+                Err(serde_json::Error::custom("missing JSON array element"))
+            };
+        }
+        self.elements += 1;
+        Ok(Some(element))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(chunks: &[&str]) -> Result<Vec<String>, serde_json::Error> {
+        let mut splitter = ArraySplitter::default();
+        let mut elements = Vec::new();
+        for chunk in chunks {
+            splitter.push(chunk.as_bytes());
+            while let Some(element) = splitter.next_element()? {
+                elements.push(String::from_utf8_lossy(&element).trim().to_string());
+            }
+        }
+        assert!(splitter.is_done());
+        Ok(elements)
+    }
+
+    #[test]
+    fn splits_elements_across_chunks() {
+        let elements = split(&[" [1, {\"a\": [2,", " 3]}, \"x,]\\\"", "\" ,[]", "]"]).unwrap();
+        assert_eq!(elements, ["1", "{\"a\": [2, 3]}", "\"x,]\\\"\"", "[]"]);
+        assert_eq!(split(&["[", " ]"]).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_malformed_arrays() {
+        assert!(split(&["{\"a\": 1}"]).is_err());
+        assert!(split(&["[1,]"]).is_err());
+        assert!(split(&["[,1]"]).is_err());
+    }
+}
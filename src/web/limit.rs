@@ -0,0 +1,154 @@
+//! Limiting of concurrent requests, see [`WebClientBuilder::max_concurrency`].
+//!
+//! [`WebClientBuilder::max_concurrency`]: crate::web::WebClientBuilder::max_concurrency
+use futures::channel::oneshot;
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::middleware::{Middleware, MiddlewareRequest, MiddlewareResult, Next};
+
+/// Order in which requests waiting for a free slot are sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    /// For requests which should not wait behind the regular traffic, eg.
+    /// health checks or cancellations.
+    High,
+}
+
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    wake: oneshot::Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Highest priority first, then first come first served.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+struct State {
+    available: usize,
+    seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+/// Semaphore handing out freed permits to the highest priority waiter.
+pub(crate) struct Limiter {
+    state: Rc<RefCell<State>>,
+}
+
+impl Limiter {
+    pub fn new(permits: usize) -> Self {
+        Limiter {
+            state: Rc::new(RefCell::new(State {
+                available: permits,
+                seq: 0,
+                waiters: BinaryHeap::new(),
+            })),
+        }
+    }
+
+    pub async fn acquire(&self, priority: Priority) -> Permit {
+        let rx = {
+            let mut state = self.state.borrow_mut();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return Permit::new(&self.state);
+            }
+            let (wake, rx) = oneshot::channel();
+            state.seq += 1;
+            let seq = state.seq;
+            state.waiters.push(Waiter {
+                priority,
+                seq,
+                wake,
+            });
+            rx
+        };
+        // the sender is only dropped together with the limiter
+        rx.await.unwrap_or_else(|_| Permit::new(&self.state))
+    }
+}
+
+/// [`BuiltinLayer::ConcurrencyLimit`](super::BuiltinLayer::ConcurrencyLimit).
+impl Middleware for Limiter {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            // the slot is held until the response arrives, its body is then
+            // read without it
+            let permit = self.acquire(request.priority()).await;
+            let result = next.run(request).await;
+            drop(permit);
+            result
+        }
+        .boxed_local()
+    }
+}
+
+impl fmt::Debug for Limiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Limiter")
+            .field("available", &state.available)
+            .field("waiting", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// Slot taken from a [`Limiter`], passed on to the next waiter when dropped.
+pub(crate) struct Permit(Option<Rc<RefCell<State>>>);
+
+impl Permit {
+    fn new(state: &Rc<RefCell<State>>) -> Self {
+        Permit(Some(state.clone()))
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let state = match self.0.take() {
+            Some(state) => state,
+            None => return,
+        };
+        let mut guard = state.borrow_mut();
+        while let Some(waiter) = guard.waiters.pop() {
+            // a waiter which gave up has dropped its receiver, and the permit
+            // comes back here to be offered to the next one
+            match waiter.wake.send(Permit::new(&state)) {
+                Ok(()) => return,
+                Err(mut permit) => permit.0 = None,
+            }
+        }
+        guard.available += 1;
+    }
+}
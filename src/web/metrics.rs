@@ -0,0 +1,238 @@
+//! Per-endpoint request metrics, see [`WebClientBuilder::metrics`].
+//!
+//! [`WebClientBuilder::metrics`]: crate::web::WebClientBuilder::metrics
+use awc::http::{Method, StatusCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::web::RequestObserver;
+use crate::Error;
+
+/// Upper bounds of the latency buckets in milliseconds, the last bucket
+/// holding everything slower.
+const LATENCY_BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Placeholder of the path segments taken for ids.
+const ID_SEGMENT: &str = "{id}";
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    requests: u64,
+    errors: u64,
+    statuses: BTreeMap<u16, u64>,
+    latencies: [u64; LATENCY_BUCKETS_MS.len() + 1],
+}
+
+/// Handle to the metrics collected by a client, installed with
+/// [`WebClientBuilder::metrics`] and read with [`snapshot`](Self::snapshot).
+///
+/// Requests are grouped by method and path template, where path segments
+/// looking like ids (numbers, or at least 8 characters with a digit, eg.
+/// UUIDs and addresses) are replaced with `{id}`, so that eg. all the
+/// `GET /payment-api/v1/invoices/{id}` requests count together.
+///
+/// [`WebClientBuilder::metrics`]: crate::web::WebClientBuilder::metrics
+#[derive(Clone, Debug, Default)]
+pub struct WebClientMetrics {
+    endpoints: Arc<Mutex<BTreeMap<(String, String), Endpoint>>>,
+}
+
+/// Point-in-time copy of [`WebClientMetrics`], ready to be serialized for
+/// scraping.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub endpoints: Vec<EndpointMetrics>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointMetrics {
+    pub method: String,
+    pub path: String,
+    pub requests: u64,
+    /// Requests failed for any reason, including an error status.
+    pub errors: u64,
+    /// Responses by status code.
+    pub statuses: BTreeMap<u16, u64>,
+    /// Cumulative latency histogram, as in Prometheus.
+    pub latency_buckets: Vec<LatencyBucket>,
+    /// Latency percentiles in milliseconds, estimated as the upper bound of
+    /// their bucket. `None` without responses or beyond the last bound.
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyBucket {
+    /// Upper bound in milliseconds, `None` for the unbounded last bucket.
+    pub le_ms: Option<u64>,
+    /// Responses received within the bound.
+    pub count: u64,
+}
+
+impl WebClientMetrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let endpoints = self.endpoints.lock().unwrap();
+        let endpoints = endpoints
+            .iter()
+            .map(|((method, path), endpoint)| endpoint.metrics(method, path))
+            .collect();
+        MetricsSnapshot { endpoints }
+    }
+
+    fn update(&self, method: &Method, url: &str, update: impl FnOnce(&mut Endpoint)) {
+        let key = (method.to_string(), path_template(url));
+        update(self.endpoints.lock().unwrap().entry(key).or_default());
+    }
+}
+
+impl RequestObserver for WebClientMetrics {
+    fn on_request(&self, method: &Method, url: &str) {
+        self.update(method, url, |endpoint| endpoint.requests += 1);
+    }
+
+    fn on_response(&self, method: &Method, url: &str, status: StatusCode, elapsed: Duration) {
+        let millis = elapsed.as_millis();
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|le| millis <= *le as u128)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.update(method, url, |endpoint| {
+            *endpoint.statuses.entry(status.as_u16()).or_default() += 1;
+            endpoint.latencies[bucket] += 1;
+        });
+    }
+
+    fn on_error(&self, method: &Method, url: &str, _err: &Error) {
+        self.update(method, url, |endpoint| endpoint.errors += 1);
+    }
+}
+
+impl Endpoint {
+    fn metrics(&self, method: &str, path: &str) -> EndpointMetrics {
+        let mut total = 0;
+        let latency_buckets: Vec<_> = self
+            .latencies
+            .iter()
+            .enumerate()
+            .map(|(idx, count)| {
+                total += count;
+                LatencyBucket {
+                    le_ms: LATENCY_BUCKETS_MS.get(idx).copied(),
+                    count: total,
+                }
+            })
+            .collect();
+        let percentile = |q: f64| {
+            let rank = (q * total as f64).ceil() as u64;
+            latency_buckets
+                .iter()
+                .find(|bucket| total > 0 && bucket.count >= rank)
+                .and_then(|bucket| bucket.le_ms)
+        };
+        EndpointMetrics {
+            method: method.to_string(),
+            path: path.to_string(),
+            requests: self.requests,
+            errors: self.errors,
+            statuses: self.statuses.clone(),
+            p50_ms: percentile(0.5),
+            p90_ms: percentile(0.9),
+            p99_ms: percentile(0.99),
+            latency_buckets,
+        }
+    }
+}
+
+/// Path of the url without the query, with the id segments replaced.
+fn path_template(url: &str) -> String {
+    let path = match url.find("://") {
+        Some(scheme) => {
+            let rest = &url[scheme + 3..];
+            rest.find('/').map(|start| &rest[start..]).unwrap_or("/")
+        }
+        None => url,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    path.split('/')
+        .map(|segment| if is_id(segment) { ID_SEGMENT } else { segment })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_id(segment: &str) -> bool {
+    let has_digit = segment.chars().any(|c| c.is_ascii_digit());
+    !segment.is_empty()
+        && (segment.chars().all(|c| c.is_ascii_digit()) || (segment.len() >= 8 && has_digit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::{MockResponse, MockServer};
+    use crate::web::WebClient;
+
+    #[test]
+    fn ids_are_collapsed() {
+        assert_eq!(
+            path_template("http://127.0.0.1:7465/payment-api/v1/invoices/8c6d1e2a-ab12?x=1"),
+            "/payment-api/v1/invoices/{id}"
+        );
+        assert_eq!(path_template("activity/42/exec"), "activity/{id}/exec");
+        assert_eq!(path_template("http://host"), "/");
+    }
+
+    #[actix_rt::test]
+    async fn counts_requests_by_endpoint() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/invoices/0x1234567890abcdef" => MockResponse::new(404),
+            _ => MockResponse::json(serde_json::json!({})),
+        });
+        let metrics = WebClientMetrics::default();
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .metrics(metrics.clone())
+            .build();
+
+        for path in ["invoices/inv-00000001", "invoices/inv-00000002", "invoices"] {
+            client
+                .get(path)
+                .send()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+        }
+        client
+            .get("invoices/0x1234567890abcdef")
+            .send()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap_err();
+
+        let snapshot = metrics.snapshot();
+        let paths: Vec<_> = snapshot.endpoints.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/invoices", "/invoices/{id}"]);
+        let by_id = &snapshot.endpoints[1];
+        assert_eq!(by_id.method, "GET");
+        assert_eq!((by_id.requests, by_id.errors), (3, 1));
+        assert_eq!(by_id.statuses, BTreeMap::from([(200, 2), (404, 1)]));
+        let last = by_id.latency_buckets.last().unwrap();
+        assert_eq!(
+            *last,
+            LatencyBucket {
+                le_ms: None,
+                count: 3
+            }
+        );
+        assert!(by_id
+            .latency_buckets
+            .iter()
+            .any(|bucket| bucket.le_ms.is_some() && bucket.count > 0));
+        assert!(serde_json::to_value(&snapshot).unwrap()["endpoints"][1]["p50Ms"].is_u64());
+    }
+}
@@ -0,0 +1,557 @@
+//! Request middlewares, see [`WebClientBuilder::layer`].
+//!
+//! [`WebClientBuilder::layer`]: crate::web::WebClientBuilder::layer
+use awc::error::SendRequestError;
+use awc::http::header::HeaderMap;
+use awc::http::{Method, Uri};
+use awc::{ClientResponse, ConnectResponse, FrozenClientRequest, SendClientRequest};
+use bytes::Bytes;
+use futures::future::LocalBoxFuture;
+use futures::{FutureExt, TryFutureExt};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::error::fingerprint_suffix;
+use crate::web::{check_status, path_and_query, request_fingerprint, Priority};
+use crate::Error;
+
+pub type MiddlewareResult = crate::Result<ClientResponse>;
+
+/// Wraps sending of every request made through [`WebRequest`] bindings.
+///
+/// A middleware may inspect the request, add headers to it, and inspect or
+/// replace the response. It can also skip the rest of the chain by not
+/// calling [`Next::run`], or call it several times (the request is cheap to
+/// clone).
+///
+/// The chain ends with sending the request and checking the status of the
+/// response, so middlewares see error statuses as [`Error::HttpError`] or
+/// [`Error::RateLimited`]. Event streams and websockets are not passed
+/// through the middlewares.
+///
+/// # Built-in layers
+///
+/// Most of the client's own request handling is made of middlewares too,
+/// the [`BuiltinLayer`]s, installed outside the ones added with [`layer`] in
+/// the [default order](BuiltinLayer::DEFAULT_ORDER). The
+/// [`builtin_layers`] of a client can be reordered or left out, and each of
+/// them [replaced](crate::web::WebClientBuilder::replace_layer).
+///
+/// Only a few steps run outside of the chain: building the request,
+/// including serializing its body (see [`stable_body_key_order`]), the check
+/// for a [draining] client before it, and reading the response body after.
+///
+/// [`WebRequest`]: crate::web::WebRequest
+/// [`layer`]: crate::web::WebClientBuilder::layer
+/// [`builtin_layers`]: crate::web::WebClientBuilder::builtin_layers
+/// [`stable_body_key_order`]: crate::web::WebClientBuilder::stable_body_key_order
+/// [draining]: crate::web::WebClient::drain
+pub trait Middleware: 'static {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult>;
+}
+
+impl fmt::Debug for dyn Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Middleware")
+    }
+}
+
+/// The client's own middlewares, see [`Middleware`].
+///
+/// Layers with nothing to do are not installed, eg. [`Retry`](Self::Retry)
+/// without a retry policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BuiltinLayer {
+    /// Takes the [`request_fingerprint`] of the request, which the inner
+    /// layers log and the errors of the request carry.
+    Fingerprint,
+    /// Sends failed requests again as the [`retry`] policy allows, and
+    /// successful ones of an unexpected content type as the
+    /// [`ContentTypePolicy::Retry`] of [`WebRequest::json`] does.
+    ///
+    /// [`retry`]: crate::web::WebClientBuilder::retry
+    /// [`ContentTypePolicy::Retry`]: crate::web::ContentTypePolicy::Retry
+    /// [`WebRequest::json`]: crate::web::WebRequest::json
+    Retry,
+    /// Waits for a free slot of the [`max_concurrency`] limit, held until the
+    /// response arrives.
+    ///
+    /// [`max_concurrency`]: crate::web::WebClientBuilder::max_concurrency
+    ConcurrencyLimit,
+    /// Reports requests to the [`observer`], which is also told of the
+    /// failures to read the body of [`WebRequest::json`] after the chain.
+    ///
+    /// [`observer`]: crate::web::WebClientBuilder::observer
+    /// [`WebRequest::json`]: crate::web::WebRequest::json
+    Observer,
+    /// Counts requests for the [`telemetry_snapshot`].
+    ///
+    /// [`telemetry_snapshot`]: crate::web::WebClient::telemetry_snapshot
+    Telemetry,
+    /// Warns of requests slower than the [`slow_request_threshold`].
+    ///
+    /// [`slow_request_threshold`]: crate::web::WebClientBuilder::slow_request_threshold
+    SlowRequests,
+    /// Compresses JSON bodies as [`compress_requests`] sets.
+    ///
+    /// [`compress_requests`]: crate::web::WebClientBuilder::compress_requests
+    Compression,
+}
+
+impl BuiltinLayer {
+    /// Order the built-in layers are installed in by default, outermost first.
+    ///
+    /// The fingerprint is taken once for all the attempts of a request, and
+    /// from its body before compression. Each attempt then waits for its
+    /// slot, and is observed, counted and timed without that wait.
+    pub const DEFAULT_ORDER: [BuiltinLayer; 7] = [
+        BuiltinLayer::Fingerprint,
+        BuiltinLayer::Retry,
+        BuiltinLayer::ConcurrencyLimit,
+        BuiltinLayer::Observer,
+        BuiltinLayer::Telemetry,
+        BuiltinLayer::SlowRequests,
+        BuiltinLayer::Compression,
+    ];
+}
+
+/// What is known of a request besides its contents, shared by all its
+/// attempts and the [`WebRequest`](crate::web::WebRequest) sending it.
+#[derive(Debug, Default)]
+pub(crate) struct RequestInfo {
+    pub priority: Cell<Priority>,
+    /// Set also after sending, eg. by `json_events`, before the request is
+    /// polled.
+    pub long_poll: Cell<bool>,
+    pub reads_json: Cell<bool>,
+    pub fingerprint: RefCell<Option<String>>,
+}
+
+/// Request on its way through the middleware chain.
+#[derive(Clone)]
+pub struct MiddlewareRequest {
+    frozen: FrozenClientRequest,
+    body: Option<Bytes>,
+    info: Rc<RequestInfo>,
+    attempt: u32,
+    /// Headers added on top of the ones the request was built with.
+    pub extra_headers: HeaderMap,
+}
+
+impl MiddlewareRequest {
+    pub(crate) fn new(
+        frozen: FrozenClientRequest,
+        body: Option<Bytes>,
+        info: Rc<RequestInfo>,
+    ) -> Self {
+        MiddlewareRequest {
+            frozen,
+            body,
+            info,
+            attempt: 1,
+            extra_headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        self.frozen.get_method()
+    }
+
+    pub fn uri(&self) -> &Uri {
+        self.frozen.get_uri()
+    }
+
+    /// Headers the request was built with, without the `extra_headers`.
+    pub fn headers(&self) -> &HeaderMap {
+        self.frozen.headers()
+    }
+
+    pub fn body(&self) -> Option<&Bytes> {
+        self.body.as_ref()
+    }
+
+    /// Replaces the body, eg. with its compressed form.
+    pub fn set_body(&mut self, body: impl Into<Bytes>) {
+        self.body = Some(body.into());
+    }
+
+    /// See [`WebRequest::priority`](crate::web::WebRequest::priority).
+    pub fn priority(&self) -> Priority {
+        self.info.priority.get()
+    }
+
+    /// See [`WebRequest::long_poll`](crate::web::WebRequest::long_poll).
+    pub fn is_long_poll(&self) -> bool {
+        self.info.long_poll.get()
+    }
+
+    /// Whether the response is read by [`WebRequest::json`], which expects
+    /// a JSON body.
+    ///
+    /// [`WebRequest::json`]: crate::web::WebRequest::json
+    pub fn reads_json(&self) -> bool {
+        self.info.reads_json.get()
+    }
+
+    /// Fingerprint set by [`BuiltinLayer::Fingerprint`], if installed.
+    pub fn fingerprint(&self) -> Option<String> {
+        self.info.fingerprint.borrow().clone()
+    }
+
+    /// Sets the fingerprint of the request, for all its attempts.
+    pub fn set_fingerprint(&mut self, fingerprint: String) {
+        *self.info.fingerprint.borrow_mut() = Some(fingerprint);
+    }
+
+    /// Number of the attempt, counted from 1, which a retrying layer sets on
+    /// the requests it sends again.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn set_attempt(&mut self, attempt: u32) {
+        self.attempt = attempt;
+    }
+
+    async fn send(self, max_response_size: usize) -> MiddlewareResult {
+        let (method, url) = (self.method().clone(), self.uri().to_string());
+        let fingerprint = self.fingerprint();
+        log::debug!(
+            "sending {} {}{}",
+            method,
+            url,
+            fingerprint_suffix(&fingerprint)
+        );
+        let builder = self.frozen.extra_headers(self.extra_headers);
+        let sent = match self.body {
+            Some(body) => builder.send_body(body).await,
+            None => builder.send().await,
+        };
+        let result = match sent {
+            Ok(response) => check_status(response, &method, &url, max_response_size).await,
+            Err(e) => Err(Error::from_request(e, method, url)),
+        };
+        match fingerprint {
+            Some(fingerprint) => result.map_err(|e| e.with_fingerprint(&fingerprint)),
+            None => result,
+        }
+    }
+}
+
+/// The rest of the middleware chain, ending with the actual send.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    layers: &'a [Rc<dyn Middleware>],
+    max_response_size: usize,
+}
+
+impl<'a> Next<'a> {
+    pub fn run(self, request: MiddlewareRequest) -> LocalBoxFuture<'a, MiddlewareResult> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.handle(request, Next { layers, ..self }),
+            None => request.send(self.max_response_size).boxed_local(),
+        }
+    }
+}
+
+/// Ordered middleware stack, the first one being the outermost.
+pub(crate) struct Layers {
+    layers: Vec<Rc<dyn Middleware>>,
+    /// Limit of the error response bodies read by the status check.
+    max_response_size: usize,
+}
+
+impl Layers {
+    pub fn new(layers: Vec<Rc<dyn Middleware>>, max_response_size: usize) -> Self {
+        Layers {
+            layers,
+            max_response_size,
+        }
+    }
+
+    /// Sends the request through all the layers, an error of which is passed
+    /// on wrapped in a [`SendRequestError::Custom`], see [`chain_error`].
+    pub fn dispatch(self: &Rc<Self>, request: MiddlewareRequest) -> SendClientRequest {
+        let layers = self.clone();
+        let response = async move {
+            let next = Next {
+                layers: &layers.layers,
+                max_response_size: layers.max_response_size,
+            };
+            next.run(request).await
+        };
+        SendClientRequest::Fut(
+            Box::pin(response.map_ok(ConnectResponse::Client).map_err(|e| {
+                SendRequestError::Custom(Box::new(ChainError(e)), Box::new("layers"))
+            })),
+            None,
+            false,
+        )
+    }
+}
+
+impl fmt::Debug for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Layers({})", self.layers.len())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+struct ChainError(Error);
+
+/// The error a request failed with in the middleware chain, or `err` itself
+/// if it failed before.
+pub(crate) fn chain_error(err: SendRequestError) -> std::result::Result<Error, SendRequestError> {
+    match err {
+        SendRequestError::Custom(err, what) => match err.downcast::<ChainError>() {
+            Ok(err) => Ok(err.0),
+            Err(err) => Err(SendRequestError::Custom(err, what)),
+        },
+        err => Err(err),
+    }
+}
+
+/// [`BuiltinLayer::Fingerprint`].
+pub(crate) struct Fingerprint;
+
+impl Middleware for Fingerprint {
+    fn handle<'a>(
+        &'a self,
+        mut request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        let uri = request.uri().to_string();
+        let body = request.body().map_or(&[][..], |body| &body[..]);
+        let fingerprint = request_fingerprint(request.method(), path_and_query(&uri), body);
+        request.set_fingerprint(fingerprint);
+        next.run(request)
+    }
+}
+
+/// [`BuiltinLayer::SlowRequests`].
+pub(crate) struct SlowRequests(pub Duration);
+
+impl Middleware for SlowRequests {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            let (method, uri) = (request.method().clone(), request.uri().clone());
+            let info = request.info.clone();
+            let started = Instant::now();
+            let result = next.run(request).await;
+            let elapsed = started.elapsed();
+            if elapsed > self.0 && !info.long_poll.get() {
+                log::warn!(
+                    "slow request: {} {}{} took {:?}",
+                    method,
+                    uri,
+                    fingerprint_suffix(&info.fingerprint.borrow()),
+                    elapsed
+                );
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+/// Logs every request with its response status and duration at debug level.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            let (method, uri) = (request.method().clone(), request.uri().clone());
+            let started = Instant::now();
+            let result = next.run(request).await;
+            match &result {
+                Ok(response) => log::debug!(
+                    "{} {} -> {} in {:?}",
+                    method,
+                    uri,
+                    response.status(),
+                    started.elapsed()
+                ),
+                Err(e) => match e.status() {
+                    Some(status) => log::debug!(
+                        "{} {} -> {} in {:?}: {}",
+                        method,
+                        uri,
+                        status,
+                        started.elapsed(),
+                        e
+                    ),
+                    None => log::debug!(
+                        "{} {} failed in {:?}: {}",
+                        method,
+                        uri,
+                        started.elapsed(),
+                        e
+                    ),
+                },
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::{LogCapture, MockResponse, MockServer};
+    use crate::web::{RetryPolicy, WebClient, WebClientBuilder};
+    use awc::http::header::{HeaderName, HeaderValue};
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    struct Tag {
+        name: &'static str,
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Middleware for Tag {
+        fn handle<'a>(
+            &'a self,
+            mut request: MiddlewareRequest,
+            next: Next<'a>,
+        ) -> LocalBoxFuture<'a, MiddlewareResult> {
+            async move {
+                self.calls.borrow_mut().push(format!("{} >", self.name));
+                request.extra_headers.append(
+                    HeaderName::from_static("x-layer"),
+                    HeaderValue::from_static(self.name),
+                );
+                let result = next.run(request).await;
+                self.calls.borrow_mut().push(format!("{} <", self.name));
+                result
+            }
+            .boxed_local()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn layers_run_in_order() {
+        let server = MockServer::start(|req| MockResponse::json(req.json()));
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .layer(Tag {
+                name: "outer",
+                calls: calls.clone(),
+            })
+            .layer(Tag {
+                name: "inner",
+                calls: calls.clone(),
+            })
+            .build();
+
+        let echo: serde_json::Value = client
+            .post("echo")
+            .send_json(&json!({"a": 1}))
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(echo, json!({"a": 1}));
+        assert_eq!(
+            *calls.borrow(),
+            vec!["outer >", "inner >", "inner <", "outer <"]
+        );
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        let layers: Vec<_> = request
+            .headers
+            .iter()
+            .filter(|(name, _)| name == "x-layer")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        assert_eq!(layers, vec!["outer", "inner"]);
+    }
+
+    #[actix_rt::test]
+    async fn request_logger_logs_status() {
+        let server = MockServer::start(|_| MockResponse::new(404));
+        let client = WebClient::builder()
+            .api_url(server.url())
+            .layer(RequestLogger)
+            .build();
+
+        let capture = LogCapture::start();
+        let result: crate::Result<()> = client.get("missing").send().json().await;
+        assert!(result.is_err());
+        assert!(capture
+            .records()
+            .iter()
+            .any(|(_, msg)| msg.contains("GET") && msg.contains("-> 404")));
+    }
+
+    #[actix_rt::test]
+    async fn builtin_layers_can_be_left_out_reordered_and_replaced() {
+        let server = MockServer::start(|_| MockResponse::new(503));
+        let retrying = || {
+            WebClient::builder()
+                .api_url(server.url())
+                .retry(RetryPolicy::new(3).base_delay(Duration::from_millis(1)))
+        };
+        let counted = |builder: WebClientBuilder| async move {
+            let client = builder.build();
+            let err = client.get("status").send().json::<()>().await.unwrap_err();
+            (client.telemetry_snapshot().requests["GET"], err)
+        };
+
+        // every attempt is counted by default, the request once outside of
+        // the retries
+        let (count, err) = counted(retrying()).await;
+        assert_eq!(count, 3);
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                fingerprint: Some(_),
+                ..
+            }
+        ));
+        let reordered = [BuiltinLayer::Telemetry, BuiltinLayer::Retry];
+        let (count, err) = counted(retrying().builtin_layers(reordered)).await;
+        assert_eq!(count, 1);
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                fingerprint: None,
+                ..
+            }
+        ));
+        assert_eq!(server.requests().len(), 6);
+
+        let (count, _) = counted(retrying().builtin_layers([BuiltinLayer::Telemetry])).await;
+        assert_eq!(count, 1);
+        assert_eq!(server.requests().len(), 7);
+
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let tag = Tag {
+            name: "retry",
+            calls: calls.clone(),
+        };
+        let (count, _) = counted(retrying().replace_layer(BuiltinLayer::Retry, tag)).await;
+        assert_eq!(count, 1);
+        assert_eq!(*calls.borrow(), vec!["retry >", "retry <"]);
+        assert_eq!(server.requests().len(), 8);
+    }
+}
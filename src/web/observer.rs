@@ -0,0 +1,98 @@
+//! Callbacks on the requests of a client, see [`WebClientBuilder::observer`].
+//!
+//! [`WebClientBuilder::observer`]: crate::web::WebClientBuilder::observer
+use awc::http::{Method, StatusCode};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::middleware::{Middleware, MiddlewareRequest, MiddlewareResult, Next};
+use crate::Error;
+
+/// Notified of every request sent through [`WebRequest`] bindings, eg. to
+/// collect metrics, by the [`Observer`] layer. All the callbacks do nothing
+/// by default.
+///
+/// Each attempt of a retried request is reported separately. A response
+/// with an error status is reported both to `on_response` and `on_error`.
+///
+/// [`WebRequest`]: crate::web::WebRequest
+/// [`Observer`]: crate::web::BuiltinLayer::Observer
+pub trait RequestObserver: 'static {
+    fn on_request(&self, _method: &Method, _url: &str) {}
+
+    /// The response head has arrived `elapsed` after sending the request.
+    fn on_response(&self, _method: &Method, _url: &str, _status: StatusCode, _elapsed: Duration) {}
+
+    /// The request failed, either to get a successful response or, for
+    /// [`WebRequest::json`], to read and parse its body.
+    ///
+    /// [`WebRequest::json`]: crate::web::WebRequest::json
+    fn on_error(&self, _method: &Method, _url: &str, _err: &Error) {}
+}
+
+/// Observer ignoring all the requests, installed by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl RequestObserver for NoopObserver {}
+
+#[derive(Clone)]
+pub(crate) struct Observer(Arc<dyn RequestObserver>);
+
+impl Observer {
+    pub fn new(observer: Arc<dyn RequestObserver>) -> Self {
+        Observer(observer)
+    }
+}
+
+impl Default for Observer {
+    fn default() -> Self {
+        Observer(Arc::new(NoopObserver))
+    }
+}
+
+impl Deref for Observer {
+    type Target = dyn RequestObserver;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// [`BuiltinLayer::Observer`](super::BuiltinLayer::Observer).
+impl Middleware for Observer {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            let (method, url) = (request.method().clone(), request.uri().to_string());
+            self.on_request(&method, &url);
+            let started = Instant::now();
+            let result = next.run(request).await;
+            let elapsed = started.elapsed();
+            match &result {
+                Ok(response) => self.on_response(&method, &url, response.status(), elapsed),
+                Err(err) => {
+                    if let Some(status) = err.status() {
+                        self.on_response(&method, &url, status, elapsed);
+                    }
+                    self.on_error(&method, &url, err);
+                }
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
@@ -0,0 +1,212 @@
+//! Walking paged list endpoints, see [`WebClient::paginate`].
+use awc::http::header::HeaderMap;
+use futures::{stream, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use std::rc::Rc;
+
+use crate::web::{QueryParamsBuilder, WebClient};
+use crate::Result;
+
+type CursorFn<T> = Rc<dyn Fn(&T) -> Option<String>>;
+
+/// Where the cursor for the next page comes from.
+pub enum PageCursor<T> {
+    /// Taken from the last element of a page, eg. its timestamp.
+    LastElement(CursorFn<T>),
+    /// Taken from a response header of a page.
+    Header(String),
+}
+
+impl<T> Clone for PageCursor<T> {
+    fn clone(&self) -> Self {
+        match self {
+            PageCursor::LastElement(cursor) => PageCursor::LastElement(cursor.clone()),
+            PageCursor::Header(name) => PageCursor::Header(name.clone()),
+        }
+    }
+}
+
+impl<T> PageCursor<T> {
+    pub fn last_element(cursor: impl Fn(&T) -> Option<String> + 'static) -> Self {
+        PageCursor::LastElement(Rc::new(cursor))
+    }
+
+    fn next(&self, page: &[T], headers: &HeaderMap) -> Option<String> {
+        match self {
+            PageCursor::LastElement(cursor) => page.last().and_then(|last| cursor(last)),
+            PageCursor::Header(name) => headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// How the pages of a list endpoint are requested.
+///
+/// By default each page asks for `maxEvents` elements after the cursor given
+/// as `afterTimestamp`, the convention of the market and payment event
+/// endpoints.
+pub struct Paginated<T> {
+    page_size: u32,
+    size_param: String,
+    cursor_param: String,
+    cursor: PageCursor<T>,
+}
+
+impl<T> Clone for Paginated<T> {
+    fn clone(&self) -> Self {
+        Paginated {
+            page_size: self.page_size,
+            size_param: self.size_param.clone(),
+            cursor_param: self.cursor_param.clone(),
+            cursor: self.cursor.clone(),
+        }
+    }
+}
+
+impl<T> Paginated<T> {
+    pub fn new(page_size: u32, cursor: PageCursor<T>) -> Self {
+        Paginated {
+            page_size,
+            size_param: "maxEvents".into(),
+            cursor_param: "afterTimestamp".into(),
+            cursor,
+        }
+    }
+
+    pub fn size_param(mut self, name: &str) -> Self {
+        self.size_param = name.to_string();
+        self
+    }
+
+    pub fn cursor_param(mut self, name: &str) -> Self {
+        self.cursor_param = name.to_string();
+        self
+    }
+
+    fn page_url(&self, url: String, cursor: Option<&str>) -> String {
+        let query = QueryParamsBuilder::default()
+            .put(&self.size_param, Some(self.page_size))
+            .put(&self.cursor_param, cursor)
+            .build();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}{}", url, separator, query)
+    }
+}
+
+struct Walk<T, F> {
+    client: WebClient,
+    url: F,
+    paging: Paginated<T>,
+    cursor: Option<String>,
+    done: bool,
+}
+
+pub(crate) fn pages<T, F>(
+    client: WebClient,
+    url: F,
+    paging: Paginated<T>,
+) -> impl Stream<Item = Result<T>>
+where
+    T: DeserializeOwned + 'static,
+    F: Fn() -> String + 'static,
+{
+    let walk = Walk {
+        client,
+        url,
+        paging,
+        cursor: None,
+        done: false,
+    };
+    stream::try_unfold(walk, next_page)
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+async fn next_page<T, F>(mut walk: Walk<T, F>) -> Result<Option<(Vec<T>, Walk<T, F>)>>
+where
+    T: DeserializeOwned,
+    F: Fn() -> String,
+{
+    if walk.done {
+        return Ok(None);
+    }
+    let url = walk.paging.page_url((walk.url)(), walk.cursor.as_deref());
+    let (page, headers): (Vec<T>, _) = walk.client.get(&url).send().json_with_headers().await?;
+    if page.is_empty() {
+        return Ok(None);
+    }
+    walk.cursor = walk.paging.cursor.next(&page, &headers);
+    walk.done = page.len() < walk.paging.page_size as usize || walk.cursor.is_none();
+    Ok(Some((page, walk)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::mock::{MockResponse, MockServer};
+    use serde_json::json;
+
+    #[derive(serde::Deserialize)]
+    struct Entry {
+        id: u32,
+        timestamp: String,
+    }
+
+    #[actix_rt::test]
+    async fn walks_pages_until_an_empty_one() {
+        let server = MockServer::start(|req| {
+            let page = match req.path.split("afterTimestamp=").nth(1) {
+                None => json!([{"id": 1, "timestamp": "t1"}, {"id": 2, "timestamp": "t2"}]),
+                Some("t2") => json!([{"id": 3, "timestamp": "t3"}, {"id": 4, "timestamp": "t4"}]),
+                Some("t4") => json!([{"id": 5, "timestamp": "t5"}, {"id": 6, "timestamp": "t6"}]),
+                Some(_) => json!([]),
+            };
+            MockResponse::json(page)
+        });
+        let paging = Paginated::new(
+            2,
+            PageCursor::last_element(|e: &Entry| Some(e.timestamp.clone())),
+        );
+
+        let entries: Vec<Entry> = server
+            .client()
+            .paginate(|| "invoiceEvents?appSessionId=s".to_string(), paging)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6]);
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/invoiceEvents?appSessionId=s&maxEvents=2",
+                "/invoiceEvents?appSessionId=s&maxEvents=2&afterTimestamp=t2",
+                "/invoiceEvents?appSessionId=s&maxEvents=2&afterTimestamp=t4",
+                "/invoiceEvents?appSessionId=s&maxEvents=2&afterTimestamp=t6",
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stops_on_a_short_page_with_header_cursor() {
+        let server = MockServer::start(|req| match req.path.split("after=").nth(1) {
+            None => MockResponse::json(json!([1, 2])).header("x-next", "a"),
+            _ => MockResponse::json(json!([3])).header("x-next", "b"),
+        });
+        let paging = Paginated::new(2, PageCursor::Header("x-next".into())).cursor_param("after");
+
+        let values: Vec<u32> = server
+            .client()
+            .paginate::<u32, _>(|| "offers".to_string(), paging)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(values, [1, 2, 3]);
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -0,0 +1,323 @@
+//! Retrying of failed requests, see [`WebClientBuilder::retry`].
+//!
+//! [`WebClientBuilder::retry`]: crate::web::WebClientBuilder::retry
+use awc::http::{Method, StatusCode};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use super::middleware::{Middleware, MiddlewareRequest, MiddlewareResult, Next};
+use crate::error::fingerprint_suffix;
+use crate::web::config::duration_secs;
+use crate::web::non_json_content_type;
+use crate::Error;
+
+/// When and how often the [`Retry`] layer sends a failed request again.
+///
+/// Each retry waits `base_delay * multiplier^(retry - 1)`, capped at
+/// `max_delay`, randomly shortened by up to a half so that many clients
+/// restarted together do not retry in lockstep.
+///
+/// Only `GET`, `HEAD`, `OPTIONS`, `PUT` and `DELETE` requests are retried,
+/// unless [`retry_post`](Self::retry_post) is set.
+///
+/// When (de)serialized, eg. as part of a [`ClientConfig`], the delays are
+/// given in seconds and the statuses as numbers; missing fields keep their
+/// defaults.
+///
+/// [`Retry`]: crate::web::BuiltinLayer::Retry
+/// [`ClientConfig`]: crate::web::ClientConfig
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    #[serde(with = "duration_secs")]
+    pub base_delay: Duration,
+    pub multiplier: f64,
+    #[serde(with = "duration_secs")]
+    pub max_delay: Duration,
+    /// Response statuses worth another attempt.
+    #[serde(with = "status_codes")]
+    pub retry_statuses: Vec<StatusCode>,
+    /// Whether to retry when the request cannot be sent at all, eg. on
+    /// a refused connection.
+    pub retry_send_errors: bool,
+    pub retry_timeouts: bool,
+    /// Whether to retry `POST` (and `PATCH`) requests, which may have taken
+    /// effect despite failing.
+    pub retry_post: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(10),
+            retry_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_send_errors: true,
+            retry_timeouts: true,
+            retry_post: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn retry_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.retry_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    pub fn retry_post(mut self, retry_post: bool) -> Self {
+        self.retry_post = retry_post;
+        self
+    }
+
+    /// Delay before sending the request again after `attempts` have failed
+    /// with `err`, `None` if it should not be retried.
+    ///
+    /// A `Retry-After` delay of a [`Error::RateLimited`] response is waited
+    /// out as it is, unless it exceeds `max_delay`, which gives up instead.
+    pub(crate) fn delay(&self, method: &Method, err: &Error, attempts: u32) -> Option<Duration> {
+        if attempts >= self.max_attempts || !self.is_retryable(method, err) {
+            return None;
+        }
+        if let Error::RateLimited {
+            retry_after: Some(retry_after),
+            ..
+        } = err
+        {
+            return Some(*retry_after).filter(|delay| *delay <= self.max_delay);
+        }
+        let backoff = self.base_delay.as_secs_f64() * self.multiplier.powi(attempts as i32 - 1);
+        let backoff = Duration::try_from_secs_f64(backoff)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        Some(backoff.mul_f64(1.0 - jitter() / 2.0))
+    }
+
+    fn is_retryable(&self, method: &Method, err: &Error) -> bool {
+        if !is_idempotent(method) && !self.retry_post {
+            return false;
+        }
+        match err {
+            Error::SendRequestError { .. } => self.retry_send_errors,
+            Error::TimeoutError { .. } => self.retry_timeouts,
+            Error::HttpError { code, .. } | Error::RateLimited { code, .. } => {
+                self.retry_statuses.contains(code)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// [`BuiltinLayer::Retry`](super::BuiltinLayer::Retry).
+pub(crate) struct Retry {
+    pub policy: Option<RetryPolicy>,
+    /// Attempts and delay of [`ContentTypePolicy::Retry`].
+    ///
+    /// [`ContentTypePolicy::Retry`]: crate::web::ContentTypePolicy::Retry
+    pub unexpected_content_type: Option<(u32, Duration)>,
+}
+
+impl Middleware for Retry {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            let method = request.method().clone();
+            let (mut attempt, mut content_type_retries) = (request.attempt(), 0);
+            loop {
+                let mut sent = request.clone();
+                sent.set_attempt(attempt);
+                let result = next.run(sent).await;
+                let (delay, failure) = match &result {
+                    Ok(response) => match (
+                        self.unexpected_content_type,
+                        non_json_content_type(response),
+                    ) {
+                        (Some((attempts, delay)), Some(content_type))
+                            if request.reads_json()
+                                && content_type_retries < attempts
+                                && is_idempotent(&method) =>
+                        {
+                            content_type_retries += 1;
+                            (delay, format!("unexpected content type '{}'", content_type))
+                        }
+                        _ => return result,
+                    },
+                    Err(Error::TimeoutError { .. }) if request.is_long_poll() => return result,
+                    Err(err) => match self
+                        .policy
+                        .as_ref()
+                        .and_then(|p| p.delay(&method, err, attempt))
+                    {
+                        Some(delay) => (delay, err.to_string()),
+                        None => return result,
+                    },
+                };
+                log::debug!(
+                    "attempt {} of {} {}{} failed: {}, retrying in {:?}",
+                    attempt,
+                    method,
+                    request.uri(),
+                    fingerprint_suffix(&request.fingerprint()),
+                    failure,
+                    delay
+                );
+                attempt += 1;
+                actix_rt::time::sleep(delay).await;
+            }
+        }
+        .boxed_local()
+    }
+}
+
+/// Whether sending the request again cannot repeat its effect.
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+mod status_codes {
+    use awc::http::StatusCode;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        statuses: &[StatusCode],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(statuses.iter().map(StatusCode::as_u16))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<StatusCode>, D::Error> {
+        Vec::<u16>::deserialize(deserializer)?
+            .into_iter()
+            .map(|code| StatusCode::from_u16(code).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Random number in `[0, 1)`.
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http_error(code: StatusCode) -> Error {
+        Error::HttpError {
+            code,
+            msg: String::new(),
+            method: Method::GET,
+            url: String::new(),
+            fingerprint: None,
+            body: None,
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let policy = RetryPolicy::new(5)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300));
+        let err = http_error(StatusCode::SERVICE_UNAVAILABLE);
+
+        for (attempts, full) in [(1, 100), (2, 200), (3, 300), (4, 300)] {
+            let delay = policy.delay(&Method::GET, &err, attempts).unwrap();
+            let full = Duration::from_millis(full);
+            assert!(delay <= full && delay >= full / 2, "{:?}", delay);
+        }
+        assert_eq!(policy.delay(&Method::GET, &err, 5), None);
+    }
+
+    #[test]
+    fn rate_limited_waits_for_retry_after() {
+        let policy = RetryPolicy::new(3).max_delay(Duration::from_secs(10));
+        let limited = |retry_after| Error::RateLimited {
+            code: StatusCode::TOO_MANY_REQUESTS,
+            msg: String::new(),
+            method: Method::GET,
+            url: String::new(),
+            fingerprint: None,
+            retry_after,
+            body: None,
+        };
+
+        assert_eq!(
+            policy.delay(&Method::GET, &limited(Some(Duration::from_secs(5))), 1),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            policy.delay(&Method::GET, &limited(Some(Duration::from_secs(60))), 1),
+            None
+        );
+        assert!(policy.delay(&Method::GET, &limited(None), 1).is_some());
+        assert!(policy.delay(&Method::POST, &limited(None), 1).is_none());
+
+        let policy = policy.retry_statuses([StatusCode::SERVICE_UNAVAILABLE]);
+        let limited = limited(Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(&Method::GET, &limited, 1), None);
+    }
+
+    #[test]
+    fn only_retryable_failures_are_retried() {
+        let policy = RetryPolicy::default();
+        let unavailable = http_error(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert!(policy.delay(&Method::DELETE, &unavailable, 1).is_some());
+        assert!(policy
+            .delay(&Method::GET, &http_error(StatusCode::BAD_REQUEST), 1)
+            .is_none());
+        assert!(policy
+            .delay(&Method::GET, &http_error(StatusCode::UNAUTHORIZED), 1)
+            .is_none());
+        assert!(policy.delay(&Method::POST, &unavailable, 1).is_none());
+        assert!(policy
+            .retry_post(true)
+            .delay(&Method::POST, &unavailable, 1)
+            .is_some());
+    }
+}
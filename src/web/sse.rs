@@ -0,0 +1,79 @@
+//! Decoding of `text/event-stream` bodies, see
+//! [`WebRequest::sse_stream`](crate::web::WebRequest::sse_stream).
+use bytes::BytesMut;
+use std::str::Utf8Error;
+
+/// Collects body chunks and assembles the `data` of the events in them.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buf: BytesMut,
+    /// `data` lines of the event being read, joined with newlines.
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Length of the buffered part of the body not forming a whole line yet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Data of the next complete event, `None` if more of the body is needed.
+    ///
+    /// Comments and fields other than `data` are skipped, as are events
+    /// without any data, eg. keep-alives.
+    pub fn next_data(&mut self) -> Result<Option<String>, Utf8Error> {
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let raw = self.buf.split_to(end + 1);
+            let line = std::str::from_utf8(&raw[..end])?;
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() {
+                match self.data.take() {
+                    Some(data) => return Ok(Some(data)),
+                    None => continue,
+                }
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            if field != "data" {
+                continue;
+            }
+            match self.data.as_mut() {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_events_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for chunk in [
+            ": keep-alive\n\nevent: update\nda",
+            "ta: {\"a\":\r\ndata:1}\nid: 7\n\n",
+            "data: second\n\ndata: unfinished",
+        ] {
+            decoder.push(chunk.as_bytes());
+            while let Some(data) = decoder.next_data().unwrap() {
+                events.push(data);
+            }
+        }
+        assert_eq!(events, ["{\"a\":\n1}", "second"]);
+        assert_eq!(decoder.buffered(), "data: unfinished".len());
+    }
+}
@@ -0,0 +1,147 @@
+//! Request counters kept by every [`WebClient`], see [`WebClient::telemetry_snapshot`].
+//!
+//! [`WebClient`]: crate::web::WebClient
+//! [`WebClient::telemetry_snapshot`]: crate::web::WebClient::telemetry_snapshot
+use awc::http::{Method, StatusCode};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::middleware::{Middleware, MiddlewareRequest, MiddlewareResult, Next};
+use crate::Error;
+
+const METHODS: [Method; 7] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::PATCH,
+    Method::HEAD,
+    Method::OPTIONS,
+];
+const OTHER_METHOD: &str = "OTHER";
+
+/// Point-in-time copy of the client counters.
+///
+/// Event streams and websockets are not counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Telemetry {
+    /// Requests sent, by method. Methods never used are left out.
+    pub requests: BTreeMap<String, u64>,
+    pub responses: StatusClasses,
+    /// Requests sent again by the client itself, eg. on an unexpected
+    /// content type.
+    pub retries: u64,
+    pub timeouts: u64,
+    /// Requests sent and still waiting for the response head.
+    pub in_flight: u64,
+}
+
+/// Response counts by status class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusClasses {
+    pub informational: u64,
+    pub success: u64,
+    pub redirection: u64,
+    pub client_error: u64,
+    pub server_error: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    requests: [AtomicU64; METHODS.len() + 1],
+    responses: [AtomicU64; 5],
+    retries: AtomicU64,
+    timeouts: AtomicU64,
+    in_flight: AtomicU64,
+}
+
+impl Counters {
+    /// Counts a request being sent, until the returned guard is dropped.
+    pub fn start(&self, method: &Method) -> InFlight<'_> {
+        let idx = METHODS
+            .iter()
+            .position(|m| m == method)
+            .unwrap_or(METHODS.len());
+        self.requests[idx].fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self)
+    }
+
+    pub fn response(&self, status: StatusCode) {
+        let class = (status.as_u16() / 100).clamp(1, 5) as usize;
+        self.responses[class - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn error(&self, err: &Error) {
+        if let Error::TimeoutError { .. } = err {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Telemetry {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let names = METHODS.iter().map(Method::as_str).chain([OTHER_METHOD]);
+        let requests = names
+            .zip(&self.requests)
+            .map(|(name, count)| (name.to_string(), load(count)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        Telemetry {
+            requests,
+            responses: StatusClasses {
+                informational: load(&self.responses[0]),
+                success: load(&self.responses[1]),
+                redirection: load(&self.responses[2]),
+                client_error: load(&self.responses[3]),
+                server_error: load(&self.responses[4]),
+            },
+            retries: load(&self.retries),
+            timeouts: load(&self.timeouts),
+            in_flight: load(&self.in_flight),
+        }
+    }
+}
+
+/// [`BuiltinLayer::Telemetry`](super::BuiltinLayer::Telemetry), counting the
+/// attempts after the first as retries.
+impl Middleware for Counters {
+    fn handle<'a>(
+        &'a self,
+        request: MiddlewareRequest,
+        next: Next<'a>,
+    ) -> LocalBoxFuture<'a, MiddlewareResult> {
+        async move {
+            if request.attempt() > 1 {
+                self.retry();
+            }
+            let in_flight = self.start(request.method());
+            let result = next.run(request).await;
+            drop(in_flight);
+            match &result {
+                Ok(response) => self.response(response.status()),
+                Err(err) => {
+                    if let Some(status) = err.status() {
+                        self.response(status);
+                    }
+                    self.error(err);
+                }
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+pub(crate) struct InFlight<'a>(&'a Counters);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}